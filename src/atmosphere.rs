@@ -0,0 +1,439 @@
+use std::sync::OnceLock;
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{
+    camera::Focused,
+    conic::Conics,
+    simulation::{self, PhysicalProperties, SimulationClock, GRAVITATIONAL_CONSTANT},
+    ui::RenderInUI,
+};
+
+/// Altitude in meters below which a body with drag is considered to have re-entered.
+pub const REENTRY_ALTITUDE: f64 = 100_000.;
+
+/// Longest orbital lifetime estimated before a body is considered not to decay, in seconds.
+const MAX_DECAY_TIME: f64 = 100. * 365.25 * 86_400.;
+
+/// Largest drop in semi-major axis per step of the lifetime estimate, in meters.
+const DECAY_STEP: f64 = 1_000.;
+
+/// Atmosphere of a body, giving the density drag is computed from.
+#[derive(Component, Clone, Debug)]
+pub struct Atmosphere {
+    pub model: AtmosphereModel,
+    /// Altitude in meters above which the density is taken to be zero.
+    pub ceiling: f64,
+}
+
+/// How an atmosphere's density changes with altitude.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AtmosphereModel {
+    /// Density decaying from a single base altitude with one scale height.
+    Exponential(AtmosphereLayer),
+    /// Piecewise exponential table, where each layer decays from its base density with its own
+    /// scale height. Layers are sorted by base altitude, the first starting at the surface.
+    Layered(Vec<AtmosphereLayer>),
+    /// Jacchia style thermosphere above 120 km, heated by the sun and by geomagnetic storms, on
+    /// top of the US Standard Atmosphere 1976 below.
+    Jacchia {
+        /// 10.7 cm solar radio flux in solar flux units.
+        solar_flux: f64,
+        /// Daily planetary geomagnetic index Ap.
+        geomagnetic_index: f64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtmosphereLayer {
+    /// Altitude in meters where the layer starts.
+    pub base_altitude: f64,
+    /// Density at the base of the layer in kg/m³.
+    pub base_density: f64,
+    /// Altitude in meters over which the density falls by a factor of e.
+    pub scale_height: f64,
+}
+
+impl AtmosphereLayer {
+    /// Density in kg/m³ at an altitude in meters.
+    pub fn density(&self, altitude: f64) -> f64 {
+        self.base_density * (-(altitude - self.base_altitude) / self.scale_height).exp()
+    }
+}
+
+/// Base altitude in km, density in kg/m³ and scale height in km of the US Standard Atmosphere
+/// 1976, as tabulated by Vallado.
+const US_STANDARD_1976: [(f64, f64, f64); 28] = [
+    (0., 1.225, 7.249),
+    (25., 3.899e-2, 6.349),
+    (30., 1.774e-2, 6.682),
+    (40., 3.972e-3, 7.554),
+    (50., 1.057e-3, 8.382),
+    (60., 3.206e-4, 7.714),
+    (70., 8.770e-5, 6.549),
+    (80., 1.905e-5, 5.799),
+    (90., 3.396e-6, 5.382),
+    (100., 5.297e-7, 5.877),
+    (110., 9.661e-8, 7.263),
+    (120., 2.438e-8, 9.473),
+    (130., 8.484e-9, 12.636),
+    (140., 3.845e-9, 16.149),
+    (150., 2.070e-9, 22.523),
+    (180., 5.464e-10, 29.740),
+    (200., 2.789e-10, 37.105),
+    (250., 7.248e-11, 45.546),
+    (300., 2.418e-11, 53.628),
+    (350., 9.518e-12, 53.298),
+    (400., 3.725e-12, 58.515),
+    (450., 1.585e-12, 60.828),
+    (500., 6.967e-13, 63.822),
+    (600., 1.454e-13, 71.835),
+    (700., 3.614e-14, 88.667),
+    (800., 1.170e-14, 124.64),
+    (900., 5.245e-15, 181.05),
+    (1000., 3.019e-15, 268.00),
+];
+
+/// Molecular mass in amu, number density at 120 km in 1/m³ and thermal diffusion factor of the
+/// gases making up the thermosphere, with the 120 km values from the US Standard Atmosphere 1976.
+const THERMOSPHERE_GASES: [(f64, f64, f64); 5] = [
+    // N2, O, O2, Ar, He
+    (28.0134, 3.73e17, 0.),
+    (15.9994, 9.3e16, 0.),
+    (31.9988, 4.0e16, 0.),
+    (39.948, 1.1e15, 0.),
+    (4.0026, 3.4e13, -0.38),
+];
+
+/// Altitude in meters where the thermosphere of the Jacchia model starts.
+const THERMOSPHERE_BASE: f64 = 120_000.;
+
+/// Temperature at the base of the thermosphere in K.
+const THERMOSPHERE_BASE_TEMPERATURE: f64 = 380.;
+
+/// Radius in meters geopotential altitudes are measured on, and surface gravity in m/s².
+const GEOPOTENTIAL_RADIUS: f64 = 6_356_766.;
+const STANDARD_GRAVITY: f64 = 9.80665;
+
+const BOLTZMANN: f64 = 1.380649e-23;
+const ATOMIC_MASS: f64 = 1.66053907e-27;
+
+impl Atmosphere {
+    /// The earth's US Standard Atmosphere 1976, up to 1500 km.
+    pub fn us_standard_1976() -> Atmosphere {
+        Atmosphere {
+            model: AtmosphereModel::Layered(us_standard_1976_layers().to_vec()),
+            ceiling: 1_500_000.,
+        }
+    }
+
+    /// The earth's atmosphere under a 10.7 cm solar flux of `solar_flux` and a geomagnetic index
+    /// Ap of `geomagnetic_index`, up to 1500 km.
+    pub fn jacchia(solar_flux: f64, geomagnetic_index: f64) -> Atmosphere {
+        Atmosphere {
+            model: AtmosphereModel::Jacchia {
+                solar_flux,
+                geomagnetic_index,
+            },
+            ceiling: 1_500_000.,
+        }
+    }
+
+    /// Density in kg/m³ at an altitude in meters.
+    pub fn density(&self, altitude: f64) -> f64 {
+        if altitude > self.ceiling {
+            return 0.;
+        }
+        match &self.model {
+            AtmosphereModel::Exponential(layer) => layer.density(altitude),
+            AtmosphereModel::Layered(layers) => layered_density(layers, altitude),
+            AtmosphereModel::Jacchia {
+                solar_flux,
+                geomagnetic_index,
+            } => {
+                if altitude < THERMOSPHERE_BASE {
+                    layered_density(us_standard_1976_layers(), altitude)
+                } else {
+                    thermosphere_density(
+                        exospheric_temperature(*solar_flux, *geomagnetic_index),
+                        altitude,
+                    )
+                }
+            }
+        }
+    }
+
+    /// Single exponential layer matching this atmosphere's density and scale height at
+    /// `altitude` meters.
+    pub fn exponential_fit(&self, altitude: f64) -> AtmosphereLayer {
+        // scale height from the density ratio one kilometer up
+        let (lower, upper) = (self.density(altitude), self.density(altitude + 1_000.));
+        let scale_height = if lower > 0. && upper > 0. && lower != upper {
+            1_000. / (lower / upper).ln()
+        } else {
+            f64::INFINITY
+        };
+        AtmosphereLayer {
+            base_altitude: altitude,
+            base_density: lower,
+            scale_height,
+        }
+    }
+
+    /// Rough time in seconds for a near circular orbit of `semi_major_axis` to decay down to the
+    /// re-entry altitude, or `None` if it lasts longer than we look ahead or isn't a bound orbit
+    /// above the surface.
+    ///
+    /// `ballistic` is the drag coefficient times area over mass, and `radius` is the radius of the
+    /// body the atmosphere belongs to.
+    pub fn decay_time(
+        &self,
+        mu: f64,
+        radius: f64,
+        ballistic: f64,
+        semi_major_axis: f64,
+    ) -> Option<f64> {
+        if !semi_major_axis.is_finite() || semi_major_axis <= radius {
+            return None;
+        }
+
+        // orbit averaged decay of a circular orbit, da/dt = -ρ B √(μ a)
+        let mut semi_major_axis = semi_major_axis;
+        let mut time = 0.;
+        while semi_major_axis - radius > REENTRY_ALTITUDE {
+            let density = self.density(semi_major_axis - radius);
+            if density <= 0. {
+                return None;
+            }
+            let rate = density * ballistic * (mu * semi_major_axis).sqrt();
+            let drop = DECAY_STEP.min(semi_major_axis - radius - REENTRY_ALTITUDE);
+
+            time += drop / rate;
+            semi_major_axis -= drop;
+            if time > MAX_DECAY_TIME {
+                return None;
+            }
+        }
+        Some(time)
+    }
+}
+
+/// The US Standard Atmosphere 1976 table in meters, built the first time it's needed.
+fn us_standard_1976_layers() -> &'static [AtmosphereLayer] {
+    static LAYERS: OnceLock<Vec<AtmosphereLayer>> = OnceLock::new();
+    LAYERS.get_or_init(|| {
+        US_STANDARD_1976
+            .iter()
+            .map(
+                |&(base_altitude, base_density, scale_height)| AtmosphereLayer {
+                    base_altitude: base_altitude * 1000.,
+                    base_density,
+                    scale_height: scale_height * 1000.,
+                },
+            )
+            .collect()
+    })
+}
+
+/// Density of the layer `altitude` falls in, or of the lowest layer below all of them.
+fn layered_density(layers: &[AtmosphereLayer], altitude: f64) -> f64 {
+    layers
+        .iter()
+        .rev()
+        .find(|layer| layer.base_altitude <= altitude)
+        .or_else(|| layers.first())
+        .map_or(0., |layer| layer.density(altitude))
+}
+
+/// Global mean exospheric temperature in K, following Jacchia 1971: the night time minimum set
+/// by the solar flux, raised by the average of the diurnal bulge and by geomagnetic heating.
+fn exospheric_temperature(solar_flux: f64, geomagnetic_index: f64) -> f64 {
+    let night_minimum = 379. + 3.24 * solar_flux;
+    let geomagnetic_heating = geomagnetic_index + 100. * (1. - (-0.08 * geomagnetic_index).exp());
+    1.1 * night_minimum + geomagnetic_heating
+}
+
+/// Thermosphere density in kg/m³ at an altitude in meters above `THERMOSPHERE_BASE`, for an
+/// exospheric temperature in K.
+///
+/// Uses Walker's analytic form of the Bates temperature profile, with every gas in diffusive
+/// equilibrium from its density at the base.
+fn thermosphere_density(exospheric_temperature: f64, altitude: f64) -> f64 {
+    let temperature_excess = exospheric_temperature - 800.;
+    let x = temperature_excess / (750. + 1.722e-4 * temperature_excess * temperature_excess);
+    // rate in 1/m at which the temperature closes in on the exospheric one
+    let shape = 2.91e-5 * (-x * x / 2.).exp();
+
+    let geopotential = (altitude - THERMOSPHERE_BASE) * (GEOPOTENTIAL_RADIUS + THERMOSPHERE_BASE)
+        / (GEOPOTENTIAL_RADIUS + altitude);
+    let base_gravity = STANDARD_GRAVITY
+        * (GEOPOTENTIAL_RADIUS / (GEOPOTENTIAL_RADIUS + THERMOSPHERE_BASE)).powi(2);
+    let temperature = exospheric_temperature
+        - (exospheric_temperature - THERMOSPHERE_BASE_TEMPERATURE) * (-shape * geopotential).exp();
+
+    THERMOSPHERE_GASES
+        .iter()
+        .map(
+            |&(molecular_mass, base_number_density, thermal_diffusion)| {
+                let mass = molecular_mass * ATOMIC_MASS;
+                let gamma = mass * base_gravity / (shape * BOLTZMANN * exospheric_temperature);
+                let number_density = base_number_density
+                    * (THERMOSPHERE_BASE_TEMPERATURE / temperature)
+                        .powf(1. + thermal_diffusion + gamma)
+                    * (-shape * gamma * geopotential).exp();
+                mass * number_density
+            },
+        )
+        .sum()
+}
+
+/// Aerodynamic properties of a body flying through an atmosphere. Its mass is taken from its
+/// `PhysicalProperties`.
+#[derive(Component, Clone, Debug)]
+pub struct DragProperties {
+    /// Cross sectional area facing the flow, in m².
+    pub area: f64,
+    pub drag_coefficient: f64,
+}
+
+impl DragProperties {
+    /// Drag coefficient times area over mass.
+    pub fn ballistic(&self, mass: f64) -> f64 {
+        self.drag_coefficient * self.area / mass
+    }
+
+    /// Deceleration in air of `density` flowing past at `relative_velocity`.
+    pub fn acceleration(&self, mass: f64, density: f64, relative_velocity: DVec3) -> DVec3 {
+        -0.5 * density * self.ballistic(mass) * relative_velocity.length() * relative_velocity
+    }
+}
+
+/// Sent when a body with drag falls below the re-entry altitude of an atmosphere. The body is
+/// despawned.
+pub struct ReentryEvent {
+    pub body: Entity,
+    /// Body whose atmosphere it entered.
+    pub primary: Entity,
+    /// Simulated seconds since the start of the simulation.
+    pub time: f64,
+}
+
+/// Readout of the focused body's orbital lifetime.
+#[derive(Component)]
+struct LifetimeText;
+
+/// Plugin used to detect re-entries and estimate orbital lifetimes.
+pub struct AtmospherePlugin;
+
+impl Plugin for AtmospherePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ReentryEvent>();
+        app.add_startup_system(spawn_lifetime_text);
+        app.add_system(detect_reentries.after(simulation::LABEL));
+        app.add_system(log_reentries.after(detect_reentries));
+        app.add_system(update_lifetime_text);
+    }
+}
+
+fn detect_reentries(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    mut reentries: EventWriter<ReentryEvent>,
+    drag_query: Query<(Entity, &PhysicalProperties), With<DragProperties>>,
+    atmosphere_query: Query<(Entity, &PhysicalProperties), With<Atmosphere>>,
+) {
+    for (body, body_properties) in drag_query.iter() {
+        let primary = atmosphere_query.iter().find(|(primary, properties)| {
+            let altitude = body_properties
+                .translation
+                .distance(&properties.translation)
+                .to_f64()
+                - properties.estimated_radius.to_f64();
+            *primary != body && altitude < REENTRY_ALTITUDE
+        });
+
+        if let Some((primary, _)) = primary {
+            commands.entity(body).despawn_recursive();
+            reentries.send(ReentryEvent {
+                body,
+                primary,
+                time: clock.elapsed,
+            });
+        }
+    }
+}
+
+fn log_reentries(mut reentries: EventReader<ReentryEvent>, name_query: Query<&RenderInUI>) {
+    let name = |entity: Entity| match name_query.get(entity) {
+        Ok(name) => name.0.clone(),
+        Err(_) => format!("{:?}", entity),
+    };
+
+    for reentry in reentries.iter() {
+        info!(
+            "{} re-entered {} after {:.2} days",
+            name(reentry.body),
+            name(reentry.primary),
+            reentry.time / 86_400.
+        );
+    }
+}
+
+fn spawn_lifetime_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(20.),
+                    top: Val::Px(200.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
+                    font_size: 18.0,
+                    color: Color::rgb(1.0, 0.5, 0.3),
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(LifetimeText);
+}
+
+fn update_lifetime_text(
+    conics: Res<Conics>,
+    focused_query: Query<(Entity, &PhysicalProperties, &DragProperties), With<Focused>>,
+    atmosphere_query: Query<(&PhysicalProperties, &Atmosphere)>,
+    mut text_query: Query<&mut Text, With<LifetimeText>>,
+) {
+    let mut text = match text_query.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    let lifetime = focused_query
+        .get_single()
+        .ok()
+        .and_then(|(body, properties, drag)| {
+            let conic = conics.0.iter().find(|conic| conic.body == body)?;
+            let (primary, atmosphere) = atmosphere_query.get(conic.primary).ok()?;
+            let mu = (GRAVITATIONAL_CONSTANT * primary.mass.clone()).to_f64();
+            Some(atmosphere.decay_time(
+                mu,
+                primary.estimated_radius.to_f64(),
+                drag.ballistic(properties.mass.to_f64()),
+                conic.elements.semi_major_axis,
+            ))
+        });
+
+    text.sections[0].value = match lifetime {
+        Some(Some(time)) => format!("re-entry in {:.1} d", time / 86_400.),
+        Some(None) => "no decay within 100 y".to_string(),
+        None => String::new(),
+    };
+}
@@ -6,9 +6,11 @@ use bevy_inspector_egui::Inspectable;
 
 use crate::{earth::DISTANCE_FROM_SUN, simulation::PhysicalProperties};
 
+pub const LABEL: &str = "CAMERA";
+
 /// Sample code taken from: https://bevy-cheatbook.github.io/cookbook/pan-orbit-camera.html
 /// Edited for my needs :D
-
+///
 /// Tags an entity as capable of panning and orbiting.
 #[derive(Component)]
 pub struct PanOrbitCamera {
@@ -37,6 +39,10 @@ pub struct Focused;
 #[derive(Component, Inspectable, Default)]
 pub struct Focusable;
 
+/// The body the focused one measures itself against, for rendezvous and relative motion.
+#[derive(Component, Inspectable, Default)]
+pub struct Targeted;
+
 /// Pan the camera with middle mouse click, zoom with scroll wheel, orbit with right mouse click.
 pub fn pan_orbit_camera(
     windows: Res<Windows>,
@@ -67,17 +73,18 @@ pub fn pan_orbit_camera(
     }
 
     for (mut orbit_cam, mut transform) in query.iter_mut() {
-        match focused_query.get_single() {
-            Ok((focused_transform, physical_properties)) => {
-                orbit_cam.focus = focused_transform.translation;
-                let _ = transform.looking_at(focused_transform.translation, Vec3::Y);
-
-                if input_keyboard.pressed(KeyCode::Space) {
-                    scroll += 0.000001;
-                    orbit_cam.radius = physical_properties.estimated_radius.to_f32() * 4.;
-                }
+        let mut min_radius = 0.05;
+
+        if let Ok((focused_transform, physical_properties)) = focused_query.get_single() {
+            // keep the near plane outside of the focused body so close-ups don't clip into it
+            min_radius = physical_properties.estimated_radius.to_f32() * 1.1;
+            orbit_cam.focus = focused_transform.translation;
+            let _ = transform.looking_at(focused_transform.translation, Vec3::Y);
+
+            if input_keyboard.pressed(KeyCode::Space) {
+                scroll += 0.000001;
+                orbit_cam.radius = physical_properties.estimated_radius.to_f32() * 4.;
             }
-            Err(_) => {}
         }
 
         if orbit_button_changed {
@@ -101,11 +108,11 @@ pub fn pan_orbit_camera(
             let yaw = Quat::from_rotation_y(-delta_x);
             let pitch = Quat::from_rotation_x(-delta_y);
             transform.rotation = yaw * transform.rotation; // rotate around global y axis
-            transform.rotation = transform.rotation * pitch; // rotate around local x axis
+            transform.rotation *= pitch; // rotate around local x axis
         } else if scroll.abs() > 0.0 {
             orbit_cam.radius -= scroll * orbit_cam.radius * 0.01;
             // dont allow zoom to reach zero or you get stuck
-            orbit_cam.radius = f32::max(orbit_cam.radius, 0.05);
+            orbit_cam.radius = f32::max(orbit_cam.radius, min_radius);
         }
 
         // emulating parent/child to make the yaw/y-axis rotation behave like a turntable
@@ -142,10 +149,42 @@ pub fn switch_focus(
     }
 }
 
+/// Cycles the target through the focusable bodies other than the focused one.
+pub fn switch_target(
+    mut commands: Commands,
+    input_keyboard: Res<Input<KeyCode>>,
+    focusable_query: Query<(Entity, Option<&Focused>, Option<&Targeted>), With<Focusable>>,
+) {
+    if !input_keyboard.just_pressed(KeyCode::Left) {
+        return;
+    }
+
+    let candidates: Vec<_> = focusable_query
+        .iter()
+        .filter(|(_, focused, _)| focused.is_none())
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+    let next = match candidates
+        .iter()
+        .position(|(_, _, targeted)| targeted.is_some())
+    {
+        Some(current) => (current + 1) % candidates.len(),
+        None => 0,
+    };
+
+    for (entity, _, targeted) in focusable_query.iter() {
+        if targeted.is_some() {
+            commands.entity(entity).remove::<Targeted>();
+        }
+    }
+    commands.entity(candidates[next].0).insert(Targeted);
+}
+
 fn get_primary_window_size(windows: &Res<Windows>) -> Vec2 {
     let window = windows.get_primary().unwrap();
-    let window = Vec2::new(window.width() as f32, window.height() as f32);
-    window
+    Vec2::new(window.width(), window.height())
 }
 
 /// Spawn a camera like this
@@ -156,6 +195,10 @@ pub fn spawn_camera(mut commands: Commands) {
                 far: DISTANCE_FROM_SUN * 2.,
                 ..default()
             },
+            perspective_projection: PerspectiveProjection {
+                far: DISTANCE_FROM_SUN * 2.,
+                ..default()
+            },
             ..default()
         })
         .insert(PanOrbitCamera {
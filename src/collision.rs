@@ -0,0 +1,369 @@
+use bevy::{math::DVec3, prelude::*};
+use rug::Float;
+
+use crate::{
+    camera::Focused,
+    simulation::{
+        self, HPVec3, PhysicalProperties, ReferenceFrame, Simulated, SimulationClock,
+        DEFAULT_PRECISION,
+    },
+    ui::RenderInUI,
+    view::BaseScale,
+};
+
+/// What happens to two bodies that touch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CollisionResponse {
+    /// The bodies become one, keeping their mass, momentum and volume.
+    Merge,
+    /// The bodies bounce apart, keeping this fraction of their closing speed.
+    Bounce { restitution: f64 },
+    /// The smaller body is removed.
+    Despawn,
+}
+
+/// Settings for collisions between simulated bodies.
+pub struct CollisionSettings {
+    pub response: CollisionResponse,
+}
+
+impl Default for CollisionSettings {
+    fn default() -> Self {
+        CollisionSettings {
+            response: CollisionResponse::Merge,
+        }
+    }
+}
+
+/// Sent whenever two simulated bodies touch, whatever the response.
+pub struct CollisionEvent {
+    /// The heavier of the two bodies.
+    pub larger: Entity,
+    pub smaller: Entity,
+    /// Simulated seconds since the start of the simulation at first contact.
+    pub time: f64,
+    /// Speed at which the bodies closed, in m/s.
+    pub relative_speed: f64,
+    pub response: CollisionResponse,
+}
+
+/// Where a body was at the end of the previous step, to sweep its path for collisions.
+#[derive(Component)]
+struct PreviousTranslation(HPVec3);
+
+/// Plugin used to detect and resolve collisions between bodies.
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollisionSettings>();
+        app.add_event::<CollisionEvent>();
+        app.add_system(toggle_collision_response);
+        app.add_system(resolve_collisions.after(simulation::LABEL));
+        app.add_system(log_collisions.after(resolve_collisions));
+    }
+}
+
+/// Cycle between collision responses with C.
+fn toggle_collision_response(
+    input_keyboard: Res<Input<KeyCode>>,
+    mut settings: ResMut<CollisionSettings>,
+) {
+    if input_keyboard.just_pressed(KeyCode::C) {
+        settings.response = match settings.response {
+            CollisionResponse::Merge => CollisionResponse::Bounce { restitution: 0.5 },
+            CollisionResponse::Bounce { .. } => CollisionResponse::Despawn,
+            CollisionResponse::Despawn => CollisionResponse::Merge,
+        };
+        info!("collisions now {:?}", settings.response);
+    }
+}
+
+/// Fraction of the last step at which two spheres moving in straight lines first touch, given
+/// the offset between them at its start and end.
+pub fn contact_fraction(start: DVec3, end: DVec3, contact_distance: f64) -> Option<f64> {
+    let motion = end - start;
+    let c = start.length_squared() - contact_distance * contact_distance;
+    if c <= 0. {
+        // already touching, which only counts while they're still closing in
+        return (start.dot(motion) < 0.).then_some(0.);
+    }
+
+    let a = motion.length_squared();
+    let b = 2. * start.dot(motion);
+    let discriminant = b * b - 4. * a * c;
+    if a <= 0. || discriminant < 0. {
+        return None;
+    }
+
+    let fraction = (-b - discriminant.sqrt()) / (2. * a);
+    (0.0..=1.).contains(&fraction).then_some(fraction)
+}
+
+fn resolve_collisions(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    settings: Res<CollisionSettings>,
+    mut collisions: EventWriter<CollisionEvent>,
+    mut sim_query: Query<
+        (
+            Entity,
+            &mut PhysicalProperties,
+            Option<&mut PreviousTranslation>,
+            Option<&mut BaseScale>,
+        ),
+        With<Simulated>,
+    >,
+    fixed_query: Query<(), With<ReferenceFrame>>,
+    focused_query: Query<(), With<Focused>>,
+) {
+    // where every body started and ended the last step, with its radius and mass
+    let mut bodies: Vec<(Entity, HPVec3, HPVec3, f64, f64)> = sim_query
+        .iter()
+        .map(|(entity, properties, previous, _)| {
+            let previous = previous.map_or_else(|| properties.translation.clone(), |p| p.0.clone());
+            (
+                entity,
+                previous,
+                properties.translation.clone(),
+                properties.estimated_radius.to_f64(),
+                properties.mass.to_f64(),
+            )
+        })
+        .collect();
+
+    let mut removed = Vec::new();
+    for a in 0..bodies.len() {
+        for b in (a + 1)..bodies.len() {
+            let (a_entity, _, _, a_radius, a_mass) = bodies[a];
+            let (b_entity, _, _, b_radius, b_mass) = bodies[b];
+            if removed.contains(&a_entity) || removed.contains(&b_entity) {
+                continue;
+            }
+
+            let start = HPVec3::sub(&bodies[b].1, &bodies[a].1).to_dvec3();
+            let end = HPVec3::sub(&bodies[b].2, &bodies[a].2).to_dvec3();
+            let fraction = match contact_fraction(start, end, a_radius + b_radius) {
+                Some(fraction) => fraction,
+                None => continue,
+            };
+
+            let (larger, smaller) = if a_mass >= b_mass {
+                (a_entity, b_entity)
+            } else {
+                (b_entity, a_entity)
+            };
+            let [(_, mut larger_properties, _, larger_scale), (_, mut smaller_properties, _, _)] =
+                match sim_query.get_many_mut([larger, smaller]) {
+                    Ok(pair) => pair,
+                    Err(_) => continue,
+                };
+            let relative_velocity = HPVec3::sub(
+                &smaller_properties.acceleration,
+                &larger_properties.acceleration,
+            )
+            .to_dvec3();
+
+            match settings.response {
+                CollisionResponse::Merge => {
+                    let old_radius = larger_properties.estimated_radius.to_f64();
+                    merge(
+                        &mut larger_properties,
+                        &smaller_properties,
+                        fixed_query.get(larger).is_ok(),
+                    );
+                    if let Some(mut scale) = larger_scale {
+                        let growth = larger_properties.estimated_radius.to_f64() / old_radius;
+                        scale.0 *= growth as f32;
+                    }
+                }
+                CollisionResponse::Bounce { restitution } => bounce(
+                    &mut larger_properties,
+                    &mut smaller_properties,
+                    fixed_query.get(larger).is_ok(),
+                    fixed_query.get(smaller).is_ok(),
+                    restitution,
+                ),
+                CollisionResponse::Despawn => {}
+            }
+
+            if !matches!(settings.response, CollisionResponse::Bounce { .. }) {
+                commands.entity(smaller).despawn_recursive();
+                if focused_query.get(smaller).is_ok() {
+                    commands.entity(larger).insert(Focused);
+                }
+                removed.push(smaller);
+            }
+
+            // later pairs sweep against where the bodies are now, and how big and heavy
+            for index in [a, b] {
+                if let Ok((_, properties, _, _)) = sim_query.get(bodies[index].0) {
+                    bodies[index].2 = properties.translation.clone();
+                    bodies[index].3 = properties.estimated_radius.to_f64();
+                    bodies[index].4 = properties.mass.to_f64();
+                }
+            }
+
+            collisions.send(CollisionEvent {
+                larger,
+                smaller,
+                time: clock.elapsed - clock.timestep * (1. - fraction),
+                relative_speed: relative_velocity.length(),
+                response: settings.response,
+            });
+        }
+    }
+
+    for (entity, properties, previous, _) in sim_query.iter_mut() {
+        match previous {
+            Some(mut previous) => previous.0 = properties.translation.clone(),
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(PreviousTranslation(properties.translation.clone()));
+            }
+        }
+    }
+}
+
+/// Folds `smaller` into `larger` at their center of mass, unless `larger` is a fixed reference
+/// frame which stays where it is.
+fn merge(larger: &mut PhysicalProperties, smaller: &PhysicalProperties, fixed: bool) {
+    let mass = Float::with_val(DEFAULT_PRECISION, &larger.mass + &smaller.mass);
+    let weighted = |a: &HPVec3, b: &HPVec3| {
+        let sum = HPVec3::add(
+            &HPVec3::scalar_mul(a, &larger.mass),
+            &HPVec3::scalar_mul(b, &smaller.mass),
+        );
+        HPVec3::scalar_mul(&sum, &Float::with_val(DEFAULT_PRECISION, 1 / &mass))
+    };
+
+    larger.acceleration = weighted(&larger.acceleration, &smaller.acceleration);
+    if !fixed {
+        larger.translation = weighted(&larger.translation, &smaller.translation);
+    }
+
+    // volumes add up
+    let radius = (larger.estimated_radius.clone().square() * &larger.estimated_radius
+        + smaller.estimated_radius.clone().square() * &smaller.estimated_radius)
+        .cbrt();
+    larger.estimated_radius = radius;
+    larger.mass = mass;
+}
+
+/// Pushes the bodies apart until they just touch and reflects their velocities along the line
+/// between them, treating fixed reference frames as immovable.
+fn bounce(
+    a: &mut PhysicalProperties,
+    b: &mut PhysicalProperties,
+    a_fixed: bool,
+    b_fixed: bool,
+    restitution: f64,
+) {
+    let offset = HPVec3::sub(&b.translation, &a.translation).to_dvec3();
+    let normal = offset.normalize_or_zero();
+    let (a_mass, b_mass) = (a.mass.to_f64(), b.mass.to_f64());
+    let (a_share, b_share) = match (a_fixed, b_fixed) {
+        (true, true) => return,
+        (true, false) => (0., 1.),
+        (false, true) => (1., 0.),
+        (false, false) => (b_mass / (a_mass + b_mass), a_mass / (a_mass + b_mass)),
+    };
+
+    // left overlapping, the next sweep would find them touching again
+    let overlap = (a.estimated_radius.to_f64() + b.estimated_radius.to_f64()) - offset.length();
+    if overlap > 0. {
+        let push = normal * overlap;
+        a.translation = HPVec3::sub(&a.translation, &HPVec3::from_dvec3(push * a_share));
+        b.translation = HPVec3::add(&b.translation, &HPVec3::from_dvec3(push * b_share));
+    }
+
+    let closing = HPVec3::sub(&a.acceleration, &b.acceleration)
+        .to_dvec3()
+        .dot(normal);
+    // already separating
+    if closing <= 0. {
+        return;
+    }
+    let impulse = normal * (1. + restitution) * closing;
+
+    a.acceleration = HPVec3::sub(&a.acceleration, &HPVec3::from_dvec3(impulse * a_share));
+    b.acceleration = HPVec3::add(&b.acceleration, &HPVec3::from_dvec3(impulse * b_share));
+}
+
+fn log_collisions(mut collisions: EventReader<CollisionEvent>, name_query: Query<&RenderInUI>) {
+    let name = |entity: Entity| match name_query.get(entity) {
+        Ok(name) => name.0.clone(),
+        Err(_) => format!("{:?}", entity),
+    };
+
+    for collision in collisions.iter() {
+        info!(
+            "{} hit {} at {:.0} m/s after {:.2} days, {:?}",
+            name(collision.smaller),
+            name(collision.larger),
+            collision.relative_speed,
+            collision.time / 86_400.,
+            collision.response
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(mass: f64, radius: f64, position: DVec3, velocity: DVec3) -> PhysicalProperties {
+        PhysicalProperties {
+            mass: Float::with_val(DEFAULT_PRECISION, mass),
+            estimated_radius: Float::with_val(DEFAULT_PRECISION, radius),
+            acceleration: HPVec3::from_dvec3(velocity),
+            translation: HPVec3::from_dvec3(position),
+        }
+    }
+
+    #[test]
+    fn catches_bodies_tunnelling_through_each_other() {
+        // from 10 m on one side to 10 m on the other, touching 2 m apart
+        let fraction = contact_fraction(DVec3::X * 10., DVec3::X * -10., 2.);
+        assert_eq!(fraction, Some(0.4));
+    }
+
+    #[test]
+    fn grazing_counts_and_missing_does_not() {
+        let start = DVec3::new(-10., 2., 0.);
+        let end = DVec3::new(10., 2., 0.);
+        let fraction = contact_fraction(start, end, 2.).unwrap();
+        assert!((fraction - 0.5).abs() < 1e-9);
+
+        assert_eq!(contact_fraction(start, end, 1.99), None);
+        // touching only after the step is over
+        assert_eq!(contact_fraction(DVec3::X * 10., DVec3::X * 5., 2.), None);
+    }
+
+    #[test]
+    fn overlapping_bodies_only_touch_while_closing() {
+        assert_eq!(
+            contact_fraction(DVec3::X * 1., DVec3::X * 0.5, 2.),
+            Some(0.)
+        );
+        assert_eq!(contact_fraction(DVec3::X * 1., DVec3::X * 1.5, 2.), None);
+    }
+
+    #[test]
+    fn bounce_leaves_bodies_touching_and_separating() {
+        let mut a = properties(3., 1., DVec3::ZERO, DVec3::X);
+        let mut b = properties(1., 1., DVec3::X * 1.5, -DVec3::X);
+        bounce(&mut a, &mut b, false, false, 1.);
+
+        let offset = HPVec3::sub(&b.translation, &a.translation).to_dvec3();
+        assert!((offset.length() - 2.).abs() < 1e-9);
+        // the lighter body moves three times as far
+        assert!((a.translation.to_dvec3().x + 0.125).abs() < 1e-9);
+
+        let closing = HPVec3::sub(&b.acceleration, &a.acceleration).to_dvec3();
+        assert!((closing.x - 2.).abs() < 1e-9);
+        let end = offset + closing;
+        assert_eq!(contact_fraction(offset, end, 2.), None);
+    }
+}
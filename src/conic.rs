@@ -0,0 +1,342 @@
+use std::f64::consts::PI;
+
+use bevy::{math::DVec3, prelude::*, render::mesh::PrimitiveTopology};
+
+use crate::{
+    camera::{self, Focused, PanOrbitCamera},
+    lines::{set_lines, spawn_lines, LineMaterialHandle},
+    orbit::{primaries, OrbitalElements},
+    simulation::{
+        body_states, HPVec3, PhysicalProperties, ReferenceFrame, Simulated, GRAVITATIONAL_CONSTANT,
+    },
+    view::{self, RenderOrigin, RenderScale},
+};
+
+/// Open orbits are drawn out to this many periapsis distances from the primary.
+const OPEN_ORBIT_EXTENT: f64 = 20.;
+
+const CONIC_COLOR: [f32; 4] = [0.2, 0.8, 1.0, 0.5];
+
+const APSIDES_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.35];
+
+/// Settings for the analytic orbit overlay.
+pub struct ConicSettings {
+    pub enabled: bool,
+    /// Line segments per drawn orbit.
+    pub segments: usize,
+}
+
+impl Default for ConicSettings {
+    fn default() -> Self {
+        ConicSettings {
+            enabled: true,
+            segments: 256,
+        }
+    }
+}
+
+/// Osculating orbit of a body, drawn around where its primary is now.
+pub struct Conic {
+    pub body: Entity,
+    pub primary: Entity,
+    pub elements: OrbitalElements,
+}
+
+/// Osculating orbits of every simulated body that has a primary, refreshed every frame.
+#[derive(Default)]
+pub struct Conics(pub Vec<Conic>);
+
+/// Line mesh drawing every conic.
+#[derive(Component)]
+struct ConicLines;
+
+/// Screen space label for the node of a body's conic.
+#[derive(Component)]
+struct NodeLabel {
+    body: Entity,
+    ascending: bool,
+}
+
+/// Readout of the focused body's osculating elements.
+#[derive(Component)]
+struct ElementsText;
+
+/// Plugin used to overlay the Keplerian orbit implied by each body's current state.
+pub struct ConicPlugin;
+
+impl Plugin for ConicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConicSettings>();
+        app.init_resource::<Conics>();
+        app.add_startup_system(spawn_conic_lines);
+        app.add_startup_system(spawn_elements_text);
+        app.add_system(toggle_conics);
+        app.add_system(update_conics.after(view::LABEL));
+        app.add_system(draw_conics.after(update_conics));
+        app.add_system(spawn_node_labels);
+        app.add_system(update_node_labels.after(update_conics).after(camera::LABEL));
+        app.add_system(update_elements_text.after(update_conics));
+    }
+}
+
+/// Show or hide the orbit overlay with O.
+fn toggle_conics(input_keyboard: Res<Input<KeyCode>>, mut settings: ResMut<ConicSettings>) {
+    if input_keyboard.just_pressed(KeyCode::O) {
+        settings.enabled = !settings.enabled;
+    }
+}
+
+fn update_conics(
+    mut conics: ResMut<Conics>,
+    sim_query: Query<(Entity, &PhysicalProperties), With<Simulated>>,
+    ref_query: Query<Entity, With<ReferenceFrame>>,
+) {
+    let bodies = body_states(sim_query.iter(), ref_query.get_single().ok());
+    let primaries = primaries(&bodies);
+
+    conics.0 = bodies
+        .iter()
+        .zip(primaries.iter())
+        .filter(|(body, _)| !body.fixed)
+        .filter_map(|(body, primary)| {
+            let primary = &bodies[(*primary)?];
+            let position = HPVec3::sub(&body.translation, &primary.translation).to_dvec3();
+            let velocity = HPVec3::sub(&body.velocity, &primary.velocity).to_dvec3();
+            let mu = (GRAVITATIONAL_CONSTANT * (body.mass.clone() + &primary.mass)).to_f64();
+
+            Some(Conic {
+                body: body.entity,
+                primary: primary.entity,
+                elements: OrbitalElements::from_state(position, velocity, mu),
+            })
+        })
+        .collect();
+}
+
+fn spawn_conic_lines(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterialHandle>,
+) {
+    let (line, _) = spawn_lines(
+        &mut commands,
+        &mut meshes,
+        &material,
+        PrimitiveTopology::LineList,
+    );
+    commands.entity(line).insert(ConicLines);
+}
+
+/// True anomalies to sample a conic at, covering the whole ellipse or the near side of an open orbit.
+fn anomaly_range(elements: &OrbitalElements) -> (f64, f64) {
+    match elements.asymptote_anomaly() {
+        None => (-PI, PI),
+        Some(limit) => {
+            // stop where the orbit leaves the drawn extent, well before the asymptote
+            let max_radius = elements.periapsis_distance() * OPEN_ORBIT_EXTENT;
+            let extent = ((elements.semi_latus_rectum() / max_radius - 1.) / elements.eccentricity)
+                .clamp(-1., 1.)
+                .acos();
+            (-extent.min(limit), extent.min(limit))
+        }
+    }
+}
+
+fn draw_conics(
+    mut meshes: ResMut<Assets<Mesh>>,
+    origin: Res<RenderOrigin>,
+    scale: Res<RenderScale>,
+    settings: Res<ConicSettings>,
+    conics: Res<Conics>,
+    line_query: Query<&Handle<Mesh>, With<ConicLines>>,
+    primary_query: Query<&PhysicalProperties>,
+) {
+    let mesh = match line_query.get_single().ok().and_then(|m| meshes.get_mut(m)) {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    if settings.enabled {
+        for conic in conics.0.iter() {
+            let primary = match primary_query.get(conic.primary) {
+                Ok(primary) => origin.relative(&primary.translation),
+                Err(_) => continue,
+            };
+            let to_render = |offset: DVec3| scale.to_render(primary + offset).to_array();
+            let elements = &conic.elements;
+
+            let (start, end) = anomaly_range(elements);
+            let step = (end - start) / settings.segments as f64;
+            for segment in 0..settings.segments {
+                let from = start + step * segment as f64;
+                positions.push(to_render(elements.position_at(from)));
+                positions.push(to_render(elements.position_at(from + step)));
+                colors.push(CONIC_COLOR);
+                colors.push(CONIC_COLOR);
+            }
+
+            // line of apsides, from periapsis through the primary to apoapsis when there is one
+            let far_end = match elements.apoapsis_distance() {
+                Some(_) => elements.position_at(PI),
+                None => DVec3::ZERO,
+            };
+            positions.push(to_render(elements.position_at(0.)));
+            positions.push(to_render(far_end));
+            colors.push(APSIDES_COLOR);
+            colors.push(APSIDES_COLOR);
+        }
+    }
+
+    set_lines(mesh, positions, colors);
+}
+
+fn spawn_node_labels(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    sim_query: Query<Entity, Added<Simulated>>,
+) {
+    for body in sim_query.iter() {
+        for ascending in [true, false] {
+            commands
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        display: Display::None,
+                        ..default()
+                    },
+                    text: Text::with_section(
+                        if ascending { "AN" } else { "DN" },
+                        TextStyle {
+                            font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
+                            font_size: 14.0,
+                            color: Color::rgb(0.2, 0.8, 1.0),
+                        },
+                        default(),
+                    ),
+                    ..default()
+                })
+                .insert(NodeLabel { body, ascending });
+        }
+    }
+}
+
+fn update_node_labels(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    origin: Res<RenderOrigin>,
+    scale: Res<RenderScale>,
+    settings: Res<ConicSettings>,
+    conics: Res<Conics>,
+    camera_query: Query<(&Camera, &Transform), With<PanOrbitCamera>>,
+    body_query: Query<&PhysicalProperties>,
+    mut label_query: Query<(Entity, &NodeLabel, &mut Style)>,
+) {
+    let (camera, camera_transform) = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let camera_global = GlobalTransform::from(*camera_transform);
+
+    for (label_entity, label, mut style) in label_query.iter_mut() {
+        if body_query.get(label.body).is_err() {
+            commands.entity(label_entity).despawn_recursive();
+            continue;
+        }
+
+        let conic = conics.0.iter().find(|conic| conic.body == label.body);
+        let screen = conic.filter(|_| settings.enabled).and_then(|conic| {
+            let anomaly = conic.elements.ascending_node_anomaly()?;
+            let anomaly = if label.ascending {
+                anomaly
+            } else {
+                anomaly + PI
+            };
+            if !conic.elements.reaches(anomaly) {
+                return None;
+            }
+            let primary = body_query.get(conic.primary).ok()?;
+            let position = scale.to_render(
+                origin.relative(&primary.translation) + conic.elements.position_at(anomaly),
+            );
+            camera.world_to_screen(&windows, &images, &camera_global, position)
+        });
+
+        match screen {
+            Some(screen) => {
+                style.display = Display::Flex;
+                style.position = Rect {
+                    left: Val::Px(screen.x),
+                    bottom: Val::Px(screen.y),
+                    ..default()
+                };
+            }
+            None => style.display = Display::None,
+        }
+    }
+}
+
+fn spawn_elements_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(20.),
+                    top: Val::Px(20.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
+                    font_size: 18.0,
+                    color: Color::rgb(0.2, 0.8, 1.0),
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(ElementsText);
+}
+
+fn update_elements_text(
+    conics: Res<Conics>,
+    focused_query: Query<Entity, With<Focused>>,
+    mut text_query: Query<&mut Text, With<ElementsText>>,
+) {
+    let mut text = match text_query.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    let conic = focused_query
+        .get_single()
+        .ok()
+        .and_then(|focused| conics.0.iter().find(|conic| conic.body == focused));
+    text.sections[0].value = match conic {
+        Some(conic) => {
+            let elements = &conic.elements;
+            let period = match elements.period() {
+                Some(period) => format!("{:.2} d", period / 86_400.),
+                None => "open".to_string(),
+            };
+            format!(
+                "mu {:.4e}\na  {:.0} km\ne  {:.6}\ni  {:.3} deg\nRAAN {:.3} deg\nw  {:.3} deg\nv  {:.3} deg\nT  {}",
+                elements.mu,
+                elements.semi_major_axis / 1000.,
+                elements.eccentricity,
+                elements.inclination.to_degrees(),
+                elements.longitude_of_ascending_node.to_degrees(),
+                elements.argument_of_periapsis.to_degrees(),
+                elements.true_anomaly.to_degrees(),
+                period,
+            )
+        }
+        None => String::new(),
+    };
+}
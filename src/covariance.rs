@@ -0,0 +1,214 @@
+use std::f64::consts::PI;
+
+use bevy::{
+    math::{DMat3, DVec3},
+    prelude::*,
+    render::mesh::PrimitiveTopology,
+};
+
+use crate::{
+    lines::{set_lines, spawn_lines, LineMaterialHandle},
+    simulation::{self, PhysicalProperties},
+    variational::{StateMatrix, StateTransition},
+    view::{self, RenderOrigin, RenderScale},
+};
+
+/// Line segments around each drawn ellipse.
+const ELLIPSE_SEGMENTS: usize = 64;
+
+/// Jacobi rotations allowed when finding the axes of an ellipsoid.
+const JACOBI_ROTATIONS: usize = 50;
+
+/// Sigma levels drawn, with their colors.
+const SIGMA_LEVELS: [(f64, [f32; 4]); 2] =
+    [(1., [1.0, 0.3, 0.3, 0.9]), (3., [1.0, 0.3, 0.3, 0.35])];
+
+/// Position and velocity covariance of a body, carried forward from `epoch` by its state
+/// transition matrix.
+#[derive(Component, Clone, Debug)]
+pub struct Covariance {
+    /// Covariance at `epoch`, in m² and m²/s².
+    pub initial: StateMatrix,
+    /// Simulated seconds since the start of the simulation the initial covariance holds at,
+    /// when the body's state transition matrix was last reset.
+    pub epoch: f64,
+    /// Covariance now.
+    pub current: StateMatrix,
+}
+
+impl Covariance {
+    /// Independent errors of `position_sigma` meters along every axis and `velocity_sigma` m/s
+    /// along every direction of motion, at `epoch`.
+    pub fn diagonal(position_sigma: f64, velocity_sigma: f64, epoch: f64) -> Covariance {
+        let initial = StateMatrix {
+            rr: DMat3::IDENTITY * position_sigma.powi(2),
+            rv: DMat3::ZERO,
+            vr: DMat3::ZERO,
+            vv: DMat3::IDENTITY * velocity_sigma.powi(2),
+        };
+        Covariance {
+            initial,
+            epoch,
+            current: initial,
+        }
+    }
+}
+
+/// Settings for the uncertainty overlay.
+pub struct CovarianceSettings {
+    pub enabled: bool,
+}
+
+impl Default for CovarianceSettings {
+    fn default() -> Self {
+        CovarianceSettings { enabled: true }
+    }
+}
+
+/// Line mesh drawing every uncertainty ellipsoid.
+#[derive(Component)]
+struct CovarianceLines;
+
+/// Plugin used to propagate position and velocity uncertainty and draw it around bodies.
+pub struct CovariancePlugin;
+
+impl Plugin for CovariancePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CovarianceSettings>();
+        app.add_startup_system(spawn_covariance_lines);
+        app.add_system(toggle_covariances);
+        app.add_system(propagate_covariances.after(simulation::LABEL));
+        app.add_system(
+            draw_covariances
+                .after(propagate_covariances)
+                .after(view::LABEL),
+        );
+    }
+}
+
+/// Show or hide the uncertainty ellipsoids with U.
+fn toggle_covariances(
+    input_keyboard: Res<Input<KeyCode>>,
+    mut settings: ResMut<CovarianceSettings>,
+) {
+    if input_keyboard.just_pressed(KeyCode::U) {
+        settings.enabled = !settings.enabled;
+    }
+}
+
+/// Maps every covariance forward as Φ P₀ Φᵀ. When the transition matrix has been reset since,
+/// the covariance carried so far becomes the new starting point.
+fn propagate_covariances(mut query: Query<(&mut Covariance, &StateTransition)>) {
+    for (mut covariance, transition) in query.iter_mut() {
+        if covariance.epoch != transition.epoch {
+            covariance.initial = covariance.current;
+            covariance.epoch = transition.epoch;
+        }
+        let matrix = transition.matrix;
+        covariance.current = matrix * covariance.initial * matrix.transpose();
+    }
+}
+
+/// Variances along the principal axes of a symmetric matrix, with the axes, by Jacobi rotations.
+pub fn principal_axes(matrix: DMat3) -> [(f64, DVec3); 3] {
+    let mut a = matrix.transpose().to_cols_array_2d();
+    let mut v = DMat3::IDENTITY.to_cols_array_2d();
+
+    for _ in 0..JACOBI_ROTATIONS {
+        // zero the largest off diagonal entry
+        let (p, q) = [(0, 1), (0, 2), (1, 2)]
+            .into_iter()
+            .max_by(|(a0, a1), (b0, b1)| a[*a0][*a1].abs().total_cmp(&a[*b0][*b1].abs()))
+            .unwrap_or((0, 1));
+        if a[p][q].abs() <= f64::EPSILON * (a[p][p].abs() + a[q][q].abs()) {
+            break;
+        }
+        let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+        let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+        let c = 1. / (t * t + 1.).sqrt();
+        let s = t * c;
+
+        for row in a.iter_mut() {
+            let (kp, kq) = (row[p], row[q]);
+            row[p] = c * kp - s * kq;
+            row[q] = s * kp + c * kq;
+        }
+        let (row_p, row_q) = (a[p], a[q]);
+        for k in 0..3 {
+            a[p][k] = c * row_p[k] - s * row_q[k];
+            a[q][k] = s * row_p[k] + c * row_q[k];
+        }
+        for row in v.iter_mut() {
+            let (kp, kq) = (row[p], row[q]);
+            row[p] = c * kp - s * kq;
+            row[q] = s * kp + c * kq;
+        }
+    }
+
+    [0, 1, 2].map(|axis| {
+        (
+            a[axis][axis],
+            DVec3::new(v[0][axis], v[1][axis], v[2][axis]),
+        )
+    })
+}
+
+fn spawn_covariance_lines(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterialHandle>,
+) {
+    let (lines, _) = spawn_lines(
+        &mut commands,
+        &mut meshes,
+        &material,
+        PrimitiveTopology::LineList,
+    );
+    commands.entity(lines).insert(CovarianceLines);
+}
+
+/// Draws the 1σ and 3σ position uncertainty ellipsoids of every body as the three ellipses
+/// through their principal axes.
+fn draw_covariances(
+    mut meshes: ResMut<Assets<Mesh>>,
+    origin: Res<RenderOrigin>,
+    scale: Res<RenderScale>,
+    settings: Res<CovarianceSettings>,
+    line_query: Query<&Handle<Mesh>, With<CovarianceLines>>,
+    covariance_query: Query<(&Covariance, &PhysicalProperties)>,
+) {
+    let mesh = match line_query.get_single().ok().and_then(|m| meshes.get_mut(m)) {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    if settings.enabled {
+        for (covariance, properties) in covariance_query.iter() {
+            let center = origin.relative(&properties.translation);
+            let axes = principal_axes(covariance.current.rr)
+                .map(|(variance, axis)| axis * variance.max(0.).sqrt());
+
+            for (sigma, color) in SIGMA_LEVELS {
+                for (first, second) in [(0, 1), (1, 2), (2, 0)] {
+                    let point = |angle: f64| {
+                        let offset =
+                            (axes[first] * angle.cos() + axes[second] * angle.sin()) * sigma;
+                        scale.to_render(center + offset).to_array()
+                    };
+                    let step = 2. * PI / ELLIPSE_SEGMENTS as f64;
+                    for segment in 0..ELLIPSE_SEGMENTS {
+                        let angle = step * segment as f64;
+                        positions.push(point(angle));
+                        positions.push(point(angle + step));
+                        colors.push(color);
+                        colors.push(color);
+                    }
+                }
+            }
+        }
+    }
+
+    set_lines(mesh, positions, colors);
+}
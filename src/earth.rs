@@ -1,7 +1,9 @@
+use crate::atmosphere::Atmosphere;
 use crate::camera::{Focusable, Focused};
+use crate::gravity::{GravityField, MAX_DEGREE};
 use crate::simulation::{HPVec3, PhysicalProperties, Rotating, Simulated};
+use crate::trail::Trail;
 use crate::ui::RenderInUI;
-use bevy::core::Zeroable;
 use bevy::prelude::*;
 use rug::Float;
 
@@ -14,11 +16,27 @@ const MASS: f32 = 5.972e+24_f32; // 5.972e+24_f32;
 /// Approximate distance from the sun to earth in meters.
 pub const DISTANCE_FROM_SUN: f32 = 150_000_000_000.;
 
-/// Approximate current acceleration force applied to the earth
-pub const INITIAL_ACCELERATION: f32 = 107_226.;
+/// Approximate orbital velocity of the earth around the sun in m/s.
+pub const ORBITAL_VELOCITY: f32 = 29_750.;
 
 pub const DEGREES_PER_SECOND: f32 = 0.00416666;
 
+/// ICGEM coefficient file of the earth's gravity field, these are free to download from ICGEM.
+const GRAVITY_MODEL: &str = "assets/gravity/EGM2008.gfc";
+
+/// Degree the earth's gravity model starts out truncated at. The whole model is loaded up to
+/// `MAX_DEGREE`, so the gravity field panel can change it later.
+const GRAVITY_MODEL_DEGREE: usize = 20;
+
+/// Equatorial radius the earth's zonal harmonics are normalised to, in meters.
+const EQUATORIAL_RADIUS: f64 = 6_378_137.;
+
+/// J2, J3 and J4 zonal harmonics of the earth, from EGM2008, used without a gravity model file.
+const ZONAL_HARMONICS: [f64; 3] = [1.082_626_68e-3, -2.532_656_5e-6, -1.619_621_6e-6];
+
+/// Simulated seconds of the earth's path around the sun kept in its trail.
+const TRAIL_LENGTH: f64 = 30. * 86_400.;
+
 //1989000000000000000000000000000
 //5972000000000000000000000
 #[derive(Component)]
@@ -29,7 +47,7 @@ pub fn setup_earth(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands
         .spawn_bundle(PbrBundle {
             transform: Transform {
-                translation: translation,
+                translation,
                 rotation: Quat::from_rotation_z(0.4101524),
                 scale: Vec3::new(RADIUS * 2., RADIUS * 2., RADIUS * 2.),
             },
@@ -42,14 +60,34 @@ pub fn setup_earth(mut commands: Commands, asset_server: Res<AssetServer>) {
             degrees_per_second: Float::with_val(128, DEGREES_PER_SECOND),
         })
         .insert(PhysicalProperties {
-            mass: Float::with_val(128, MASS.clone()),
-            estimated_radius: Float::with_val(128, RADIUS.clone()),
-            acceleration: HPVec3::from_vec3(&Vec3::new(0., 0., INITIAL_ACCELERATION)),
+            mass: Float::with_val(128, MASS),
+            estimated_radius: Float::with_val(128, RADIUS),
+            acceleration: HPVec3::from_vec3(&Vec3::new(0., 0., ORBITAL_VELOCITY)),
             translation: HPVec3::from_vec3(&translation),
         })
+        .insert(gravity_field(GRAVITY_MODEL_DEGREE))
+        .insert(Atmosphere::us_standard_1976())
+        .insert(Trail::new(
+            None,
+            TRAIL_LENGTH,
+            Color::rgba(0.3, 0.5, 1.0, 0.8),
+        ))
         .insert(Focused)
         .insert(Focusable)
         .with_children(|earth| {
             earth.spawn_scene(asset_server.load("models/earth_1x.glb#Scene0"));
         });
 }
+
+/// The earth's gravity model truncated at `degree`, or just its largest zonal harmonics when the
+/// model can't be loaded.
+fn gravity_field(degree: usize) -> GravityField {
+    let field = GravityField::load(GRAVITY_MODEL, MAX_DEGREE).map(|mut field| {
+        field.degree = degree.min(field.loaded_degree());
+        field
+    });
+    field.unwrap_or_else(|error| {
+        warn!("{}, falling back on J2 to J4", error);
+        GravityField::zonal(EQUATORIAL_RADIUS, &ZONAL_HARMONICS)
+    })
+}
@@ -0,0 +1,78 @@
+use bevy::{math::DVec3, prelude::*};
+
+use crate::maneuver::frame;
+
+/// Standard gravity in m/s², which specific impulse is measured against.
+pub const STANDARD_GRAVITY: f64 = 9.80665;
+
+/// Which way an engine points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Attitude {
+    /// Along the velocity relative to the primary.
+    Prograde,
+    Retrograde,
+    /// Along the orbit normal.
+    Normal,
+    /// Held along a fixed direction in simulation space.
+    InertialFixed(DVec3),
+}
+
+/// Rocket engine on a spacecraft. Everything the craft weighs above its dry mass is propellant,
+/// burnt off as it thrusts.
+#[derive(Component, Clone, Debug)]
+pub struct Engine {
+    /// Thrust at full throttle in newtons.
+    pub thrust: f64,
+    /// Specific impulse in seconds.
+    pub specific_impulse: f64,
+    /// Fraction of full thrust, from 0 to 1.
+    pub throttle: f64,
+    pub attitude: Attitude,
+    /// Mass in kg with the tanks empty.
+    pub dry_mass: f64,
+}
+
+impl Engine {
+    /// Propellant used per second at the current throttle, in kg/s.
+    pub fn mass_flow(&self) -> f64 {
+        self.thrust * self.throttle / (self.specific_impulse * STANDARD_GRAVITY)
+    }
+
+    /// Propellant left in kg for a craft weighing `mass`.
+    pub fn propellant(&self, mass: f64) -> f64 {
+        (mass - self.dry_mass).max(0.)
+    }
+
+    /// Whether the engine is pushing at all.
+    pub fn firing(&self, mass: f64) -> bool {
+        self.throttle > 0. && self.propellant(mass) > 0.
+    }
+
+    /// Delta-v in m/s left in the tanks of a craft weighing `mass`, from the rocket equation.
+    pub fn delta_v(&self, mass: f64) -> f64 {
+        if mass <= self.dry_mass {
+            return 0.;
+        }
+        self.specific_impulse * STANDARD_GRAVITY * (mass / self.dry_mass).ln()
+    }
+
+    /// Unit thrust direction for a craft at `position` moving at `velocity`, both relative to its
+    /// primary.
+    pub fn direction(&self, position: DVec3, velocity: DVec3) -> DVec3 {
+        let (prograde, normal, _) = frame(position, velocity);
+        match self.attitude {
+            Attitude::Prograde => prograde,
+            Attitude::Retrograde => -prograde,
+            Attitude::Normal => normal,
+            Attitude::InertialFixed(direction) => direction.normalize_or_zero(),
+        }
+    }
+
+    /// Thrust acceleration of a craft weighing `mass`, pointed along `direction`.
+    pub fn acceleration(&self, mass: f64, direction: DVec3) -> DVec3 {
+        if !self.firing(mass) {
+            return DVec3::ZERO;
+        }
+        direction * self.thrust * self.throttle / mass
+    }
+}
@@ -0,0 +1,379 @@
+use std::fmt;
+
+use bevy::math::{DVec2, DVec3};
+
+use crate::{
+    maneuver::{advance_through, ManeuverNode},
+    orbit::{kepler_propagate, spheres_of_influence, REFERENCE_POLE},
+    prediction::patched_conic_step,
+    simulation::{BodyState, GRAVITATIONAL_CONSTANT},
+    targeting::{correct, Control, Outcome},
+};
+
+/// Steps the bodies are laid out over for the encounter search.
+const SEARCH_STEPS: usize = 2000;
+
+/// Halvings spent pinning down when the craft crosses a sphere of influence.
+const BISECTION_ITERATIONS: usize = 40;
+
+/// Miss in meters on the B-plane that counts as on target.
+const TOLERANCE: f64 = 1_000.;
+
+/// Hyperbolic pass of a body by a planet.
+#[derive(Clone, Copy, Debug)]
+pub struct Flyby {
+    /// Hyperbolic excess velocity relative to the planet on the way in.
+    pub v_infinity_in: DVec3,
+    /// And on the way out, turned by the planet.
+    pub v_infinity_out: DVec3,
+    /// Radians the velocity relative to the planet is turned through.
+    pub turning_angle: f64,
+    /// Closest approach to the planet's centre in meters.
+    pub periapsis: f64,
+    /// Components of the miss vector on the B-plane in meters. T lies in the reference plane,
+    /// R completes the frame pointing down.
+    pub b_t: f64,
+    pub b_r: f64,
+}
+
+impl Flyby {
+    /// Flyby of a body at `position` moving at `velocity` relative to a planet with gravitational
+    /// parameter `mu`. `None` unless the body is on an escape trajectory.
+    pub fn from_state(position: DVec3, velocity: DVec3, mu: f64) -> Option<Flyby> {
+        let energy = velocity.length_squared() / 2. - mu / position.length();
+        let momentum = position.cross(velocity);
+        if energy <= 0. || momentum.length_squared() == 0. {
+            return None;
+        }
+        let v_infinity = (2. * energy).sqrt();
+        let eccentricity_vector = ((velocity.length_squared() - mu / position.length()) * position
+            - position.dot(velocity) * velocity)
+            / mu;
+        let eccentricity = eccentricity_vector.length();
+
+        let periapsis_direction = eccentricity_vector / eccentricity;
+        let normal = momentum.normalize();
+        let sideways = normal.cross(periapsis_direction);
+        let along = (1. - 1. / (eccentricity * eccentricity)).sqrt();
+        let incoming = periapsis_direction / eccentricity + sideways * along;
+        let outgoing = -periapsis_direction / eccentricity + sideways * along;
+
+        // the asymptote misses the planet along incoming × normal, by h / v∞
+        let b = incoming.cross(normal) * momentum.length() / v_infinity;
+        let t = incoming.cross(REFERENCE_POLE).normalize_or_zero();
+        let r = incoming.cross(t);
+
+        Some(Flyby {
+            v_infinity_in: incoming * v_infinity,
+            v_infinity_out: outgoing * v_infinity,
+            turning_angle: 2. * (1. / eccentricity).asin(),
+            periapsis: mu / (v_infinity * v_infinity) * (eccentricity - 1.),
+            b_t: b.dot(t),
+            b_r: b.dot(r),
+        })
+    }
+
+    /// Change in velocity the planet gives the body, the same in any frame the planet moves
+    /// steadily through.
+    pub fn delta_v(&self) -> DVec3 {
+        self.v_infinity_out - self.v_infinity_in
+    }
+
+    /// Change in speed relative to the planet's own primary, for a planet moving at
+    /// `planet_velocity` relative to it.
+    pub fn speed_change(&self, planet_velocity: DVec3) -> f64 {
+        (planet_velocity + self.v_infinity_out).length()
+            - (planet_velocity + self.v_infinity_in).length()
+    }
+
+    fn b_plane(&self) -> DVec2 {
+        DVec2::new(self.b_t, self.b_r)
+    }
+}
+
+/// Where a craft crosses into a planet's sphere of influence, and the flyby that follows.
+#[derive(Clone, Copy, Debug)]
+pub struct Encounter {
+    /// Simulated seconds from the burn.
+    pub time: f64,
+    pub flyby: Flyby,
+    /// The planet's velocity relative to its own primary.
+    pub planet_velocity: DVec3,
+    /// Radius of the planet in meters, to turn the periapsis into an altitude.
+    pub planet_radius: f64,
+}
+
+#[derive(Debug)]
+pub enum FlybyError {
+    /// The craft never reaches the planet's sphere of influence, or gets captured on arrival.
+    Missed,
+    /// Ran out of iterations this far off target, in meters.
+    NotConverged(f64),
+}
+
+impl fmt::Display for FlybyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlybyError::Missed => write!(f, "no flyby of the planet"),
+            FlybyError::NotConverged(miss) => {
+                write!(f, "gave up {:.1} km off target", miss / 1000.)
+            }
+        }
+    }
+}
+
+/// Every body's patched-conic path from a burn onwards, laid out once so a craft too light to
+/// pull on anything can be flown through it over and over with different burns.
+pub struct FlybySearch {
+    /// Seconds between laid out states.
+    timestep: f64,
+    /// Position and velocity of every body at every step.
+    states: Vec<Vec<(DVec3, DVec3)>>,
+    /// Body each body orbits at the burn, and the radius of its own sphere of influence.
+    influence: Vec<(Option<usize>, f64)>,
+    mu: Vec<f64>,
+    radius: Vec<f64>,
+    fixed: Vec<bool>,
+    craft: usize,
+    planet: usize,
+}
+
+impl FlybySearch {
+    /// Lays out `bodies` from `start` up to the burn of `node`, flying every node due before it
+    /// on the way, and from there on for `horizon` seconds. Later nodes are left out.
+    pub fn new(
+        mut bodies: Vec<BodyState>,
+        craft: usize,
+        planet: usize,
+        node: &ManeuverNode,
+        nodes: &[ManeuverNode],
+        start: f64,
+        horizon: f64,
+    ) -> FlybySearch {
+        let earlier: Vec<ManeuverNode> = nodes
+            .iter()
+            .filter(|other| other.time < node.time && *other != node)
+            .cloned()
+            .collect();
+        advance_through(
+            &mut bodies,
+            &earlier,
+            start,
+            (node.time - start).max(0.),
+            patched_conic_step,
+        );
+
+        let timestep = horizon / SEARCH_STEPS as f64;
+        let snapshot = |bodies: &[BodyState]| {
+            bodies
+                .iter()
+                .map(|body| (body.translation.to_dvec3(), body.velocity.to_dvec3()))
+                .collect::<Vec<_>>()
+        };
+        let influence = spheres_of_influence(&bodies);
+        let mu = bodies
+            .iter()
+            .map(|body| (GRAVITATIONAL_CONSTANT * body.mass.clone()).to_f64())
+            .collect();
+        let radius = bodies.iter().map(|body| body.radius).collect();
+        let fixed = bodies.iter().map(|body| body.fixed).collect();
+
+        let mut states = Vec::with_capacity(SEARCH_STEPS + 1);
+        states.push(snapshot(&bodies));
+        for _ in 0..SEARCH_STEPS {
+            patched_conic_step(&mut bodies, timestep);
+            states.push(snapshot(&bodies));
+        }
+
+        FlybySearch {
+            timestep,
+            states,
+            influence,
+            mu,
+            radius,
+            fixed,
+            craft,
+            planet,
+        }
+    }
+
+    /// State of `body` `elapsed` seconds into `step`, moved along its orbit around its primary.
+    fn state_at(&self, step: usize, body: usize, elapsed: f64) -> (DVec3, DVec3) {
+        if elapsed >= self.timestep {
+            return self.states[step + 1][body];
+        }
+        let (position, velocity) = self.states[step][body];
+        if elapsed == 0. || self.fixed[body] {
+            return (position, velocity);
+        }
+        match self.influence[body].0 {
+            Some(primary) => {
+                let (primary_position, primary_velocity) = self.states[step][primary];
+                let (relative_position, relative_velocity) = kepler_propagate(
+                    position - primary_position,
+                    velocity - primary_velocity,
+                    self.mu[primary],
+                    elapsed,
+                );
+                let (primary_position, primary_velocity) = self.state_at(step, primary, elapsed);
+                (
+                    primary_position + relative_position,
+                    primary_velocity + relative_velocity,
+                )
+            }
+            None => (position + velocity * elapsed, velocity),
+        }
+    }
+
+    /// Body whose sphere of influence a craft at `position` is in, `elapsed` seconds into `step`.
+    fn primary_of(&self, step: usize, elapsed: f64, position: DVec3) -> Option<usize> {
+        (0..self.states[step].len())
+            .filter(|&body| body != self.craft)
+            .filter(|&body| {
+                let (body_position, _) = self.state_at(step, body, elapsed);
+                position.distance(body_position) < self.influence[body].1
+            })
+            .min_by(|a, b| self.influence[*a].1.total_cmp(&self.influence[*b].1))
+    }
+
+    /// Moves the craft on by `duration` from `elapsed` seconds into `step`, around `primary`.
+    fn coast(
+        &self,
+        step: usize,
+        elapsed: f64,
+        duration: f64,
+        primary: Option<usize>,
+        position: DVec3,
+        velocity: DVec3,
+    ) -> (DVec3, DVec3) {
+        match primary {
+            Some(primary) => {
+                let (primary_position, primary_velocity) = self.state_at(step, primary, elapsed);
+                let (relative_position, relative_velocity) = kepler_propagate(
+                    position - primary_position,
+                    velocity - primary_velocity,
+                    self.mu[primary],
+                    duration,
+                );
+                let (primary_position, primary_velocity) =
+                    self.state_at(step, primary, elapsed + duration);
+                (
+                    primary_position + relative_position,
+                    primary_velocity + relative_velocity,
+                )
+            }
+            None => (position + velocity * duration, velocity),
+        }
+    }
+
+    /// Flies the craft through the laid out bodies after burning `node`, up to where it enters
+    /// the planet's sphere of influence.
+    pub fn encounter(&self, node: &ManeuverNode) -> Option<Encounter> {
+        let (mut position, mut velocity) = self.states[0][self.craft];
+        let primary = self.primary_of(0, 0., position);
+        let (primary_position, primary_velocity) = match primary {
+            Some(primary) => self.states[0][primary],
+            None => (DVec3::ZERO, DVec3::ZERO),
+        };
+        velocity += node.delta_v_vector(position - primary_position, velocity - primary_velocity);
+
+        for step in 0..SEARCH_STEPS {
+            let mut elapsed = 0.;
+            while elapsed < self.timestep {
+                let primary = self.primary_of(step, elapsed, position);
+                if primary == Some(self.planet) {
+                    return self.arrive(step, elapsed, position, velocity);
+                }
+
+                let remaining = self.timestep - elapsed;
+                let (end_position, end_velocity) =
+                    self.coast(step, elapsed, remaining, primary, position, velocity);
+                if self.primary_of(step, self.timestep, end_position) == primary {
+                    position = end_position;
+                    velocity = end_velocity;
+                    break;
+                }
+
+                // crossed a sphere of influence on the way, find where
+                let (mut inside, mut outside) = (0., remaining);
+                for _ in 0..BISECTION_ITERATIONS {
+                    let middle = (inside + outside) / 2.;
+                    let (middle_position, _) =
+                        self.coast(step, elapsed, middle, primary, position, velocity);
+                    if self.primary_of(step, elapsed + middle, middle_position) == primary {
+                        inside = middle;
+                    } else {
+                        outside = middle;
+                    }
+                }
+                (position, velocity) =
+                    self.coast(step, elapsed, outside, primary, position, velocity);
+                elapsed += outside;
+            }
+        }
+        None
+    }
+
+    fn arrive(
+        &self,
+        step: usize,
+        elapsed: f64,
+        position: DVec3,
+        velocity: DVec3,
+    ) -> Option<Encounter> {
+        let (planet_position, planet_velocity) = self.state_at(step, self.planet, elapsed);
+        let flyby = Flyby::from_state(
+            position - planet_position,
+            velocity - planet_velocity,
+            self.mu[self.planet],
+        )?;
+        let parent_velocity = match self.influence[self.planet].0 {
+            Some(parent) => self.state_at(step, parent, elapsed).1,
+            None => DVec3::ZERO,
+        };
+
+        Some(Encounter {
+            time: step as f64 * self.timestep + elapsed,
+            flyby,
+            planet_velocity: planet_velocity - parent_velocity,
+            planet_radius: self.radius[self.planet],
+        })
+    }
+
+    /// Adjusts the burn of `node` so the flyby passes through `b_t` and `b_r` on the B-plane,
+    /// taking the smallest change in delta-v that fixes the miss. Returns the corrected node, its
+    /// encounter and the iterations used.
+    pub fn target(
+        &self,
+        node: &ManeuverNode,
+        b_t: f64,
+        b_r: f64,
+    ) -> Result<(ManeuverNode, Encounter, usize), FlybyError> {
+        let goal = DVec2::new(b_t, b_r);
+        let correction = correct(
+            node,
+            &[Control::Prograde, Control::Normal, Control::Radial],
+            &[TOLERANCE; 2],
+            |node| {
+                let miss = self.encounter(node)?.flyby.b_plane() - goal;
+                Some(vec![miss.x, miss.y])
+            },
+        );
+
+        match correction.outcome {
+            Outcome::Converged => {
+                let encounter = self.encounter(&correction.node).ok_or(FlybyError::Missed)?;
+                let iterations = correction.history.len() - 1;
+                Ok((correction.node, encounter, iterations))
+            }
+            Outcome::Unreachable => Err(FlybyError::Missed),
+            _ => {
+                let miss = correction
+                    .history
+                    .last()
+                    .map_or(f64::INFINITY, |miss| DVec2::new(miss[0], miss[1]).length());
+                Err(FlybyError::NotConverged(miss))
+            }
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use bevy::{
+    math::{DMat3, DQuat, DVec3},
+    prelude::*,
+};
+
+/// Highest degree a field can be evaluated to before the unnormalised recursion overflows.
+pub const MAX_DEGREE: usize = 120;
+
+/// Largest degree or order read from a file, far beyond any model but safe to index with.
+const MAX_INDEX: f64 = 1e6;
+
+/// Spherical harmonic expansion of a body's gravity field, on top of its point mass.
+///
+/// The body fixed frame follows the body's `Transform` rotation: the pole is its local Y axis
+/// and the prime meridian its local X axis.
+#[derive(Component, Clone, Debug)]
+pub struct GravityField {
+    /// Radius the coefficients are normalised to, in meters.
+    pub reference_radius: f64,
+    /// Degree the expansion is truncated at when evaluated, up to the degree loaded. Each body's
+    /// field can be cut down or raised again while the simulation runs.
+    pub degree: usize,
+    loaded_degree: usize,
+    /// Unnormalised cosine coefficients, indexed by `coefficient_index`.
+    c: Vec<f64>,
+    /// Unnormalised sine coefficients, indexed by `coefficient_index`.
+    s: Vec<f64>,
+}
+
+/// Why a gravity model couldn't be loaded.
+#[derive(Debug)]
+pub enum GravityModelError {
+    Io(io::Error),
+    MissingHeader(&'static str),
+    Parse { line: usize, message: String },
+    DegreeTooHigh(usize),
+}
+
+impl fmt::Display for GravityModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GravityModelError::Io(error) => write!(f, "couldn't read gravity model: {}", error),
+            GravityModelError::MissingHeader(key) => {
+                write!(f, "gravity model header has no {}", key)
+            }
+            GravityModelError::Parse { line, message } => {
+                write!(f, "gravity model line {}: {}", line, message)
+            }
+            GravityModelError::DegreeTooHigh(degree) => {
+                write!(f, "degree {} is above the supported {}", degree, MAX_DEGREE)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for GravityModelError {
+    fn from(error: io::Error) -> Self {
+        GravityModelError::Io(error)
+    }
+}
+
+/// Position of degree `n`, order `m` in the coefficient lists.
+fn coefficient_index(n: usize, m: usize) -> usize {
+    n * (n + 1) / 2 + m
+}
+
+/// Factor turning a fully normalised coefficient into an unnormalised one.
+fn normalization(n: usize, m: usize) -> f64 {
+    // (n - m)! / (n + m)! is taken through logs, it leaves f64 range long before MAX_DEGREE
+    let log_factorials: f64 = ((n - m + 1)..=(n + m)).map(|k| (k as f64).ln()).sum();
+    let kronecker = if m == 0 { 1. } else { 2. };
+    (0.5 * ((kronecker * (2 * n + 1) as f64).ln() - log_factorials)).exp()
+}
+
+impl GravityField {
+    /// Field with only zonal harmonics, given as unnormalised J2, J3, ... coefficients.
+    pub fn zonal(reference_radius: f64, zonal: &[f64]) -> GravityField {
+        let mut field = GravityField::empty(reference_radius, zonal.len() + 1);
+        for (index, coefficient) in zonal.iter().enumerate() {
+            field.c[coefficient_index(index + 2, 0)] = -coefficient;
+        }
+        field
+    }
+
+    fn empty(reference_radius: f64, degree: usize) -> GravityField {
+        let count = coefficient_index(degree, degree) + 1;
+        GravityField {
+            reference_radius,
+            degree,
+            loaded_degree: degree,
+            c: vec![0.; count],
+            s: vec![0.; count],
+        }
+    }
+
+    /// Highest degree `degree` can be raised to.
+    pub fn loaded_degree(&self) -> usize {
+        self.loaded_degree
+    }
+
+    /// Loads an ICGEM `.gfc` coefficient file, keeping terms up to `degree`.
+    ///
+    /// Time variable terms are left at their reference epoch. The file's own gravitational
+    /// constant is ignored in favour of the simulated body's mass.
+    pub fn load(path: impl AsRef<Path>, degree: usize) -> Result<GravityField, GravityModelError> {
+        GravityField::parse(BufReader::new(File::open(path)?), degree)
+    }
+
+    /// Reads ICGEM coefficients from `reader`, keeping terms up to `degree`.
+    fn parse(reader: impl BufRead, degree: usize) -> Result<GravityField, GravityModelError> {
+        if degree > MAX_DEGREE {
+            return Err(GravityModelError::DegreeTooHigh(degree));
+        }
+
+        let mut lines = reader.lines().enumerate();
+        let mut radius = None;
+        let mut max_degree = None;
+        let mut normalized = true;
+
+        for (index, line) in lines.by_ref() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("end_of_head") => break,
+                Some("radius") => radius = Some(parse_number(words.next(), index)?),
+                Some("max_degree") => max_degree = Some(parse_index(words.next(), index)?),
+                Some("norm") => normalized = words.next() != Some("unnormalized"),
+                _ => {}
+            }
+        }
+
+        let radius = radius.ok_or(GravityModelError::MissingHeader("radius"))?;
+        let degree = degree.min(max_degree.unwrap_or(degree));
+        let mut field = GravityField::empty(radius, degree);
+
+        for (index, line) in lines {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            // trend and periodic terms of time variable models are skipped
+            if !matches!(words.next(), Some("gfc") | Some("gfct")) {
+                continue;
+            }
+
+            let n = parse_index(words.next(), index)?;
+            let m = parse_index(words.next(), index)?;
+            let c = parse_number(words.next(), index)?;
+            let s = parse_number(words.next(), index)?;
+            if m > n {
+                return Err(GravityModelError::Parse {
+                    line: index + 1,
+                    message: format!("order {} is above degree {}", m, n),
+                });
+            }
+            if n > degree {
+                continue;
+            }
+
+            let scale = if normalized { normalization(n, m) } else { 1. };
+            field.c[coefficient_index(n, m)] = c * scale;
+            field.s[coefficient_index(n, m)] = s * scale;
+        }
+
+        Ok(field)
+    }
+
+    /// Acceleration on top of the point mass pull, felt at `offset` from the body's center.
+    /// `mu` is the gravitational parameter of the body alone.
+    pub fn acceleration(&self, mu: f64, offset: DVec3, orientation: DQuat) -> DVec3 {
+        let degree = self.degree.min(self.loaded_degree);
+        let local = orientation.inverse() * offset;
+        // body fixed axes, z up the pole and x through the prime meridian
+        let (x, y, z) = (local.x, -local.z, local.y);
+        let radius_squared = x * x + y * y + z * z;
+        if degree < 2 || radius_squared <= 0. {
+            return DVec3::ZERO;
+        }
+
+        // Cunningham's V and W terms, as laid out by Montenbruck and Gill
+        let size = degree + 2;
+        let at = |n: usize, m: usize| n * size + m;
+        let reference = self.reference_radius;
+        let (x0, y0, z0) = (
+            reference * x / radius_squared,
+            reference * y / radius_squared,
+            reference * z / radius_squared,
+        );
+        let rho = reference * reference / radius_squared;
+
+        let mut v = vec![0.; size * size];
+        let mut w = vec![0.; size * size];
+        v[0] = reference / radius_squared.sqrt();
+        for m in 0..size {
+            if m > 0 {
+                let k = (2 * m - 1) as f64;
+                let (v_previous, w_previous) = (v[at(m - 1, m - 1)], w[at(m - 1, m - 1)]);
+                v[at(m, m)] = k * (x0 * v_previous - y0 * w_previous);
+                w[at(m, m)] = k * (x0 * w_previous + y0 * v_previous);
+            }
+            if m + 1 < size {
+                let k = (2 * m + 1) as f64;
+                v[at(m + 1, m)] = k * z0 * v[at(m, m)];
+                w[at(m + 1, m)] = k * z0 * w[at(m, m)];
+            }
+            for n in (m + 2)..size {
+                let a = (2 * n - 1) as f64 / (n - m) as f64;
+                let b = (n + m - 1) as f64 / (n - m) as f64;
+                v[at(n, m)] = a * z0 * v[at(n - 1, m)] - b * rho * v[at(n - 2, m)];
+                w[at(n, m)] = a * z0 * w[at(n - 1, m)] - b * rho * w[at(n - 2, m)];
+            }
+        }
+
+        let mut acceleration = DVec3::ZERO;
+        for n in 2..=degree {
+            for m in 0..=n {
+                let c = self.c[coefficient_index(n, m)];
+                let s = self.s[coefficient_index(n, m)];
+                if m == 0 {
+                    acceleration.x -= c * v[at(n + 1, 1)];
+                    acceleration.y -= c * w[at(n + 1, 1)];
+                    acceleration.z -= (n + 1) as f64 * c * v[at(n + 1, 0)];
+                } else {
+                    let factor = ((n - m + 1) * (n - m + 2)) as f64;
+                    let (v_up, w_up) = (v[at(n + 1, m + 1)], w[at(n + 1, m + 1)]);
+                    let (v_down, w_down) = (v[at(n + 1, m - 1)], w[at(n + 1, m - 1)]);
+                    acceleration.x +=
+                        0.5 * (-c * v_up - s * w_up + factor * (c * v_down + s * w_down));
+                    acceleration.y +=
+                        0.5 * (-c * w_up + s * v_up + factor * (-c * w_down + s * v_down));
+                    acceleration.z +=
+                        (n - m + 1) as f64 * (-c * v[at(n + 1, m)] - s * w[at(n + 1, m)]);
+                }
+            }
+        }
+
+        let acceleration = acceleration * (mu / (reference * reference));
+        orientation * DVec3::new(acceleration.x, acceleration.z, -acceleration.y)
+    }
+
+    /// How `acceleration` changes with `offset`, ∂a/∂r, by central differences.
+    pub fn gradient(&self, mu: f64, offset: DVec3, orientation: DQuat) -> DMat3 {
+        // small against the distance, large enough to stay clear of rounding
+        let nudge = offset.length() * 1e-6;
+        if nudge <= 0. {
+            return DMat3::ZERO;
+        }
+        let column = |axis: DVec3| {
+            let ahead = self.acceleration(mu, offset + axis * nudge, orientation);
+            let behind = self.acceleration(mu, offset - axis * nudge, orientation);
+            (ahead - behind) / (2. * nudge)
+        };
+        DMat3::from_cols(column(DVec3::X), column(DVec3::Y), column(DVec3::Z))
+    }
+}
+
+/// Reads a number from a coefficient file, which may use Fortran style `D` exponents.
+fn parse_number(word: Option<&str>, index: usize) -> Result<f64, GravityModelError> {
+    let word = word.ok_or_else(|| GravityModelError::Parse {
+        line: index + 1,
+        message: "missing value".to_string(),
+    })?;
+    word.replace(['D', 'd'], "E")
+        .parse()
+        .map_err(|_| GravityModelError::Parse {
+            line: index + 1,
+            message: format!("{} is not a number", word),
+        })
+}
+
+/// Reads a degree or order, which has to be a whole number of at least zero.
+fn parse_index(word: Option<&str>, index: usize) -> Result<usize, GravityModelError> {
+    let number = parse_number(word, index)?;
+    if number < 0. || number.fract() != 0. || number > MAX_INDEX {
+        return Err(GravityModelError::Parse {
+            line: index + 1,
+            message: format!("{} is not a degree or order", number),
+        });
+    }
+    Ok(number as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EARTH_MU: f64 = 3.986004418e14;
+    const EARTH_RADIUS: f64 = 6_378_136.3;
+    const J2: f64 = 1.08262668e-3;
+    /// J2 of `MODEL`, its fully normalised C20 times √5.
+    const MODEL_J2: f64 = 0.484165143790815e-3 * 2.23606797749979;
+
+    const MODEL: &str = "\
+begin_of_head ==========================
+product_type     gravity_field
+modelname        test
+earth_gravity_constant 0.3986004415E+15
+radius           0.63781363E+07
+max_degree       3
+norm             fully_normalized
+key   L    M         C                  S
+end_of_head ============================
+gfc   0    0  1.000000000000D+00  0.000000000000D+00
+gfc   2    0 -0.484165143790815D-03  0.000000000000D+00
+gfct  2    2  0.243938357328313D-05 -0.140027370385934D-05  20050101.0000
+trnd  2    2  0.100000000000000D-10  0.000000000000D+00
+gfc   3    0  0.957161207093473D-06  0.000000000000D+00
+gfc   4    0  0.539965866638991D-06  0.000000000000D+00
+";
+
+    fn assert_close(actual: f64, expected: f64) {
+        let tolerance = 1e-9 * expected.abs().max(f64::MIN_POSITIVE);
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn parses_header_and_coefficients() {
+        let field = GravityField::parse(MODEL.as_bytes(), 10).unwrap();
+
+        assert_eq!(field.reference_radius, EARTH_RADIUS);
+        // max_degree in the header caps the degree asked for
+        assert_eq!(field.loaded_degree(), 3);
+        assert_eq!(field.degree, 3);
+        assert_close(field.c[coefficient_index(2, 0)], -MODEL_J2);
+        assert_close(
+            field.c[coefficient_index(2, 2)],
+            0.243938357328313e-5 * normalization(2, 2),
+        );
+        assert_close(
+            field.s[coefficient_index(2, 2)],
+            -0.140027370385934e-5 * normalization(2, 2),
+        );
+        assert_close(
+            field.c[coefficient_index(3, 0)],
+            0.957161207093473e-6 * 7f64.sqrt(),
+        );
+    }
+
+    #[test]
+    fn truncates_to_degree() {
+        let field = GravityField::parse(MODEL.as_bytes(), 2).unwrap();
+
+        assert_eq!(field.loaded_degree(), 2);
+        assert_eq!(field.c.len(), coefficient_index(2, 2) + 1);
+        assert_close(field.c[coefficient_index(2, 0)], -MODEL_J2);
+    }
+
+    #[test]
+    fn rejects_bad_models() {
+        let headless = MODEL.replace("radius", "reference");
+        assert!(matches!(
+            GravityField::parse(headless.as_bytes(), 3),
+            Err(GravityModelError::MissingHeader("radius"))
+        ));
+
+        let bad_order = MODEL.replace("gfc   3    0", "gfc   3    4");
+        assert!(matches!(
+            GravityField::parse(bad_order.as_bytes(), 3),
+            Err(GravityModelError::Parse { line: 14, .. })
+        ));
+
+        assert!(matches!(
+            GravityField::parse(MODEL.as_bytes(), MAX_DEGREE + 1),
+            Err(GravityModelError::DegreeTooHigh(_))
+        ));
+
+        for corrupt in [
+            "gfc   -3    0",
+            "gfc   3.5    0",
+            "gfc   NaN    0",
+            "gfc   3    -1",
+        ] {
+            let corrupt = MODEL.replace("gfc   3    0", corrupt);
+            assert!(matches!(
+                GravityField::parse(corrupt.as_bytes(), 3),
+                Err(GravityModelError::Parse { line: 14, .. })
+            ));
+        }
+        let corrupt = MODEL.replace("max_degree       3", "max_degree       -3");
+        assert!(matches!(
+            GravityField::parse(corrupt.as_bytes(), 3),
+            Err(GravityModelError::Parse { line: 6, .. })
+        ));
+    }
+
+    /// Closed form J2 acceleration around a pole along `orientation * Y`.
+    fn j2_acceleration(offset: DVec3, orientation: DQuat) -> DVec3 {
+        let radius = offset.length();
+        let direction = offset / radius;
+        let pole = orientation * DVec3::Y;
+        let sine = direction.dot(pole);
+        -1.5 * J2 * EARTH_MU * EARTH_RADIUS.powi(2) / radius.powi(4)
+            * ((1. - 5. * sine * sine) * direction + 2. * sine * pole)
+    }
+
+    #[test]
+    fn gradient_is_symmetric_and_traceless() {
+        let field = GravityField::zonal(EARTH_RADIUS, &[J2, -2.5326565e-6, -1.6196216e-6]);
+        let orientation = DQuat::from_rotation_z(0.4101524);
+        let offset = DVec3::new(-3_000_000., 4_500_000., 5_200_000.);
+        let gradient = field.gradient(EARTH_MU, offset, orientation);
+
+        // outside the body the potential is harmonic, so ∇·a = 0
+        let scale = gradient
+            .to_cols_array()
+            .iter()
+            .fold(0., |a: f64, b| a.max(b.abs()));
+        let trace = gradient.x_axis.x + gradient.y_axis.y + gradient.z_axis.z;
+        assert!(trace.abs() < 1e-6 * scale);
+        assert!((gradient - gradient.transpose())
+            .to_cols_array()
+            .iter()
+            .all(|entry| entry.abs() < 1e-6 * scale));
+
+        // and it predicts how the acceleration changes over a short move
+        let step = DVec3::new(20., -35., 10.);
+        let change = field.acceleration(EARTH_MU, offset + step, orientation)
+            - field.acceleration(EARTH_MU, offset, orientation);
+        assert!((gradient * step - change).length() < 1e-4 * change.length());
+    }
+
+    #[test]
+    fn zonal_field_matches_closed_form_j2() {
+        // J3 is only there to check that it is cut off by the degree
+        let mut field = GravityField::zonal(EARTH_RADIUS, &[J2, -2.5326565e-6]);
+        field.degree = 2;
+
+        let orientations = [
+            DQuat::IDENTITY,
+            DQuat::from_rotation_z(0.4101524),
+            DQuat::from_euler(EulerRot::YXZ, 1.2, -0.7, 0.3),
+        ];
+        let offsets = [
+            DVec3::new(7_000_000., 0., 0.),
+            DVec3::new(0., 7_000_000., 0.),
+            DVec3::new(-3_000_000., 4_500_000., 5_200_000.),
+            DVec3::new(12_000_000., -20_000_000., 31_000_000.),
+        ];
+        for orientation in orientations {
+            for offset in offsets {
+                let expected = j2_acceleration(offset, orientation);
+                let actual = field.acceleration(EARTH_MU, offset, orientation);
+                assert!(
+                    (actual - expected).length() <= 1e-9 * expected.length(),
+                    "{} is not {} at {}",
+                    actual,
+                    expected,
+                    offset
+                );
+            }
+        }
+    }
+}
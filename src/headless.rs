@@ -0,0 +1,85 @@
+use std::str::FromStr;
+
+use bevy::{ecs::system::SystemState, prelude::*};
+
+use crate::{
+    simulation::{
+        body_states, BodyState, ForceModels, PhysicalProperties, ReferenceFrame, Simulated,
+    },
+    ui::RenderInUI,
+};
+
+/// Command line arguments of a command run without a window, after its flag.
+pub struct Args(std::vec::IntoIter<String>);
+
+impl Args {
+    /// The arguments after the program name other than `flag`, or `None` without `flag`.
+    pub fn find(args: &[String], flag: &str) -> Option<Args> {
+        if !args.iter().any(|arg| arg == flag) {
+            return None;
+        }
+        let rest: Vec<String> = args.iter().filter(|arg| *arg != flag).cloned().collect();
+        Some(Args(rest.into_iter()))
+    }
+
+    /// Reads the value following `arg`.
+    pub fn value<T: FromStr>(&mut self, arg: &str) -> Result<T, String> {
+        let value = self
+            .0
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        parse(arg, &value)
+    }
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.0.next()
+    }
+}
+
+/// Reads `value` given to `arg`, describing the problem if it can't.
+pub fn parse<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} can't take {}", arg, value))
+}
+
+/// The simulated bodies the startup systems spawned into a world, detached from it.
+pub struct Snapshot {
+    /// Bodies with their force models attached.
+    pub bodies: Vec<BodyState>,
+    /// Name shown for each body.
+    pub names: Vec<String>,
+}
+
+impl Snapshot {
+    pub fn from_world(world: &mut World) -> Snapshot {
+        let mut state: SystemState<(
+            Query<(Entity, &PhysicalProperties), With<Simulated>>,
+            Query<Entity, With<ReferenceFrame>>,
+            Query<&RenderInUI>,
+            ForceModels,
+        )> = SystemState::new(world);
+        let (sim_query, ref_query, name_query, force_models) = state.get_mut(world);
+
+        let mut bodies = body_states(sim_query.iter(), ref_query.get_single().ok());
+        force_models.attach(&mut bodies);
+        let names = bodies
+            .iter()
+            .map(|body| {
+                name_query
+                    .get(body.entity)
+                    .map_or_else(|_| "?".to_string(), |n| n.0.clone())
+            })
+            .collect();
+        Snapshot { bodies, names }
+    }
+
+    /// Index of the body called `name`.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|other| other == name)
+    }
+}
@@ -0,0 +1,364 @@
+use std::f64::consts::PI;
+
+use bevy::math::DVec3;
+
+use crate::orbit::REFERENCE_POLE;
+
+/// Iterations allowed for each root find.
+const MAX_ITERATIONS: usize = 35;
+
+/// Change in the Lancaster–Blanchard variable at which a root find has converged.
+const TOLERANCE: f64 = 1e-8;
+
+/// One way of getting between two points in a given time.
+#[derive(Clone, Copy, Debug)]
+pub struct LambertSolution {
+    /// Full turns around the primary on the way.
+    pub revolutions: u32,
+    /// Velocity needed at the start, relative to the primary.
+    pub departure_velocity: DVec3,
+    /// Velocity on arrival, relative to the primary.
+    pub arrival_velocity: DVec3,
+}
+
+/// Prograde transfers from `from` to `to` taking `time_of_flight` seconds around a primary with
+/// gravitational parameter `mu`, with up to `max_revolutions` full turns on the way. Every
+/// multi-revolution count that fits has a short and a long period solution.
+///
+/// Uses Izzo's method, "Revisiting Lambert's problem" (2015), with Householder iterations on
+/// the Lancaster–Blanchard variable.
+pub fn lambert(
+    mu: f64,
+    from: DVec3,
+    to: DVec3,
+    time_of_flight: f64,
+    max_revolutions: u32,
+) -> Vec<LambertSolution> {
+    let chord = (to - from).length();
+    let (from_radius, to_radius) = (from.length(), to.length());
+    if chord <= 0. || time_of_flight <= 0. || from_radius <= 0. || to_radius <= 0. {
+        return Vec::new();
+    }
+    let semi_perimeter = (from_radius + to_radius + chord) / 2.;
+
+    let (from_direction, to_direction) = (from / from_radius, to / to_radius);
+    let mut normal = from_direction.cross(to_direction).normalize_or_zero();
+    if normal == DVec3::ZERO {
+        // straight across or straight on, the plane isn't defined
+        return Vec::new();
+    }
+    let mut lambda = (1. - (chord / semi_perimeter).min(1.)).sqrt();
+    // transfers go the same way round as the bodies, the long way if need be
+    if normal.dot(REFERENCE_POLE) < 0. {
+        lambda = -lambda;
+        normal = -normal;
+    }
+    let from_tangent = normal.cross(from_direction);
+    let to_tangent = normal.cross(to_direction);
+
+    let time = (2. * mu / semi_perimeter.powi(3)).sqrt() * time_of_flight;
+    let revolutions = max_revolutions.min(feasible_revolutions(time, lambda));
+
+    let gamma = (mu * semi_perimeter / 2.).sqrt();
+    let rho = (from_radius - to_radius) / chord;
+    let sigma = (1. - rho * rho).sqrt();
+
+    let mut solutions = Vec::new();
+    for revolution in 0..=revolutions {
+        let paths: &[bool] = if revolution == 0 {
+            &[true]
+        } else {
+            &[true, false]
+        };
+        for &low_path in paths {
+            let x = match householder(
+                initial_guess(time, lambda, revolution, low_path),
+                time,
+                lambda,
+                revolution,
+            ) {
+                Some(x) => x,
+                None => continue,
+            };
+            let y = compute_y(x, lambda);
+
+            let from_radial = gamma * ((lambda * y - x) - rho * (lambda * y + x)) / from_radius;
+            let to_radial = -gamma * ((lambda * y - x) + rho * (lambda * y + x)) / to_radius;
+            let from_transverse = gamma * sigma * (y + lambda * x) / from_radius;
+            let to_transverse = gamma * sigma * (y + lambda * x) / to_radius;
+
+            solutions.push(LambertSolution {
+                revolutions: revolution,
+                departure_velocity: from_direction * from_radial + from_tangent * from_transverse,
+                arrival_velocity: to_direction * to_radial + to_tangent * to_transverse,
+            });
+        }
+    }
+    solutions
+}
+
+/// Most full revolutions that fit in the non-dimensional time of flight.
+fn feasible_revolutions(time: f64, lambda: f64) -> u32 {
+    let mut revolutions = (time / PI).floor() as u32;
+    let minimum_energy = lambda.acos() + lambda * (1. - lambda * lambda).sqrt();
+    if revolutions > 0 && time < minimum_energy + revolutions as f64 * PI {
+        // only fits if it's past the shortest time for that many turns
+        if let Some(x) = halley(0.1, lambda, revolutions) {
+            let shortest = time_equation(x, compute_y(x, lambda), 0., lambda, revolutions);
+            if time < shortest {
+                revolutions -= 1;
+            }
+        }
+    }
+    revolutions
+}
+
+fn compute_y(x: f64, lambda: f64) -> f64 {
+    (1. - lambda * lambda * (1. - x * x)).sqrt()
+}
+
+fn compute_psi(x: f64, y: f64, lambda: f64) -> f64 {
+    if (-1. ..1.).contains(&x) {
+        (x * y + lambda * (1. - x * x)).acos()
+    } else if x > 1. {
+        ((y - x * lambda) * (x * x - 1.).sqrt()).asinh()
+    } else {
+        0.
+    }
+}
+
+/// Gauss hypergeometric function 2F1(3, 1, 5/2, x), by its series.
+fn hypergeometric(x: f64) -> f64 {
+    if x >= 1. {
+        return f64::INFINITY;
+    }
+    let (mut sum, mut term) = (1., 1.);
+    for index in 0.. {
+        let index = index as f64;
+        term *= (3. + index) * (1. + index) / (2.5 + index) * x / (index + 1.);
+        let previous = sum;
+        sum += term;
+        if sum == previous {
+            break;
+        }
+    }
+    sum
+}
+
+/// Non-dimensional time of flight for `x`, less `target`.
+fn time_equation(x: f64, y: f64, target: f64, lambda: f64, revolutions: u32) -> f64 {
+    let time = if revolutions == 0 && 0.6_f64.sqrt() < x && x < 1.4_f64.sqrt() {
+        // near parabolic, where the closed form loses precision
+        let eta = y - lambda * x;
+        let s = (1. - lambda - x * eta) / 2.;
+        let q = 4. / 3. * hypergeometric(s);
+        (eta.powi(3) * q + 4. * lambda * eta) / 2.
+    } else {
+        let psi = compute_psi(x, y, lambda);
+        ((psi + revolutions as f64 * PI) / (1. - x * x).abs().sqrt() - x + lambda * y)
+            / (1. - x * x)
+    };
+    time - target
+}
+
+/// First three derivatives of the time of flight with respect to `x`.
+fn time_derivatives(x: f64, y: f64, time: f64, lambda: f64) -> (f64, f64, f64) {
+    let lambda2 = lambda * lambda;
+    let first = (3. * time * x - 2. + 2. * lambda.powi(3) * x / y) / (1. - x * x);
+    let second = (3. * time + 5. * x * first + 2. * (1. - lambda2) * lambda.powi(3) / y.powi(3))
+        / (1. - x * x);
+    let third = (7. * x * second + 8. * first
+        - 6. * (1. - lambda2) * lambda.powi(5) * x / y.powi(5))
+        / (1. - x * x);
+    (first, second, third)
+}
+
+fn initial_guess(time: f64, lambda: f64, revolutions: u32, low_path: bool) -> f64 {
+    if revolutions == 0 {
+        let minimum_energy = lambda.acos() + lambda * (1. - lambda * lambda).sqrt();
+        let parabolic = 2. * (1. - lambda.powi(3)) / 3.;
+        if time >= minimum_energy {
+            (minimum_energy / time).powf(2. / 3.) - 1.
+        } else if time < parabolic {
+            2.5 * parabolic / time * (parabolic - time) / (1. - lambda.powi(5)) + 1.
+        } else {
+            (2_f64.ln() * (time / minimum_energy).ln() / (parabolic / minimum_energy).ln()).exp()
+                - 1.
+        }
+    } else {
+        let turns = revolutions as f64 * PI;
+        let left = ((turns + PI) / (8. * time)).powf(2. / 3.);
+        let right = ((8. * time) / turns).powf(2. / 3.);
+        let (left, right) = ((left - 1.) / (left + 1.), (right - 1.) / (right + 1.));
+        if low_path {
+            left.max(right)
+        } else {
+            left.min(right)
+        }
+    }
+}
+
+/// Finds where the time of flight is stationary, the shortest multi-revolution transfer.
+fn halley(mut x: f64, lambda: f64, revolutions: u32) -> Option<f64> {
+    for _ in 0..MAX_ITERATIONS {
+        let y = compute_y(x, lambda);
+        let time = time_equation(x, y, 0., lambda, revolutions);
+        let (first, second, third) = time_derivatives(x, y, time, lambda);
+        if second == 0. {
+            return None;
+        }
+        let next = x - 2. * first * second / (2. * second * second - first * third);
+        if (next - x).abs() < TOLERANCE {
+            return Some(next);
+        }
+        x = next;
+    }
+    None
+}
+
+/// Solves the time of flight equation for `x`.
+fn householder(mut x: f64, target: f64, lambda: f64, revolutions: u32) -> Option<f64> {
+    for _ in 0..MAX_ITERATIONS {
+        let y = compute_y(x, lambda);
+        let error = time_equation(x, y, target, lambda, revolutions);
+        let (first, second, third) = time_derivatives(x, y, error + target, lambda);
+        let next = x - error * (first * first - error * second / 2.)
+            / (first * (first * first - error * second) + third * error * error / 6.);
+        if !next.is_finite() {
+            return None;
+        }
+        if (next - x).abs() < TOLERANCE {
+            return Some(next);
+        }
+        x = next;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orbit::kepler_propagate;
+
+    const EARTH_MU: f64 = 3.986004418e14;
+
+    fn assert_close(actual: DVec3, expected: DVec3, tolerance: f64) {
+        assert!(
+            (actual - expected).length() <= tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn matches_vallado_example() {
+        // Vallado, Fundamentals of Astrodynamics and Applications, example 7-5, turned into the
+        // plane the bodies orbit in here, about -Y
+        let to_scene = |x: f64, y: f64| DVec3::new(x, 0., y) * 1000.;
+        let solutions = lambert(
+            EARTH_MU,
+            to_scene(15_945.34, 0.),
+            to_scene(12_214.838_99, 10_249.467_31),
+            76. * 60.,
+            0,
+        );
+
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions[0].revolutions, 0);
+        assert_close(
+            solutions[0].departure_velocity,
+            to_scene(2.058_913, 2.915_965),
+            1e-2,
+        );
+        assert_close(
+            solutions[0].arrival_velocity,
+            to_scene(-3.451_565, 0.910_315),
+            1e-2,
+        );
+    }
+
+    #[test]
+    fn every_solution_flies_to_the_target() {
+        let from = DVec3::new(7_000_000., 0., 0.);
+        let to = DVec3::new(-4_000_000., 1_000_000., 9_000_000.);
+        // long enough for two full turns of a low orbit on the way
+        let time_of_flight = 5. * 3_600.;
+        let solutions = lambert(EARTH_MU, from, to, time_of_flight, 2);
+
+        for revolutions in 0..=2 {
+            let count = solutions
+                .iter()
+                .filter(|solution| solution.revolutions == revolutions)
+                .count();
+            assert_eq!(count, if revolutions == 0 { 1 } else { 2 });
+        }
+        for solution in solutions {
+            let (position, velocity) =
+                kepler_propagate(from, solution.departure_velocity, EARTH_MU, time_of_flight);
+            assert_close(position, to, 1.);
+            assert_close(velocity, solution.arrival_velocity, 1e-3);
+        }
+    }
+
+    #[test]
+    fn one_revolution_needs_its_shortest_time() {
+        let from = DVec3::new(7_000_000., 0., 0.);
+        let to = DVec3::new(-4_000_000., 1_000_000., 9_000_000.);
+        let chord = (to - from).length();
+        let semi_perimeter = (from.length() + to.length() + chord) / 2.;
+        let mut lambda = (1. - chord / semi_perimeter).sqrt();
+        if from.cross(to).dot(REFERENCE_POLE) < 0. {
+            lambda = -lambda;
+        }
+        // the shortest single revolution transfer, by brute force over the whole ellipse range
+        let (fastest, shortest) = (1..200_000)
+            .map(|step| {
+                let x = -1. + step as f64 / 100_000.;
+                (x, time_equation(x, compute_y(x, lambda), 0., lambda, 1))
+            })
+            .fold((0., f64::INFINITY), |best, (x, time)| {
+                if time < best.1 {
+                    (x, time)
+                } else {
+                    best
+                }
+            });
+        let found = halley(0.1, lambda, 1).unwrap();
+        assert!(
+            (found - fastest).abs() < 1e-4,
+            "{} is not {}",
+            found,
+            fastest
+        );
+        assert_eq!(feasible_revolutions(shortest * 0.999, lambda), 0);
+        assert_eq!(feasible_revolutions(shortest * 1.001, lambda), 1);
+        let shortest = shortest / (2. * EARTH_MU / semi_perimeter.powi(3)).sqrt();
+
+        let revolutions = |time_of_flight: f64| {
+            let solutions = lambert(EARTH_MU, from, to, time_of_flight, 1);
+            for solution in solutions.iter() {
+                let (position, _) =
+                    kepler_propagate(from, solution.departure_velocity, EARTH_MU, time_of_flight);
+                assert_close(position, to, 1.);
+            }
+            solutions
+                .iter()
+                .filter(|solution| solution.revolutions == 1)
+                .count()
+        };
+        assert_eq!(revolutions(shortest * 0.99), 0);
+        assert_eq!(revolutions(shortest * 1.01), 2);
+    }
+
+    #[test]
+    fn goes_round_the_way_bodies_orbit() {
+        let from = DVec3::new(7_000_000., 0., 0.);
+        let to = DVec3::new(0., 0., -8_000_000.);
+        let solutions = lambert(EARTH_MU, from, to, 3_600., 0);
+
+        // a quarter turn backwards for a prograde transfer, so it takes the long way round
+        let normal = from.cross(solutions[0].departure_velocity);
+        assert!(normal.dot(REFERENCE_POLE) > 0.);
+    }
+}
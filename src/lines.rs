@@ -0,0 +1,137 @@
+use bevy::{
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    pbr::MaterialPipeline,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::{MeshVertexBufferLayout, PrimitiveTopology},
+        render_asset::{PrepareAssetError, RenderAsset},
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupLayout, BindGroupLayoutDescriptor,
+            RenderPipelineDescriptor, SpecializedMeshPipelineError,
+        },
+        renderer::RenderDevice,
+        view::NoFrustumCulling,
+    },
+};
+
+/// Unlit, alpha blended material for meshes made of lines with per vertex colors.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "9f2c3d4e-5b6a-4c7d-8e9f-a0b1c2d3e4f5"]
+pub struct LineMaterial;
+
+pub struct GpuLineMaterial {
+    bind_group: BindGroup,
+}
+
+impl RenderAsset for LineMaterial {
+    type ExtractedAsset = LineMaterial;
+    type PreparedAsset = GpuLineMaterial;
+    type Param = (SRes<RenderDevice>, SRes<MaterialPipeline<Self>>);
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        _extracted_asset: Self::ExtractedAsset,
+        (render_device, material_pipeline): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &[],
+            label: None,
+            layout: &material_pipeline.material_layout,
+        });
+
+        Ok(GpuLineMaterial { bind_group })
+    }
+}
+
+impl Material for LineMaterial {
+    fn vertex_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load("shaders/lines.wgsl"))
+    }
+
+    fn fragment_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load("shaders/lines.wgsl"))
+    }
+
+    fn bind_group(render_asset: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &render_asset.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[],
+            label: None,
+        })
+    }
+
+    fn alpha_mode(_render_asset: &<Self as RenderAsset>::PreparedAsset) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(1),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+/// Handle to the shared line material, so every line mesh is drawn by the same pipeline.
+pub struct LineMaterialHandle(pub Handle<LineMaterial>);
+
+impl FromWorld for LineMaterialHandle {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<LineMaterial>>();
+        LineMaterialHandle(materials.add(LineMaterial))
+    }
+}
+
+/// Plugin used to render colored lines in render space.
+pub struct LinesPlugin;
+
+impl Plugin for LinesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MaterialPlugin::<LineMaterial>::default());
+        app.init_resource::<LineMaterialHandle>();
+    }
+}
+
+/// Spawns an empty line mesh entity drawn in render space, returning it with the mesh to fill in.
+///
+/// Line vertices move every frame, so the mesh opts out of frustum culling instead of keeping
+/// its bounding box up to date.
+pub fn spawn_lines(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    material: &LineMaterialHandle,
+    topology: PrimitiveTopology,
+) -> (Entity, Handle<Mesh>) {
+    let mut mesh = Mesh::new(topology);
+    set_lines(&mut mesh, Vec::new(), Vec::new());
+    let mesh = meshes.add(mesh);
+    let entity = commands
+        .spawn_bundle(MaterialMeshBundle {
+            mesh: mesh.clone(),
+            material: material.0.clone(),
+            ..default()
+        })
+        .insert(NoFrustumCulling)
+        .id();
+    (entity, mesh)
+}
+
+/// Replaces the vertices of a line mesh.
+pub fn set_lines(mesh: &mut Mesh, positions: Vec<[f32; 3]>, colors: Vec<[f32; 4]>) {
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
@@ -1,42 +1,180 @@
+// bevy systems and queries trip these lints by design
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use std::fmt;
+
+use atmosphere::AtmospherePlugin;
 use bevy::{
+    asset::AssetPlugin,
+    log::LogPlugin,
     prelude::*,
     render::{render_resource::WgpuFeatures, settings::WgpuSettings},
+    scene::ScenePlugin,
+    transform::TransformPlugin,
 };
 
-use camera::{pan_orbit_camera, spawn_camera, switch_focus, FocusIndex};
+use camera::{pan_orbit_camera, spawn_camera, switch_focus, switch_target, FocusIndex};
+use collision::CollisionPlugin;
+use conic::ConicPlugin;
+use covariance::CovariancePlugin;
 use earth::setup_earth;
-use simulation::SimulationPlugin;
+use lines::LinesPlugin;
+use mars::setup_mars;
+use mercury::setup_mercury;
+use monte_carlo::{run_monte_carlo, MonteCarloSettings};
+use moon::setup_moon;
+use panels::PanelsPlugin;
+use porkchop::{run_porkchop, PorkchopSettings};
+use prediction::PredictionPlugin;
+use radiation::RadiationPlugin;
+use relative::RelativePlugin;
+use relativity::{check_perihelion, PerihelionSettings, RelativityPlugin};
+use satellite::setup_satellite;
+use simulation::{GravitySettings, SimulationPlugin};
+use soi::SoiPlugin;
 use sun::setup_sun;
+use targeting::{run_targeting, TargetingSettings};
+use tidal::TidalPlugin;
+use trail::TrailPlugin;
 use ui::UIPlugin;
+use view::ViewPlugin;
 
+mod atmosphere;
 mod camera;
+mod collision;
+mod conic;
+mod covariance;
 mod earth;
+mod engine;
+mod flyby;
+mod gravity;
+mod headless;
+mod lambert;
+mod lines;
+mod maneuver;
+mod mars;
+mod mercury;
+mod monte_carlo;
+mod moon;
+mod orbit;
+mod panels;
+mod porkchop;
+mod prediction;
+mod radiation;
+mod relative;
+mod relativity;
+mod satellite;
 mod simulation;
+mod soi;
 mod sun;
+mod targeting;
+mod tidal;
+mod trail;
+mod transfer;
 mod ui;
+mod variational;
+mod view;
 
 fn main() {
-    App::new()
-        .insert_resource(WindowDescriptor {
-            title: "Orbital Simulations".to_string(),
-            ..default()
-        })
-        .insert_resource(FocusIndex(0))
-        .insert_resource(ClearColor(Color::rgb(0., 0., 0.)))
-        .insert_resource(WgpuSettings {
-            features: WgpuFeatures::POLYGON_MODE_LINE,
-            ..default()
-        })
-        .add_plugins(DefaultPlugins)
-        .add_plugin(SimulationPlugin)
-        .add_plugin(UIPlugin)
-        .add_startup_system(setup_earth)
-        .add_startup_system(setup_sun)
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (gravity, args) = match GravitySettings::from_args(&args) {
+        Ok(settings) => settings,
+        Err(problem) => {
+            eprintln!("{}", problem);
+            std::process::exit(2);
+        }
+    };
+    run_headless(
+        &gravity,
+        MonteCarloSettings::from_args(&args),
+        run_monte_carlo,
+    );
+    run_headless(
+        &gravity,
+        PerihelionSettings::from_args(&args),
+        check_perihelion,
+    );
+    run_headless(&gravity, PorkchopSettings::from_args(&args), run_porkchop);
+    run_headless(&gravity, TargetingSettings::from_args(&args), run_targeting);
+
+    let mut app = App::new();
+    app.insert_resource(WindowDescriptor {
+        title: "Orbital Simulations".to_string(),
+        ..default()
+    })
+    .insert_resource(FocusIndex(0))
+    .insert_resource(gravity)
+    .insert_resource(ClearColor(Color::rgb(0., 0., 0.)))
+    .insert_resource(WgpuSettings {
+        features: WgpuFeatures::POLYGON_MODE_LINE,
+        ..default()
+    })
+    .add_plugins(DefaultPlugins)
+    .add_plugin(SimulationPlugin)
+    .add_plugin(UIPlugin)
+    .add_plugin(ViewPlugin)
+    .add_plugin(LinesPlugin)
+    .add_plugin(TrailPlugin)
+    .add_plugin(PredictionPlugin)
+    .add_plugin(ConicPlugin)
+    .add_plugin(AtmospherePlugin)
+    .add_plugin(RadiationPlugin)
+    .add_plugin(RelativityPlugin)
+    .add_plugin(CollisionPlugin)
+    .add_plugin(TidalPlugin)
+    .add_plugin(SoiPlugin)
+    .add_plugin(RelativePlugin)
+    .add_plugin(CovariancePlugin)
+    .add_plugin(PanelsPlugin);
+    add_bodies(&mut app)
         .add_startup_system(spawn_camera)
-        .add_system(pan_orbit_camera.after(simulation::LABEL))
+        .add_system(pan_orbit_camera.label(camera::LABEL).after(view::LABEL))
         .add_system(switch_focus)
+        .add_system(switch_target)
         .run();
 }
 
-#[derive(Component)]
-struct MainCamera;
+/// Spawns the solar system the simulation starts from.
+fn add_bodies(app: &mut App) -> &mut App {
+    app.add_startup_system(setup_earth)
+        .add_startup_system(setup_sun)
+        .add_startup_system_to_stage(StartupStage::PostStartup, setup_moon)
+        .add_startup_system_to_stage(StartupStage::PostStartup, setup_satellite)
+        .add_startup_system_to_stage(StartupStage::PostStartup, setup_mercury)
+        .add_startup_system_to_stage(StartupStage::PostStartup, setup_mars)
+}
+
+/// When a command's `settings` were given on the command line, spawns the bodies without a
+/// window or renderer, just the assets their setup needs, runs the command on them and exits.
+fn run_headless<S, E: fmt::Display>(
+    gravity: &GravitySettings,
+    settings: Result<Option<S>, E>,
+    command: fn(&mut World, &S) -> Result<(), E>,
+) {
+    let settings = match settings {
+        Ok(Some(settings)) => settings,
+        Ok(None) => return,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }
+    };
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(AssetPlugin)
+        .add_plugin(ScenePlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>()
+        .insert_resource(gravity.clone());
+    add_bodies(&mut app).update();
+
+    if let Err(error) = command(&mut app.world, &settings) {
+        error!("{}", error);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
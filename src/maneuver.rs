@@ -0,0 +1,202 @@
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{
+    orbit::primaries,
+    simulation::{BodyState, HPVec3},
+};
+
+/// Impulsive burn planned for a spacecraft. Nodes live on their own entities so a craft can have
+/// several lined up.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct ManeuverNode {
+    /// The spacecraft that burns.
+    pub body: Entity,
+    /// Simulated seconds since the start of the simulation at which the burn happens.
+    pub time: f64,
+    /// Delta-v in m/s along the velocity relative to the current primary.
+    pub prograde: f64,
+    /// Delta-v in m/s along the orbit normal.
+    pub normal: f64,
+    /// Delta-v in m/s along prograde × normal, outwards on a circular orbit.
+    pub radial: f64,
+}
+
+impl ManeuverNode {
+    /// Size of the burn in m/s.
+    pub fn delta_v(&self) -> f64 {
+        DVec3::new(self.prograde, self.normal, self.radial).length()
+    }
+
+    /// Burn as a velocity change, for a body at `position` moving at `velocity` relative to its
+    /// primary.
+    pub fn delta_v_vector(&self, position: DVec3, velocity: DVec3) -> DVec3 {
+        let (prograde, normal, radial) = frame(position, velocity);
+        prograde * self.prograde + normal * self.normal + radial * self.radial
+    }
+
+    /// Applies the burn to its body in `bodies`, relative to the primary the body is in the
+    /// sphere of influence of. Bodies without a primary have no frame to burn in.
+    pub fn apply(&self, bodies: &mut [BodyState]) {
+        let body = match bodies.iter().position(|body| body.entity == self.body) {
+            Some(body) => body,
+            None => return,
+        };
+        let primary = match primaries(bodies)[body] {
+            Some(primary) => primary,
+            None => return,
+        };
+
+        let position = HPVec3::sub(&bodies[body].translation, &bodies[primary].translation);
+        let velocity = HPVec3::sub(&bodies[body].velocity, &bodies[primary].velocity);
+        let delta_v = self.delta_v_vector(position.to_dvec3(), velocity.to_dvec3());
+        bodies[body].velocity.add_self(&HPVec3::from_dvec3(delta_v));
+    }
+}
+
+/// Prograde, normal and radial unit vectors of a body at `position` moving at `velocity`, both
+/// relative to its primary.
+pub fn frame(position: DVec3, velocity: DVec3) -> (DVec3, DVec3, DVec3) {
+    let prograde = velocity.normalize_or_zero();
+    let normal = position.cross(velocity).normalize_or_zero();
+    (prograde, normal, prograde.cross(normal))
+}
+
+/// Moves `bodies` on from `start` by `duration` seconds with `advance`, stopping to burn every
+/// node due by the end. Nodes that are already overdue burn straight away. Returns the indices of
+/// the nodes that burned, in the order they did.
+pub fn advance_through(
+    bodies: &mut [BodyState],
+    nodes: &[ManeuverNode],
+    start: f64,
+    duration: f64,
+    mut advance: impl FnMut(&mut [BodyState], f64),
+) -> Vec<usize> {
+    let end = start + duration;
+    let mut due: Vec<usize> = (0..nodes.len())
+        .filter(|index| nodes[*index].time <= end)
+        .collect();
+    due.sort_by(|a, b| nodes[*a].time.total_cmp(&nodes[*b].time));
+
+    // split the step at every burn
+    let mut now = start;
+    for &index in due.iter() {
+        let time = nodes[index].time.max(now);
+        if time > now {
+            advance(bodies, time - now);
+            now = time;
+        }
+        nodes[index].apply(bodies);
+    }
+    if end > now {
+        advance(bodies, end - now);
+    }
+
+    due
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::Float;
+
+    use super::*;
+    use crate::simulation::{PhysicalProperties, DEFAULT_PRECISION};
+
+    /// A 6e24 kg primary and a craft 7000 km from it, moving at 7.5 km/s.
+    fn bodies() -> Vec<BodyState> {
+        let state = |index: u32, mass: f64, position: DVec3, velocity: DVec3| {
+            let properties = PhysicalProperties {
+                mass: Float::with_val(DEFAULT_PRECISION, mass),
+                estimated_radius: Float::with_val(DEFAULT_PRECISION, 1.),
+                acceleration: HPVec3::from_dvec3(velocity),
+                translation: HPVec3::from_dvec3(position),
+            };
+            BodyState::new(Entity::from_raw(index), &properties, false)
+        };
+        vec![
+            state(0, 6e24, DVec3::ZERO, DVec3::ZERO),
+            state(1, 1., DVec3::X * 7e6, DVec3::Z * 7_500.),
+        ]
+    }
+
+    fn node(time: f64, prograde: f64) -> ManeuverNode {
+        ManeuverNode {
+            body: Entity::from_raw(1),
+            time,
+            prograde,
+            normal: 0.,
+            radial: 0.,
+        }
+    }
+
+    /// Advances by coasting in straight lines, logging every stretch.
+    fn coast<'a>(log: &'a mut Vec<f64>) -> impl FnMut(&mut [BodyState], f64) + 'a {
+        move |bodies, duration| {
+            log.push(duration);
+            for body in bodies.iter_mut() {
+                let drift = HPVec3::scalar_mul(
+                    &body.velocity,
+                    &Float::with_val(DEFAULT_PRECISION, duration),
+                );
+                body.translation.add_self(&drift);
+            }
+        }
+    }
+
+    fn speed(bodies: &[BodyState]) -> f64 {
+        bodies[1].velocity.to_dvec3().length()
+    }
+
+    #[test]
+    fn splits_the_step_at_a_node() {
+        let mut bodies = bodies();
+        let mut log = Vec::new();
+        let burned = advance_through(&mut bodies, &[node(13., 10.)], 10., 5., coast(&mut log));
+
+        assert_eq!(burned, vec![0]);
+        assert_eq!(log, vec![3., 2.]);
+        assert!((speed(&bodies) - 7_510.).abs() < 1e-9);
+        // 3 s at the old speed and 2 s at the new one
+        let travelled = bodies[1].translation.to_dvec3().z;
+        assert!((travelled - (3. * 7_500. + 2. * 7_510.)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn burns_overdue_nodes_straight_away() {
+        let mut bodies = bodies();
+        let mut log = Vec::new();
+        let burned = advance_through(&mut bodies, &[node(2., 10.)], 10., 5., coast(&mut log));
+
+        assert_eq!(burned, vec![0]);
+        assert_eq!(log, vec![5.]);
+        let travelled = bodies[1].translation.to_dvec3().z;
+        assert!((travelled - 5. * 7_510.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn burns_several_nodes_in_order_within_a_step() {
+        let mut bodies = bodies();
+        let mut log = Vec::new();
+        let nodes = [
+            node(14., 5.),
+            node(30., 100.),
+            node(11., 10.),
+            node(15., 1.),
+        ];
+        let burned = advance_through(&mut bodies, &nodes, 10., 5., coast(&mut log));
+
+        assert_eq!(burned, vec![2, 0, 3]);
+        assert_eq!(log, vec![1., 3., 1.]);
+        assert!((speed(&bodies) - 7_516.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn leaves_later_nodes_alone() {
+        let mut bodies = bodies();
+        let mut log = Vec::new();
+        let burned = advance_through(&mut bodies, &[node(16., 10.)], 10., 5., coast(&mut log));
+
+        assert!(burned.is_empty());
+        assert_eq!(log, vec![5.]);
+        assert_eq!(speed(&bodies), 7_500.);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::camera::Focusable;
+use crate::simulation::{HPVec3, PhysicalProperties, Simulated, GRAVITATIONAL_CONSTANT};
+use crate::sun::Sun;
+use crate::trail::Trail;
+use crate::ui::RenderInUI;
+use bevy::{math::DVec3, prelude::*};
+use rug::Float;
+
+/// Approximate radius of mars in meters.
+const RADIUS: f32 = 3.3895e+6_f32;
+
+/// Approximate mass of mars in kg.
+const MASS: f32 = 6.4171e+23_f32;
+
+/// Semi-major axis of mars' orbit in meters.
+const SEMI_MAJOR_AXIS: f64 = 227.939e9;
+
+const ECCENTRICITY: f64 = 0.0934;
+
+/// Length of a martian year in seconds, used for the trail.
+const ORBITAL_PERIOD: f64 = 686.98 * 86_400.;
+
+/// Radians mars starts ahead of the earth, about where a Hohmann transfer needs it, so the first
+/// launch window is close.
+const LEAD_ANGLE: f64 = 0.77;
+
+#[derive(Component)]
+pub struct Mars;
+
+/// Spawns mars at perihelion a little ahead of the earth, so it has to run after the sun exists.
+pub fn setup_mars(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    sun_query: Query<(Entity, &PhysicalProperties), With<Sun>>,
+) {
+    let (sun, sun_properties) = match sun_query.get_single() {
+        Ok(sun) => sun,
+        Err(_) => return,
+    };
+
+    let mu = (GRAVITATIONAL_CONSTANT * (sun_properties.mass.clone() + MASS)).to_f64();
+    let perihelion = SEMI_MAJOR_AXIS * (1. - ECCENTRICITY);
+    let perihelion_velocity =
+        (mu * (1. + ECCENTRICITY) / (SEMI_MAJOR_AXIS * (1. - ECCENTRICITY))).sqrt();
+
+    // the earth starts on +X heading +Z, so ahead of it is turned from +X towards +Z
+    let direction = DVec3::new(LEAD_ANGLE.cos(), 0., LEAD_ANGLE.sin());
+    let translation = HPVec3::add(
+        &sun_properties.translation,
+        &HPVec3::from_dvec3(direction * perihelion),
+    );
+    let velocity = HPVec3::add(
+        &sun_properties.acceleration,
+        &HPVec3::from_dvec3(DVec3::Y.cross(direction) * -perihelion_velocity),
+    );
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::UVSphere {
+                radius: 0.5,
+                ..default()
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.75, 0.35, 0.2),
+                perceptual_roughness: 1.,
+                ..default()
+            }),
+            transform: Transform::from_scale(Vec3::splat(RADIUS * 2.)),
+            ..default()
+        })
+        .insert(Mars)
+        .insert(RenderInUI("Mars".to_string()))
+        .insert(Simulated)
+        .insert(PhysicalProperties {
+            mass: Float::with_val(128, MASS),
+            estimated_radius: Float::with_val(128, RADIUS),
+            acceleration: velocity,
+            translation,
+        })
+        .insert(Trail::new(
+            Some(sun),
+            ORBITAL_PERIOD,
+            Color::rgba(0.8, 0.4, 0.3, 0.8),
+        ))
+        .insert(Focusable);
+}
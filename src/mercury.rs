@@ -0,0 +1,85 @@
+use crate::camera::Focusable;
+use crate::relativity::PerihelionTracker;
+use crate::simulation::{HPVec3, PhysicalProperties, Simulated, GRAVITATIONAL_CONSTANT};
+use crate::sun::Sun;
+use crate::trail::Trail;
+use crate::ui::RenderInUI;
+use bevy::{math::DVec3, prelude::*};
+use rug::Float;
+
+/// Approximate radius of mercury in meters.
+const RADIUS: f32 = 2.4397e+6_f32;
+
+/// Approximate mass of mercury in kg.
+const MASS: f32 = 3.3011e+23_f32;
+
+/// Semi-major axis of mercury's orbit in meters.
+const SEMI_MAJOR_AXIS: f64 = 57.909e9;
+
+const ECCENTRICITY: f64 = 0.2056;
+
+/// Length of a mercurian year in seconds, used for the trail.
+const ORBITAL_PERIOD: f64 = 87.97 * 86_400.;
+
+#[derive(Component)]
+pub struct Mercury;
+
+/// Spawns mercury at perihelion on the far side of the sun from the earth, so it has to run
+/// after the sun exists.
+pub fn setup_mercury(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    sun_query: Query<(Entity, &PhysicalProperties), With<Sun>>,
+) {
+    let (sun, sun_properties) = match sun_query.get_single() {
+        Ok(sun) => sun,
+        Err(_) => return,
+    };
+
+    let mu = (GRAVITATIONAL_CONSTANT * (sun_properties.mass.clone() + MASS)).to_f64();
+    let perihelion = SEMI_MAJOR_AXIS * (1. - ECCENTRICITY);
+    let perihelion_velocity =
+        (mu * (1. + ECCENTRICITY) / (SEMI_MAJOR_AXIS * (1. - ECCENTRICITY))).sqrt();
+
+    // orbiting the same way round as the earth
+    let translation = HPVec3::add(
+        &sun_properties.translation,
+        &HPVec3::from_dvec3(DVec3::new(-perihelion, 0., 0.)),
+    );
+    let velocity = HPVec3::add(
+        &sun_properties.acceleration,
+        &HPVec3::from_dvec3(DVec3::new(0., 0., -perihelion_velocity)),
+    );
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::UVSphere {
+                radius: 0.5,
+                ..default()
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.55, 0.5, 0.45),
+                perceptual_roughness: 1.,
+                ..default()
+            }),
+            transform: Transform::from_scale(Vec3::splat(RADIUS * 2.)),
+            ..default()
+        })
+        .insert(Mercury)
+        .insert(RenderInUI("Mercury".to_string()))
+        .insert(Simulated)
+        .insert(PhysicalProperties {
+            mass: Float::with_val(128, MASS),
+            estimated_radius: Float::with_val(128, RADIUS),
+            acceleration: velocity,
+            translation,
+        })
+        .insert(PerihelionTracker::new(sun))
+        .insert(Trail::new(
+            Some(sun),
+            ORBITAL_PERIOD,
+            Color::rgba(0.7, 0.6, 0.5, 0.8),
+        ))
+        .insert(Focusable);
+}
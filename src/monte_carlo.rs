@@ -0,0 +1,582 @@
+use std::{
+    f64::consts::PI,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use bevy::{math::DVec3, prelude::*};
+use rug::Float;
+
+use crate::{
+    collision::contact_fraction,
+    headless::{self, Args, Snapshot},
+    maneuver::{advance_through, ManeuverNode},
+    relative::ric_frame,
+    satellite::{POSITION_SIGMA, VELOCITY_SIGMA},
+    simulation::{step, BodyState, HPVec3, DEFAULT_PRECISION},
+};
+
+/// Flag on the command line that runs the dispersion analysis instead of opening a window.
+pub const FLAG: &str = "--monte-carlo";
+
+const USAGE: &str = "usage: orbital-simulations --monte-carlo [--body NAME] [--target NAME] \
+[--runs N] [--duration SECONDS] [--timestep SECONDS] [--threads N] [--seed N] [--output FILE] \
+[--position-sigma M] [--velocity-sigma M/S] [--mass-sigma FRACTION] \
+[--burn TIME,PROGRADE,NORMAL,RADIAL]... [--burn-sigma FRACTION] [--pointing-sigma DEGREES] \
+[--timing-sigma SECONDS] [--softening M]";
+
+/// Spread of the errors every dispersed run samples, as standard deviations.
+#[derive(Clone, Debug)]
+pub struct Dispersions {
+    /// Starting position error in meters along each axis.
+    pub position_sigma: f64,
+    /// Starting velocity error in m/s along each axis.
+    pub velocity_sigma: f64,
+    /// Starting mass error as a fraction of the mass.
+    pub mass_sigma: f64,
+    /// Burn size error as a fraction of each burn.
+    pub burn_sigma: f64,
+    /// Burn pointing error in radians about each axis across the burn.
+    pub pointing_sigma: f64,
+    /// Burn timing error in seconds.
+    pub timing_sigma: f64,
+}
+
+/// What to disperse and for how long, read off the command line.
+#[derive(Clone, Debug)]
+pub struct MonteCarloSettings {
+    /// Name of the dispersed body.
+    pub body: String,
+    /// Name of the body distances and final positions are measured from.
+    pub target: String,
+    /// Dispersed runs, on top of the nominal one.
+    pub runs: usize,
+    /// Simulated seconds every run lasts.
+    pub duration: f64,
+    pub timestep: f64,
+    pub threads: usize,
+    pub seed: u64,
+    /// Per run results. Aggregate statistics go next to it with a `_summary` suffix.
+    pub output: PathBuf,
+    pub dispersions: Dispersions,
+    /// Burns of the dispersed body as seconds from the start and prograde, normal and radial
+    /// delta-v in m/s.
+    pub burns: Vec<(f64, DVec3)>,
+}
+
+impl Default for MonteCarloSettings {
+    fn default() -> Self {
+        MonteCarloSettings {
+            body: "Satellite".to_string(),
+            target: "Earth".to_string(),
+            runs: 100,
+            duration: 6_000.,
+            timestep: 1.,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+            seed: 0,
+            output: PathBuf::from("monte_carlo.csv"),
+            dispersions: Dispersions {
+                position_sigma: POSITION_SIGMA,
+                velocity_sigma: VELOCITY_SIGMA,
+                mass_sigma: 0.,
+                burn_sigma: 0.01,
+                pointing_sigma: 1_f64.to_radians(),
+                timing_sigma: 1.,
+            },
+            burns: Vec::new(),
+        }
+    }
+}
+
+impl MonteCarloSettings {
+    /// Settings from the command line arguments after the program name, or `None` without
+    /// `FLAG`.
+    pub fn from_args(args: &[String]) -> Result<Option<MonteCarloSettings>, MonteCarloError> {
+        let mut args = match Args::find(args, FLAG) {
+            Some(args) => args,
+            None => return Ok(None),
+        };
+
+        let mut settings = MonteCarloSettings::default();
+        while let Some(arg) = args.next() {
+            let mut value = || args.value::<String>(&arg).map_err(MonteCarloError::Usage);
+            let dispersions = &mut settings.dispersions;
+            match arg.as_str() {
+                "--body" => settings.body = value()?,
+                "--target" => settings.target = value()?,
+                "--runs" => settings.runs = parse(&arg, &value()?)?,
+                "--duration" => settings.duration = parse(&arg, &value()?)?,
+                "--timestep" => settings.timestep = parse(&arg, &value()?)?,
+                "--threads" => settings.threads = parse::<usize>(&arg, &value()?)?.max(1),
+                "--seed" => settings.seed = parse(&arg, &value()?)?,
+                "--output" => settings.output = PathBuf::from(value()?),
+                "--position-sigma" => dispersions.position_sigma = parse(&arg, &value()?)?,
+                "--velocity-sigma" => dispersions.velocity_sigma = parse(&arg, &value()?)?,
+                "--mass-sigma" => dispersions.mass_sigma = parse(&arg, &value()?)?,
+                "--burn-sigma" => dispersions.burn_sigma = parse(&arg, &value()?)?,
+                "--pointing-sigma" => {
+                    dispersions.pointing_sigma = parse::<f64>(&arg, &value()?)?.to_radians()
+                }
+                "--timing-sigma" => dispersions.timing_sigma = parse(&arg, &value()?)?,
+                "--burn" => {
+                    let burn = value()?;
+                    let parts = burn
+                        .split(',')
+                        .map(|part| parse::<f64>(&arg, part.trim()))
+                        .collect::<Result<Vec<f64>, _>>()?;
+                    match parts[..] {
+                        [time, prograde, normal, radial] => settings
+                            .burns
+                            .push((time, DVec3::new(prograde, normal, radial))),
+                        _ => {
+                            return Err(MonteCarloError::Usage(format!(
+                                "--burn takes time,prograde,normal,radial, not {}",
+                                burn
+                            )))
+                        }
+                    }
+                }
+                _ => return Err(MonteCarloError::Usage(format!("unknown argument {}", arg))),
+            }
+        }
+        if settings.timestep <= 0. || settings.duration < 0. {
+            return Err(MonteCarloError::Usage(
+                "--timestep has to be positive and --duration can't be negative".to_string(),
+            ));
+        }
+        if settings.runs < 2 {
+            return Err(MonteCarloError::Usage(
+                "--runs has to be at least 2 for a spread".to_string(),
+            ));
+        }
+        Ok(Some(settings))
+    }
+
+    /// Where the aggregate statistics are written.
+    pub fn summary_path(&self) -> PathBuf {
+        let stem = self
+            .output
+            .file_stem()
+            .map_or("monte_carlo".into(), |stem| stem.to_string_lossy());
+        self.output.with_file_name(format!("{}_summary.csv", stem))
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, MonteCarloError> {
+    headless::parse(arg, value).map_err(MonteCarloError::Usage)
+}
+
+#[derive(Debug)]
+pub enum MonteCarloError {
+    Usage(String),
+    MissingBody(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for MonteCarloError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonteCarloError::Usage(problem) => write!(f, "{}\n{}", problem, USAGE),
+            MonteCarloError::MissingBody(name) => write!(f, "there's no body called {}", name),
+            MonteCarloError::Io(error) => write!(f, "couldn't write the dispersions: {}", error),
+        }
+    }
+}
+
+impl From<io::Error> for MonteCarloError {
+    fn from(error: io::Error) -> Self {
+        MonteCarloError::Io(error)
+    }
+}
+
+/// SplitMix64, small and good enough for sampling errors. Every run seeds its own, so results
+/// don't depend on how runs are spread over threads.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64, run: usize) -> Random {
+        let mut random = Random(seed ^ (run as u64).wrapping_mul(0xD1B5_4A32_D192_ED03));
+        random.next_u64();
+        random
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Standard normal, by Box–Muller.
+    fn normal(&mut self) -> f64 {
+        let radius = (-2. * (1. - self.uniform()).ln()).sqrt();
+        radius * (2. * PI * self.uniform()).cos()
+    }
+
+    fn normal_vector(&mut self, sigma: f64) -> DVec3 {
+        DVec3::new(self.normal(), self.normal(), self.normal()) * sigma
+    }
+}
+
+/// Errors one run started from.
+struct Sample {
+    position: DVec3,
+    velocity: DVec3,
+    mass_factor: f64,
+    burns: Vec<ManeuverNode>,
+}
+
+impl Sample {
+    fn draw(
+        random: &mut Random,
+        dispersions: &Dispersions,
+        body: Entity,
+        burns: &[(f64, DVec3)],
+    ) -> Sample {
+        let position = random.normal_vector(dispersions.position_sigma);
+        let velocity = random.normal_vector(dispersions.velocity_sigma);
+        let mass_factor = (1. + random.normal() * dispersions.mass_sigma).max(0.);
+        let burns = burns
+            .iter()
+            .map(|(time, delta_v)| {
+                // small rotations about both axes across the burn, then a size error
+                let (across, other) = delta_v.normalize_or_zero().any_orthonormal_pair();
+                let pointing = (across * random.normal() + other * random.normal())
+                    * dispersions.pointing_sigma;
+                let delta_v = (*delta_v + pointing.cross(*delta_v))
+                    * (1. + random.normal() * dispersions.burn_sigma);
+                ManeuverNode {
+                    body,
+                    time: time + random.normal() * dispersions.timing_sigma,
+                    prograde: delta_v.x,
+                    normal: delta_v.y,
+                    radial: delta_v.z,
+                }
+            })
+            .collect();
+        Sample {
+            position,
+            velocity,
+            mass_factor,
+            burns,
+        }
+    }
+
+    fn nominal(body: Entity, burns: &[(f64, DVec3)]) -> Sample {
+        Sample {
+            position: DVec3::ZERO,
+            velocity: DVec3::ZERO,
+            mass_factor: 1.,
+            burns: burns
+                .iter()
+                .map(|(time, delta_v)| ManeuverNode {
+                    body,
+                    time: *time,
+                    prograde: delta_v.x,
+                    normal: delta_v.y,
+                    radial: delta_v.z,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How one run went.
+struct RunResult {
+    position_error: f64,
+    velocity_error: f64,
+    mass: f64,
+    /// Position and velocity relative to the target at the end, unless the body hit something.
+    final_state: Option<(DVec3, DVec3)>,
+    min_distance: f64,
+    min_distance_time: f64,
+    /// What the body hit and when.
+    impact: Option<(usize, f64)>,
+}
+
+/// Starting bodies, and which of them are dispersed and measured from.
+struct Scenario {
+    bodies: Vec<BodyState>,
+    names: Vec<String>,
+    body: usize,
+    target: usize,
+}
+
+impl Scenario {
+    /// Snapshots the simulated bodies the startup systems spawned into `world`.
+    fn from_world(
+        world: &mut World,
+        settings: &MonteCarloSettings,
+    ) -> Result<Scenario, MonteCarloError> {
+        let snapshot = Snapshot::from_world(world);
+        let find = |name: &String| {
+            snapshot
+                .find(name)
+                .ok_or_else(|| MonteCarloError::MissingBody(name.clone()))
+        };
+        let (body, target) = (find(&settings.body)?, find(&settings.target)?);
+        Ok(Scenario {
+            bodies: snapshot.bodies,
+            names: snapshot.names,
+            body,
+            target,
+        })
+    }
+
+    /// Flies the scenario from `sample`'s errors for `settings.duration` seconds, stopping early
+    /// if the dispersed body hits anything.
+    fn run(&self, settings: &MonteCarloSettings, sample: Sample) -> RunResult {
+        let mut bodies = self.bodies.clone();
+        let body = &mut bodies[self.body];
+        body.translation
+            .add_self(&HPVec3::from_dvec3(sample.position));
+        body.velocity.add_self(&HPVec3::from_dvec3(sample.velocity));
+        body.mass *= sample.mass_factor;
+        let mass = body.mass.to_f64();
+
+        let offsets = |bodies: &[BodyState]| -> Vec<DVec3> {
+            bodies
+                .iter()
+                .map(|other| {
+                    HPVec3::sub(&bodies[self.body].translation, &other.translation).to_dvec3()
+                })
+                .collect()
+        };
+        let mut nodes = sample.burns;
+        let mut now = 0.;
+        let mut min_distance = offsets(&bodies)[self.target].length();
+        let mut min_distance_time = 0.;
+        let mut impact = None;
+
+        while now < settings.duration && impact.is_none() {
+            let timestep = settings.timestep.min(settings.duration - now);
+            let start = offsets(&bodies);
+            let burned = advance_through(&mut bodies, &nodes, now, timestep, |bodies, timestep| {
+                step(bodies, &Float::with_val(DEFAULT_PRECISION, timestep));
+            });
+            for index in burned.into_iter().rev() {
+                nodes.remove(index);
+            }
+            let end = offsets(&bodies);
+
+            impact = (0..bodies.len())
+                .filter(|other| *other != self.body)
+                .filter_map(|other| {
+                    let contact = bodies[self.body].radius + bodies[other].radius;
+                    contact_fraction(start[other], end[other], contact)
+                        .map(|fraction| (other, now + fraction * timestep))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            now += timestep;
+
+            let distance = end[self.target].length();
+            if distance < min_distance {
+                min_distance = distance;
+                min_distance_time = now;
+            }
+        }
+
+        let final_state = impact.is_none().then(|| {
+            let target = &bodies[self.target];
+            let body = &bodies[self.body];
+            (
+                HPVec3::sub(&body.translation, &target.translation).to_dvec3(),
+                HPVec3::sub(&body.velocity, &target.velocity).to_dvec3(),
+            )
+        });
+        RunResult {
+            position_error: sample.position.length(),
+            velocity_error: sample.velocity.length(),
+            mass,
+            final_state,
+            min_distance,
+            min_distance_time,
+            impact,
+        }
+    }
+}
+
+/// Runs the nominal case and `settings.runs` dispersed ones of the bodies spawned into `world`,
+/// then writes every run and their statistics out as CSV.
+pub fn run_monte_carlo(
+    world: &mut World,
+    settings: &MonteCarloSettings,
+) -> Result<(), MonteCarloError> {
+    let scenario = Scenario::from_world(world, settings)?;
+    let entity = scenario.bodies[scenario.body].entity;
+    info!(
+        "flying {} runs of {} for {:.2} h on {} threads",
+        settings.runs + 1,
+        settings.body,
+        settings.duration / 3600.,
+        settings.threads.min(settings.runs).max(1)
+    );
+
+    let mut results = vec![scenario.run(settings, Sample::nominal(entity, &settings.burns))];
+    let threads = settings.threads.min(settings.runs).max(1);
+    let mut dispersed: Vec<(usize, RunResult)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                let scenario = &scenario;
+                scope.spawn(move || {
+                    (1..=settings.runs)
+                        .skip(worker)
+                        .step_by(threads)
+                        .map(|run| {
+                            let mut random = Random::new(settings.seed, run);
+                            let sample = Sample::draw(
+                                &mut random,
+                                &settings.dispersions,
+                                entity,
+                                &settings.burns,
+                            );
+                            (run, scenario.run(settings, sample))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    dispersed.sort_by_key(|(run, _)| *run);
+    results.extend(dispersed.into_iter().map(|(_, result)| result));
+
+    write_runs(&settings.output, &scenario, &results)?;
+    write_summary(&settings.summary_path(), &results)?;
+    info!(
+        "wrote {} and {}",
+        settings.output.display(),
+        settings.summary_path().display()
+    );
+    Ok(())
+}
+
+/// Final position of every run against the nominal one, in meters along the nominal's radial,
+/// in-track and cross-track directions about the target.
+fn final_offsets(results: &[RunResult]) -> Vec<Option<DVec3>> {
+    let nominal = results.first().and_then(|nominal| nominal.final_state);
+    results
+        .iter()
+        .map(|result| {
+            let (position, velocity) = nominal?;
+            let (final_position, _) = result.final_state?;
+            Some(ric_frame(position, velocity).transpose() * (final_position - position))
+        })
+        .collect()
+}
+
+/// Writes one CSV row per run, the nominal run first.
+fn write_runs(path: &Path, scenario: &Scenario, results: &[RunResult]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        "run,position_error_m,velocity_error_m_s,mass_kg,radial_m,in_track_m,cross_track_m,\
+final_distance_m,min_distance_m,min_distance_time_s,impact_body,impact_time_s"
+    )?;
+    for (run, (result, offset)) in results.iter().zip(final_offsets(results)).enumerate() {
+        let offset = match offset {
+            Some(offset) => format!("{:.3},{:.3},{:.3}", offset.x, offset.y, offset.z),
+            None => ",,".to_string(),
+        };
+        let final_distance = result
+            .final_state
+            .map_or_else(String::new, |(position, _)| {
+                format!("{:.3}", position.length())
+            });
+        let impact = match result.impact {
+            Some((body, time)) => format!("{},{:.1}", scenario.names[body], time),
+            None => ",".to_string(),
+        };
+        writeln!(
+            file,
+            "{},{:.3},{:.5},{:.3},{},{},{:.3},{:.1},{}",
+            run,
+            result.position_error,
+            result.velocity_error,
+            result.mass,
+            offset,
+            final_distance,
+            result.min_distance,
+            result.min_distance_time,
+            impact
+        )?;
+    }
+    file.flush()
+}
+
+/// Writes the statistics over the dispersed runs as `statistic,value` rows. Statistics of final
+/// positions are left empty when too few runs got to the end without an impact.
+fn write_summary(path: &Path, results: &[RunResult]) -> io::Result<()> {
+    let dispersed = &results[1..];
+    let runs = dispersed.len() as f64;
+    let impacts = dispersed
+        .iter()
+        .filter(|result| result.impact.is_some())
+        .count();
+    let offsets: Vec<DVec3> = final_offsets(results)
+        .into_iter()
+        .skip(1)
+        .flatten()
+        .collect();
+    let count = offsets.len() as f64;
+    let mean = (count > 0.).then(|| {
+        offsets
+            .iter()
+            .fold(DVec3::ZERO, |sum, offset| sum + *offset)
+            / count
+    });
+    let sigma = mean.filter(|_| count > 1.).map(|mean| {
+        let variance = offsets.iter().fold(DVec3::ZERO, |sum, offset| {
+            sum + (*offset - mean) * (*offset - mean)
+        }) / (count - 1.);
+        DVec3::new(variance.x.sqrt(), variance.y.sqrt(), variance.z.sqrt())
+    });
+    let misses: Vec<f64> = offsets.iter().map(|offset| offset.length()).collect();
+    let min_distances: Vec<f64> = dispersed.iter().map(|result| result.min_distance).collect();
+
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "statistic,value")?;
+    let mut row = |statistic: &str, value: Option<f64>| match value {
+        Some(value) => writeln!(file, "{},{}", statistic, value),
+        None => writeln!(file, "{},", statistic),
+    };
+    row("runs", Some(runs))?;
+    row("impacts", Some(impacts as f64))?;
+    row("impact_probability", Some(impacts as f64 / runs))?;
+    row("final_radial_mean_m", mean.map(|mean| mean.x))?;
+    row("final_in_track_mean_m", mean.map(|mean| mean.y))?;
+    row("final_cross_track_mean_m", mean.map(|mean| mean.z))?;
+    row("final_radial_sigma_m", sigma.map(|sigma| sigma.x))?;
+    row("final_in_track_sigma_m", sigma.map(|sigma| sigma.y))?;
+    row("final_cross_track_sigma_m", sigma.map(|sigma| sigma.z))?;
+    row(
+        "final_miss_mean_m",
+        mean.map(|_| misses.iter().sum::<f64>() / count),
+    )?;
+    row("final_miss_max_m", misses.iter().copied().reduce(f64::max))?;
+    row(
+        "min_distance_min_m",
+        min_distances.iter().copied().reduce(f64::min),
+    )?;
+    row(
+        "min_distance_mean_m",
+        Some(min_distances.iter().sum::<f64>() / runs),
+    )?;
+    file.flush()
+}
@@ -0,0 +1,84 @@
+use crate::camera::Focusable;
+use crate::earth::Earth;
+use crate::simulation::{HPVec3, PhysicalProperties, Simulated, GRAVITATIONAL_CONSTANT};
+use crate::tidal::Disruptible;
+use crate::trail::Trail;
+use crate::ui::RenderInUI;
+use bevy::prelude::*;
+use rug::Float;
+
+/// Approximate radius of the moon in meters.
+const RADIUS: f32 = 1.7374e+6_f32;
+
+/// Approximate mass of the moon in kg.
+const MASS: f32 = 7.342e+22_f32;
+
+/// Approximate distance from the earth to the moon in meters.
+pub const DISTANCE_FROM_EARTH: f32 = 384_400_000.;
+
+/// Approximate length of a lunar orbit in seconds, used for the trail.
+const ORBITAL_PERIOD: f64 = 27.3 * 86_400.;
+
+#[derive(Component)]
+pub struct Moon;
+
+/// Spawns the moon on a circular orbit around the earth, so it has to run after the earth exists.
+pub fn setup_moon(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    earth_query: Query<(Entity, &PhysicalProperties), With<Earth>>,
+) {
+    let (earth, earth_properties) = match earth_query.get_single() {
+        Ok(earth) => earth,
+        Err(_) => return,
+    };
+
+    let distance = Float::with_val(128, DISTANCE_FROM_EARTH);
+    let orbital_velocity =
+        (GRAVITATIONAL_CONSTANT * earth_properties.mass.clone() / distance).sqrt();
+
+    let translation = HPVec3::add(
+        &earth_properties.translation,
+        &HPVec3::from_vec3(&Vec3::new(DISTANCE_FROM_EARTH, 0., 0.)),
+    );
+    let velocity = HPVec3::add(
+        &earth_properties.acceleration,
+        &HPVec3::new(
+            Float::with_val(128, 0.),
+            Float::with_val(128, 0.),
+            orbital_velocity,
+        ),
+    );
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::UVSphere {
+                radius: 0.5,
+                ..default()
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.6, 0.6, 0.6),
+                perceptual_roughness: 1.,
+                ..default()
+            }),
+            transform: Transform::from_scale(Vec3::splat(RADIUS * 2.)),
+            ..default()
+        })
+        .insert(Moon)
+        .insert(RenderInUI("Moon".to_string()))
+        .insert(Simulated)
+        .insert(PhysicalProperties {
+            mass: Float::with_val(128, MASS),
+            estimated_radius: Float::with_val(128, RADIUS),
+            acceleration: velocity,
+            translation,
+        })
+        .insert(Trail::new(
+            Some(earth),
+            ORBITAL_PERIOD,
+            Color::rgba(0.8, 0.8, 0.8, 0.8),
+        ))
+        .insert(Disruptible { fragments: 24 })
+        .insert(Focusable);
+}
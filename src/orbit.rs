@@ -0,0 +1,449 @@
+use std::f64::consts::PI;
+
+use bevy::math::{const_dvec3, DVec3};
+
+use crate::simulation::BodyState;
+
+/// Orbits are measured against this pole. Bodies are set up orbiting clockwise when seen from +Y,
+/// so the pole points down -Y to keep them prograde.
+pub const REFERENCE_POLE: DVec3 = const_dvec3!([0., -1., 0.]);
+
+/// Direction in the reference plane that longitudes are measured from.
+pub const REFERENCE_DIRECTION: DVec3 = DVec3::X;
+
+/// Eccentricities and inclinations below this are treated as circular and equatorial.
+const DEGENERATE_TOLERANCE: f64 = 1e-9;
+
+/// Newton iterations allowed when solving Kepler's equation.
+const KEPLER_ITERATIONS: usize = 50;
+
+/// Relative change in the universal anomaly at which Kepler's equation counts as solved.
+const KEPLER_TOLERANCE: f64 = 1e-12;
+
+/// Osculating Keplerian elements of a body relative to its primary.
+#[derive(Clone, Debug)]
+pub struct OrbitalElements {
+    /// Gravitational parameter of the two bodies, G * (m1 + m2).
+    pub mu: f64,
+    /// Negative for hyperbolic orbits, infinite for parabolic ones.
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    /// Radians between the orbit plane and the reference plane.
+    pub inclination: f64,
+    /// Radians from the reference direction to the ascending node.
+    pub longitude_of_ascending_node: f64,
+    /// Radians from the ascending node to periapsis.
+    pub argument_of_periapsis: f64,
+    /// Radians from periapsis to the current position.
+    pub true_anomaly: f64,
+    /// Unit vector towards periapsis.
+    pub periapsis_direction: DVec3,
+    /// Unit vector normal to the orbit plane, along the angular momentum.
+    pub normal: DVec3,
+    /// Specific angular momentum in m²/s, which stays finite on parabolic orbits.
+    pub angular_momentum: f64,
+}
+
+impl OrbitalElements {
+    /// Elements of the orbit through `position` with `velocity`, both relative to the primary.
+    pub fn from_state(position: DVec3, velocity: DVec3, mu: f64) -> OrbitalElements {
+        let radius = position.length();
+        let angular_momentum = position.cross(velocity);
+        let normal = angular_momentum.normalize_or_zero();
+
+        let eccentricity_vector =
+            velocity.cross(angular_momentum) / mu - position / radius.max(f64::MIN_POSITIVE);
+        let eccentricity = eccentricity_vector.length();
+
+        let energy = velocity.length_squared() / 2. - mu / radius;
+        let semi_major_axis = -mu / (2. * energy);
+
+        let inclination = normal.dot(REFERENCE_POLE).clamp(-1., 1.).acos();
+        let reference_y = REFERENCE_POLE.cross(REFERENCE_DIRECTION);
+        let node = REFERENCE_POLE.cross(angular_momentum);
+
+        // circular orbits measure from the node, equatorial ones from the reference direction
+        let node_direction = if node.length() > DEGENERATE_TOLERANCE * angular_momentum.length() {
+            node.normalize()
+        } else {
+            REFERENCE_DIRECTION
+        };
+        let periapsis_direction = if eccentricity > DEGENERATE_TOLERANCE {
+            eccentricity_vector / eccentricity
+        } else {
+            node_direction
+        };
+
+        let longitude_of_ascending_node = node_direction
+            .dot(reference_y)
+            .atan2(node_direction.dot(REFERENCE_DIRECTION))
+            .rem_euclid(2. * PI);
+        let argument_of_periapsis = signed_angle(node_direction, periapsis_direction, normal);
+        let true_anomaly = signed_angle(periapsis_direction, position, normal);
+
+        OrbitalElements {
+            mu,
+            semi_major_axis,
+            eccentricity,
+            inclination,
+            longitude_of_ascending_node,
+            argument_of_periapsis,
+            true_anomaly,
+            periapsis_direction,
+            normal,
+            angular_momentum: angular_momentum.length(),
+        }
+    }
+
+    /// Semi-latus rectum, the radius a quarter turn from periapsis. Taken from the angular
+    /// momentum rather than the semi-major axis, which is infinite on parabolic orbits.
+    pub fn semi_latus_rectum(&self) -> f64 {
+        self.angular_momentum * self.angular_momentum / self.mu
+    }
+
+    pub fn periapsis_distance(&self) -> f64 {
+        self.semi_latus_rectum() / (1. + self.eccentricity)
+    }
+
+    /// Apoapsis distance, for closed orbits only.
+    pub fn apoapsis_distance(&self) -> Option<f64> {
+        if self.is_closed() {
+            Some(self.semi_major_axis * (1. + self.eccentricity))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.eccentricity < 1.
+    }
+
+    /// Orbital period in seconds, for closed orbits only.
+    pub fn period(&self) -> Option<f64> {
+        if self.is_closed() {
+            Some(2. * PI * (self.semi_major_axis.powi(3) / self.mu).sqrt())
+        } else {
+            None
+        }
+    }
+
+    /// Largest true anomaly reached by an open orbit, where it heads off to infinity.
+    pub fn asymptote_anomaly(&self) -> Option<f64> {
+        if self.is_closed() {
+            None
+        } else {
+            Some((-1. / self.eccentricity).acos())
+        }
+    }
+
+    /// Position relative to the primary at a true anomaly, in radians from periapsis.
+    pub fn position_at(&self, true_anomaly: f64) -> DVec3 {
+        let radius = self.semi_latus_rectum() / (1. + self.eccentricity * true_anomaly.cos());
+        let q = self.normal.cross(self.periapsis_direction);
+        (self.periapsis_direction * true_anomaly.cos() + q * true_anomaly.sin()) * radius
+    }
+
+    /// Velocity relative to the primary at a true anomaly, in radians from periapsis.
+    pub fn velocity_at(&self, true_anomaly: f64) -> DVec3 {
+        let q = self.normal.cross(self.periapsis_direction);
+        (q * (self.eccentricity + true_anomaly.cos())
+            - self.periapsis_direction * true_anomaly.sin())
+            * (self.mu / self.semi_latus_rectum()).sqrt()
+    }
+
+    /// True anomaly of the ascending node, or `None` for equatorial orbits without nodes.
+    pub fn ascending_node_anomaly(&self) -> Option<f64> {
+        if self.inclination < DEGENERATE_TOLERANCE || PI - self.inclination < DEGENERATE_TOLERANCE {
+            return None;
+        }
+        Some(-self.argument_of_periapsis)
+    }
+
+    /// Whether a true anomaly lies on the orbit, which for open orbits excludes the far side.
+    pub fn reaches(&self, true_anomaly: f64) -> bool {
+        match self.asymptote_anomaly() {
+            Some(limit) => {
+                let wrapped = (true_anomaly + PI).rem_euclid(2. * PI) - PI;
+                wrapped.abs() < limit
+            }
+            None => true,
+        }
+    }
+}
+
+/// Angle from `from` to `to` around `axis`, in `[0, 2π)`.
+fn signed_angle(from: DVec3, to: DVec3, axis: DVec3) -> f64 {
+    from.cross(to)
+        .dot(axis)
+        .atan2(from.dot(to))
+        .rem_euclid(2. * PI)
+}
+
+/// Radius of the Laplace sphere of influence of a body of `mass` orbiting `primary_mass` at `distance`.
+pub fn laplace_radius(distance: f64, mass: f64, primary_mass: f64) -> f64 {
+    distance * (mass / primary_mass).powf(2. / 5.)
+}
+
+/// Radius of the Hill sphere of a body of `mass` orbiting `primary_mass` at `distance`.
+pub fn hill_radius(distance: f64, mass: f64, primary_mass: f64) -> f64 {
+    distance * (mass / (3. * primary_mass)).cbrt()
+}
+
+/// Index of the body each body orbits with the Laplace radius of its own sphere of influence,
+/// found by walking down the hierarchy from the most massive body. The most massive body has no
+/// primary and an infinite sphere of influence.
+pub fn spheres_of_influence(bodies: &[BodyState]) -> Vec<(Option<usize>, f64)> {
+    let mut order: Vec<usize> = (0..bodies.len()).collect();
+    order.sort_by(|a, b| bodies[*b].mass.total_cmp(&bodies[*a].mass));
+
+    let mut primaries = vec![None; bodies.len()];
+    let mut influence = vec![f64::INFINITY; bodies.len()];
+
+    for (rank, &body) in order.iter().enumerate() {
+        // the innermost sphere of influence of a heavier body that contains this one
+        let primary = order[..rank]
+            .iter()
+            .copied()
+            .filter(|&candidate| distance(&bodies[body], &bodies[candidate]) < influence[candidate])
+            .min_by(|a, b| influence[*a].total_cmp(&influence[*b]));
+
+        if let Some(primary) = primary {
+            influence[body] = laplace_radius(
+                distance(&bodies[body], &bodies[primary]),
+                bodies[body].mass.to_f64(),
+                bodies[primary].mass.to_f64(),
+            );
+        }
+        primaries[body] = primary;
+    }
+
+    primaries.into_iter().zip(influence).collect()
+}
+
+/// Index of the body each body orbits, see [`spheres_of_influence`].
+pub fn primaries(bodies: &[BodyState]) -> Vec<Option<usize>> {
+    spheres_of_influence(bodies)
+        .into_iter()
+        .map(|(primary, _)| primary)
+        .collect()
+}
+
+/// Stumpff functions C(z) and S(z) of the universal variable formulation.
+fn stumpff(z: f64) -> (f64, f64) {
+    if z.abs() < 1e-6 {
+        // series, the closed forms lose everything to cancellation near zero
+        (1. / 2. - z / 24., 1. / 6. - z / 120.)
+    } else if z > 0. {
+        let root = z.sqrt();
+        ((1. - root.cos()) / z, (root - root.sin()) / root.powi(3))
+    } else {
+        let root = (-z).sqrt();
+        ((root.cosh() - 1.) / -z, (root.sinh() - root) / root.powi(3))
+    }
+}
+
+/// Two-body position and velocity `time` seconds after `position` and `velocity`, both relative
+/// to the primary, solving Kepler's equation in universal variables so any conic works.
+pub fn kepler_propagate(position: DVec3, velocity: DVec3, mu: f64, time: f64) -> (DVec3, DVec3) {
+    let radius = position.length();
+    if radius <= 0. || time == 0. {
+        return (position, velocity);
+    }
+    let root_mu = mu.sqrt();
+    let radial_velocity = position.dot(velocity) / radius;
+    // reciprocal of the semi-major axis, zero for parabolas
+    let alpha = 2. / radius - velocity.length_squared() / mu;
+
+    let mut chi = root_mu * alpha.abs() * time;
+    if !chi.is_finite() || chi == 0. {
+        chi = root_mu * time / radius;
+    }
+    for _ in 0..KEPLER_ITERATIONS {
+        let z = alpha * chi * chi;
+        let (c, s) = stumpff(z);
+        let f = radius * radial_velocity / root_mu * chi * chi * c
+            + (1. - alpha * radius) * chi.powi(3) * s
+            + radius * chi
+            - root_mu * time;
+        let df = radius * radial_velocity / root_mu * chi * (1. - z * s)
+            + (1. - alpha * radius) * chi * chi * c
+            + radius;
+        let delta = f / df;
+        chi -= delta;
+        if delta.abs() < KEPLER_TOLERANCE * chi.abs().max(1.) {
+            break;
+        }
+    }
+
+    let z = alpha * chi * chi;
+    let (c, s) = stumpff(z);
+    let f = 1. - chi * chi / radius * c;
+    let g = time - chi.powi(3) / root_mu * s;
+    let new_position = f * position + g * velocity;
+    let new_radius = new_position.length();
+    let f_dot = root_mu / (new_radius * radius) * (z * s - 1.) * chi;
+    let g_dot = 1. - chi * chi / new_radius * c;
+
+    (new_position, f_dot * position + g_dot * velocity)
+}
+
+fn distance(a: &BodyState, b: &BodyState) -> f64 {
+    a.translation.distance(&b.translation).to_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EARTH_MU: f64 = 3.986004418e14;
+
+    const PERIAPSIS: f64 = 7_000_000.;
+
+    fn assert_close(actual: DVec3, expected: DVec3, tolerance: f64) {
+        assert!(
+            (actual - expected).length() <= tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    /// State at periapsis of an equatorial prograde orbit of `eccentricity`.
+    fn periapsis_state(eccentricity: f64) -> (DVec3, DVec3) {
+        let speed = (EARTH_MU * (1. + eccentricity) / PERIAPSIS).sqrt();
+        (DVec3::X * PERIAPSIS, DVec3::Z * speed)
+    }
+
+    /// Solves `anomaly` = `equation`(x) for x by Newton's method from `anomaly`.
+    fn solve(anomaly: f64, equation: impl Fn(f64) -> (f64, f64)) -> f64 {
+        let mut x = anomaly;
+        for _ in 0..100 {
+            let (value, slope) = equation(x);
+            x -= (value - anomaly) / slope;
+        }
+        x
+    }
+
+    #[test]
+    fn reads_elements_of_a_known_orbit() {
+        let (position, velocity) = periapsis_state(0.3);
+        let elements = OrbitalElements::from_state(position, velocity, EARTH_MU);
+
+        assert!((elements.eccentricity - 0.3).abs() < 1e-12);
+        assert!((elements.semi_major_axis - PERIAPSIS / 0.7).abs() < 1e-3);
+        assert!((elements.periapsis_distance() - PERIAPSIS).abs() < 1e-3);
+        assert!((elements.apoapsis_distance().unwrap() - PERIAPSIS * 1.3 / 0.7).abs() < 1e-3);
+        assert!(elements.inclination < 1e-12);
+        assert!(elements.true_anomaly.min(2. * PI - elements.true_anomaly) < 1e-12);
+    }
+
+    #[test]
+    fn elements_give_back_the_state() {
+        let position = DVec3::new(6_500_000., 1_200_000., -2_000_000.);
+        for speed in [6_000., 8_000., 11_000., 14_000.] {
+            let velocity = DVec3::new(1_000., -3_000., 7_000.).normalize() * speed;
+            let elements = OrbitalElements::from_state(position, velocity, EARTH_MU);
+
+            assert_close(elements.position_at(elements.true_anomaly), position, 1e-3);
+            assert_close(elements.velocity_at(elements.true_anomaly), velocity, 1e-6);
+        }
+
+        // parabolic, whose semi-major axis is infinite
+        let (position, velocity) = periapsis_state(1.);
+        let elements = OrbitalElements::from_state(position, velocity, EARTH_MU);
+        assert!((elements.eccentricity - 1.).abs() < 1e-12);
+        assert_close(
+            elements.position_at(PI / 2.),
+            DVec3::Z * 2. * PERIAPSIS,
+            1e-3,
+        );
+        assert_close(elements.velocity_at(elements.true_anomaly), velocity, 1e-6);
+    }
+
+    #[test]
+    fn propagates_ellipses_along_keplers_equation() {
+        let eccentricity = 0.3;
+        let (position, velocity) = periapsis_state(eccentricity);
+        let elements = OrbitalElements::from_state(position, velocity, EARTH_MU);
+        let mean_motion = (EARTH_MU / elements.semi_major_axis.powi(3)).sqrt();
+
+        for time in [600., 2_000., 5_000.] {
+            let eccentric = solve(mean_motion * time, |x| {
+                (x - eccentricity * x.sin(), 1. - eccentricity * x.cos())
+            });
+            let true_anomaly = 2.
+                * (((1. + eccentricity) / (1. - eccentricity)).sqrt() * (eccentric / 2.).tan())
+                    .atan();
+            let (new_position, new_velocity) = kepler_propagate(position, velocity, EARTH_MU, time);
+
+            assert_close(new_position, elements.position_at(true_anomaly), 1e-2);
+            assert_close(new_velocity, elements.velocity_at(true_anomaly), 1e-5);
+        }
+    }
+
+    #[test]
+    fn propagates_hyperbolas_along_keplers_equation() {
+        let eccentricity = 1.8;
+        let (position, velocity) = periapsis_state(eccentricity);
+        let elements = OrbitalElements::from_state(position, velocity, EARTH_MU);
+        let mean_motion = (EARTH_MU / (-elements.semi_major_axis).powi(3)).sqrt();
+
+        for time in [-3_000., 600., 20_000.] {
+            let hyperbolic = solve(mean_motion * time, |x| {
+                (eccentricity * x.sinh() - x, eccentricity * x.cosh() - 1.)
+            });
+            let true_anomaly = 2.
+                * (((eccentricity + 1.) / (eccentricity - 1.)).sqrt() * (hyperbolic / 2.).tanh())
+                    .atan();
+            let (new_position, _) = kepler_propagate(position, velocity, EARTH_MU, time);
+
+            assert_close(new_position, elements.position_at(true_anomaly), 1e-2);
+        }
+    }
+
+    #[test]
+    fn propagates_parabolas_along_barkers_equation() {
+        let (position, velocity) = periapsis_state(1.);
+        let elements = OrbitalElements::from_state(position, velocity, EARTH_MU);
+        let semi_latus_rectum = elements.semi_latus_rectum();
+
+        for time in [-5_000., 1_000., 50_000.] {
+            // t = √(p³/μ) (D + D³/3) / 2 with D = tan(ν/2)
+            let scaled = 2. * time * (EARTH_MU / semi_latus_rectum.powi(3)).sqrt();
+            let d = solve(scaled, |x| (x + x.powi(3) / 3., 1. + x * x));
+            let (new_position, _) = kepler_propagate(position, velocity, EARTH_MU, time);
+
+            assert_close(new_position, elements.position_at(2. * d.atan()), 1.);
+        }
+    }
+
+    #[test]
+    fn propagation_runs_back_to_the_start() {
+        let position = DVec3::new(6_500_000., 1_200_000., -2_000_000.);
+        for eccentricity in [0.01, 0.7, 1. - 1e-9, 1. + 1e-9, 3.] {
+            // speed at this radius for the eccentricity, launched along the periapsis direction
+            let radius = position.length();
+            let speed = (EARTH_MU * (1. + eccentricity) / radius).sqrt();
+            let velocity = position.cross(DVec3::Y).normalize() * speed;
+
+            let (there, there_velocity) = kepler_propagate(position, velocity, EARTH_MU, 7_200.);
+            let (back, back_velocity) = kepler_propagate(there, there_velocity, EARTH_MU, -7_200.);
+
+            assert_close(back, position, 1e-2);
+            assert_close(back_velocity, velocity, 1e-5);
+        }
+    }
+
+    #[test]
+    fn full_period_comes_back_round() {
+        let position = DVec3::new(6_500_000., 1_200_000., -2_000_000.);
+        let velocity = DVec3::new(1_000., -3_000., 7_000.).normalize() * 8_500.;
+        let period = OrbitalElements::from_state(position, velocity, EARTH_MU)
+            .period()
+            .unwrap();
+
+        let (after, after_velocity) = kepler_propagate(position, velocity, EARTH_MU, period);
+        assert_close(after, position, 1e-2);
+        assert_close(after_velocity, velocity, 1e-5);
+    }
+}
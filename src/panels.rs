@@ -0,0 +1,1380 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{math::DVec3, prelude::*, tasks::AsyncComputeTaskPool};
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContext, EguiPlugin},
+    egui,
+};
+
+use crate::{
+    atmosphere::{Atmosphere, AtmosphereModel},
+    camera::{Focused, Targeted},
+    conic::Conics,
+    covariance::{principal_axes, Covariance},
+    engine::{Attitude, Engine},
+    flyby::{Encounter, Flyby, FlybySearch},
+    gravity::GravityField,
+    maneuver::{frame, ManeuverNode},
+    porkchop::PorkchopGrid,
+    relative::{cw_propagate, cw_rendezvous, node_components, range_rate, relative_to_target},
+    satellite::{POSITION_SIGMA, VELOCITY_SIGMA},
+    simulation::{
+        body_states, AccelerationBreakdown, ForceSource, GravitySettings, HPVec3,
+        PhysicalProperties, ReferenceFrame, Simulated, SimulationClock,
+    },
+    targeting::{correct, Control, Goal, Outcome, Shooter},
+    transfer::{bi_elliptic, hohmann, phasing_wait, Transfer},
+    ui::RenderInUI,
+    variational::{sensitivity, StateMatrix, StateTransition},
+};
+
+/// Where the transfer planner is headed.
+struct TransferPlanner {
+    /// Body to meet, whose orbit sets the target radius.
+    target: Option<Entity>,
+    /// Circular orbit radius in km to go to when there's no target body.
+    radius: f64,
+    /// Radius in km the bi-elliptic transfer swings out to.
+    intermediate_radius: f64,
+}
+
+impl Default for TransferPlanner {
+    fn default() -> Self {
+        TransferPlanner {
+            target: None,
+            // geostationary
+            radius: 42_164.,
+            intermediate_radius: 100_000.,
+        }
+    }
+}
+
+/// Departure and arrival bodies and the search ranges of the porkchop plot.
+struct PorkchopPlanner {
+    from: Option<Entity>,
+    to: Option<Entity>,
+    grid: PorkchopGrid,
+    /// CSV file the grid is exported to, with the heatmap next to it.
+    output: String,
+    /// Where the scan running in the background leaves its result.
+    scan: Option<Arc<Mutex<Option<String>>>>,
+    /// Result of the last export.
+    status: String,
+}
+
+impl Default for PorkchopPlanner {
+    fn default() -> Self {
+        PorkchopPlanner {
+            from: None,
+            to: None,
+            grid: PorkchopGrid::default(),
+            output: "porkchop.csv".to_string(),
+            scan: None,
+            status: String::new(),
+        }
+    }
+}
+
+/// Transfer time of the Clohessy–Wiltshire rendezvous.
+struct RendezvousPlanner {
+    /// Minutes from the first burn to arriving on the target.
+    transfer_time: f64,
+}
+
+impl Default for RendezvousPlanner {
+    fn default() -> Self {
+        RendezvousPlanner {
+            // under half a low earth orbit, clear of where the targeting breaks down
+            transfer_time: 40.,
+        }
+    }
+}
+
+/// Planet to fly by and the B-plane point to aim for.
+struct FlybyPlanner {
+    planet: Option<Entity>,
+    /// Days after the burn searched for the encounter.
+    horizon: f64,
+    /// B-plane target in km.
+    b_t: f64,
+    b_r: f64,
+    /// Encounter found by the last search.
+    encounter: Option<Encounter>,
+    status: String,
+    /// Search or targeting running on the task pool.
+    running: Option<Arc<Mutex<Option<FlybyResult>>>>,
+}
+
+/// What a flyby search or targeting run came back with.
+struct FlybyResult {
+    encounter: Option<Encounter>,
+    status: String,
+    /// Maneuver node to change, and the burn it was corrected to.
+    corrected: Option<(Entity, ManeuverNode)>,
+}
+
+impl Default for FlybyPlanner {
+    fn default() -> Self {
+        FlybyPlanner {
+            planet: None,
+            // long enough to reach the moon
+            horizon: 10.,
+            b_t: 0.,
+            b_r: 10_000.,
+            encounter: None,
+            status: String::new(),
+            running: None,
+        }
+    }
+}
+
+/// Goals and controls of the differential corrector, and what it reported last.
+struct TargetingPlanner {
+    /// Periapsis altitude in km right after the burn.
+    periapsis_altitude: Option<f64>,
+    /// Inclination in degrees right after the burn.
+    inclination: Option<f64>,
+    /// Hours from now at which to arrive on the target.
+    arrival: Option<f64>,
+    /// Which of `Control::ALL` may change.
+    controls: [bool; 4],
+    report: Vec<String>,
+}
+
+impl Default for TargetingPlanner {
+    fn default() -> Self {
+        TargetingPlanner {
+            periapsis_altitude: Some(400.),
+            inclination: None,
+            arrival: None,
+            controls: [true, true, true, false],
+            report: Vec::new(),
+        }
+    }
+}
+
+/// Starting uncertainty restored on the focused body.
+struct UncertaintyPlanner {
+    /// Position error in meters along each axis.
+    position_sigma: f64,
+    /// Velocity error in m/s along each axis.
+    velocity_sigma: f64,
+}
+
+impl Default for UncertaintyPlanner {
+    fn default() -> Self {
+        UncertaintyPlanner {
+            position_sigma: POSITION_SIGMA,
+            velocity_sigma: VELOCITY_SIGMA,
+        }
+    }
+}
+
+/// Points along the drawn relative paths.
+const RELATIVE_PATH_POINTS: usize = 200;
+
+/// Plugin used to show inspector panels for the focused body.
+pub struct PanelsPlugin;
+
+impl Plugin for PanelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(EguiPlugin);
+        app.add_system(acceleration_panel);
+        app.add_system(gravity_field_panel);
+        app.add_system(softening_panel);
+        app.add_system(atmosphere_panel);
+        app.add_system(maneuver_panel);
+        app.add_system(engine_panel);
+        app.init_resource::<TransferPlanner>();
+        app.add_system(transfer_panel);
+        app.init_resource::<PorkchopPlanner>();
+        app.add_system(porkchop_panel);
+        app.init_resource::<RendezvousPlanner>();
+        app.add_system(rendezvous_panel);
+        app.init_resource::<FlybyPlanner>();
+        app.add_system(flyby_panel);
+        app.init_resource::<TargetingPlanner>();
+        app.add_system(targeting_panel);
+        app.init_resource::<UncertaintyPlanner>();
+        app.add_system(sensitivity_panel);
+    }
+}
+
+/// Acceleration a body gets from `source`, or zero if it doesn't feel it.
+fn source_acceleration(breakdown: &AccelerationBreakdown, source: ForceSource) -> DVec3 {
+    breakdown
+        .0
+        .iter()
+        .filter(|(other, _)| *other == source)
+        .fold(DVec3::ZERO, |sum, (_, acceleration)| sum + *acceleration)
+}
+
+/// Lists what accelerates the focused body. Third bodies also show their tidal part, what's left
+/// after taking away how hard they pull on the central body.
+fn acceleration_panel(
+    mut egui_context: ResMut<EguiContext>,
+    conics: Res<Conics>,
+    focused_query: Query<(Entity, &AccelerationBreakdown), With<Focused>>,
+    breakdown_query: Query<&AccelerationBreakdown>,
+    name_query: Query<&RenderInUI>,
+) {
+    let (body, breakdown) = match focused_query.get_single() {
+        Ok(focused) => focused,
+        Err(_) => return,
+    };
+    let primary = conics
+        .0
+        .iter()
+        .find(|conic| conic.body == body)
+        .map(|conic| conic.primary);
+    let primary_breakdown = primary.and_then(|primary| breakdown_query.get(primary).ok());
+
+    let name = |entity: Entity| match name_query.get(entity) {
+        Ok(name) => name.0.clone(),
+        Err(_) => format!("{:?}", entity),
+    };
+    let central = primary
+        .map(|primary| source_acceleration(breakdown, ForceSource::Gravity(primary)).length())
+        .unwrap_or_default();
+    let total = breakdown
+        .0
+        .iter()
+        .fold(DVec3::ZERO, |sum, (_, acceleration)| sum + *acceleration);
+
+    let mut sources = breakdown.0.clone();
+    sources.sort_by(|(_, a), (_, b)| b.length().total_cmp(&a.length()));
+
+    egui::Window::new("Accelerations").show(egui_context.ctx_mut(), |ui| {
+        egui::Grid::new("acceleration_sources")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("source");
+                ui.label("m/s²");
+                ui.label("tidal m/s²");
+                ui.label("of central");
+                ui.end_row();
+
+                for (source, acceleration) in sources.iter() {
+                    let (label, tidal) = match *source {
+                        ForceSource::Gravity(other) if Some(other) == primary => {
+                            (format!("central {}", name(other)), None)
+                        }
+                        ForceSource::Gravity(other) => {
+                            let tidal = primary_breakdown.map(|primary_breakdown| {
+                                *acceleration - source_acceleration(primary_breakdown, *source)
+                            });
+                            (format!("third body {}", name(other)), tidal)
+                        }
+                        ForceSource::GravityField(other) => {
+                            (format!("{} gravity field", name(other)), None)
+                        }
+                        ForceSource::Drag(other) => (format!("drag in {}", name(other)), None),
+                        ForceSource::RadiationPressure(other) => {
+                            (format!("radiation from {}", name(other)), None)
+                        }
+                        ForceSource::PostNewtonian(other) => {
+                            (format!("{} relativity", name(other)), None)
+                        }
+                        ForceSource::Thrust(_) => ("thrust".to_string(), None),
+                    };
+
+                    ui.label(label);
+                    ui.label(format!("{:.3e}", acceleration.length()));
+                    ui.label(match tidal {
+                        Some(tidal) => format!("{:.3e}", tidal.length()),
+                        None => String::new(),
+                    });
+                    ui.label(if central > 0. {
+                        format!("{:.2e}", acceleration.length() / central)
+                    } else {
+                        String::new()
+                    });
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+        ui.label(format!("total {:.3e} m/s²", total.length()));
+    });
+}
+
+/// Sets the degree the focused body's gravity field is truncated at.
+fn gravity_field_panel(
+    mut egui_context: ResMut<EguiContext>,
+    mut field_query: Query<&mut GravityField, With<Focused>>,
+) {
+    let mut field = match field_query.get_single_mut() {
+        Ok(field) => field,
+        Err(_) => return,
+    };
+
+    let mut degree = field.degree;
+    egui::Window::new("Gravity field").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("degree");
+            ui.add(egui::DragValue::new(&mut degree).clamp_range(2..=field.loaded_degree()));
+        });
+        ui.label(format!("loaded up to degree {}", field.loaded_degree()));
+    });
+    // only touch the field when edited, so it isn't marked changed every frame
+    if degree != field.degree {
+        field.degree = degree;
+    }
+}
+
+/// Sets the Plummer softening length of gravity between every pair of bodies.
+fn softening_panel(mut egui_context: ResMut<EguiContext>, mut settings: ResMut<GravitySettings>) {
+    let mut softening = settings.softening / 1000.;
+    egui::Window::new("Softening").show(egui_context.ctx_mut(), |ui| {
+        ui.add(
+            egui::DragValue::new(&mut softening)
+                .clamp_range(0. ..=f64::MAX)
+                .speed(1.)
+                .prefix("length ")
+                .suffix(" km"),
+        );
+        if softening == 0. {
+            ui.label("bodies meeting pause the simulation");
+        }
+    });
+    // only touch the settings when edited, so they aren't marked changed every frame
+    if softening * 1000. != settings.softening {
+        settings.softening = softening * 1000.;
+    }
+}
+
+/// Altitude in meters an exponential atmosphere is fitted at when switching to it.
+const EXPONENTIAL_FIT_ALTITUDE: f64 = 400_000.;
+
+/// Moderate solar flux and geomagnetic index the Jacchia model starts from.
+const MEAN_SOLAR_FLUX: f64 = 150.;
+const MEAN_GEOMAGNETIC_INDEX: f64 = 15.;
+
+/// Chooses the focused body's atmosphere model and sets its inputs.
+fn atmosphere_panel(
+    mut egui_context: ResMut<EguiContext>,
+    mut atmosphere_query: Query<&mut Atmosphere, With<Focused>>,
+) {
+    let mut atmosphere = match atmosphere_query.get_single_mut() {
+        Ok(atmosphere) => atmosphere,
+        Err(_) => return,
+    };
+
+    let mut edited = atmosphere.clone();
+    egui::Window::new("Atmosphere").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            let exponential = matches!(edited.model, AtmosphereModel::Exponential(_));
+            if ui.radio(exponential, "exponential").clicked() && !exponential {
+                edited.model =
+                    AtmosphereModel::Exponential(edited.exponential_fit(EXPONENTIAL_FIT_ALTITUDE));
+            }
+            let layered = matches!(edited.model, AtmosphereModel::Layered(_));
+            if ui.radio(layered, "US Standard 1976").clicked() && !layered {
+                edited = Atmosphere::us_standard_1976();
+            }
+            let jacchia = matches!(edited.model, AtmosphereModel::Jacchia { .. });
+            if ui.radio(jacchia, "Jacchia").clicked() && !jacchia {
+                edited = Atmosphere::jacchia(MEAN_SOLAR_FLUX, MEAN_GEOMAGNETIC_INDEX);
+            }
+        });
+
+        match &mut edited.model {
+            AtmosphereModel::Exponential(layer) => {
+                let mut base_altitude = layer.base_altitude / 1000.;
+                let mut scale_height = layer.scale_height / 1000.;
+                let density_speed = layer.base_density * 0.01;
+                egui::Grid::new("exponential_atmosphere").show(ui, |ui| {
+                    ui.label("base altitude km");
+                    ui.add(egui::DragValue::new(&mut base_altitude).clamp_range(0.0..=f64::MAX));
+                    ui.end_row();
+                    ui.label("base density kg/m³");
+                    ui.add(
+                        egui::DragValue::new(&mut layer.base_density)
+                            .speed(density_speed)
+                            .clamp_range(0.0..=f64::MAX),
+                    );
+                    ui.end_row();
+                    ui.label("scale height km");
+                    ui.add(egui::DragValue::new(&mut scale_height).clamp_range(0.1..=f64::MAX));
+                    ui.end_row();
+                });
+                layer.base_altitude = base_altitude * 1000.;
+                layer.scale_height = scale_height * 1000.;
+            }
+            AtmosphereModel::Layered(layers) => {
+                ui.label(format!("{} layers", layers.len()));
+            }
+            AtmosphereModel::Jacchia {
+                solar_flux,
+                geomagnetic_index,
+            } => {
+                egui::Grid::new("jacchia_atmosphere").show(ui, |ui| {
+                    ui.label("F10.7 sfu");
+                    ui.add(egui::DragValue::new(solar_flux).clamp_range(50.0..=400.0));
+                    ui.end_row();
+                    ui.label("Ap");
+                    ui.add(egui::DragValue::new(geomagnetic_index).clamp_range(0.0..=400.0));
+                    ui.end_row();
+                });
+            }
+        }
+
+        ui.separator();
+        for altitude in [200., 400., 600.] {
+            ui.label(format!(
+                "{:.0} km: {:.3e} kg/m³",
+                altitude,
+                edited.density(altitude * 1000.)
+            ));
+        }
+    });
+
+    // only touch the atmosphere when edited, so it isn't marked changed every frame
+    if edited.model != atmosphere.model {
+        *atmosphere = edited;
+    }
+}
+
+/// Lists the focused body's maneuver nodes for editing, with burn times counted from now.
+fn maneuver_panel(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    clock: Res<SimulationClock>,
+    focused_query: Query<Entity, With<Focused>>,
+    mut node_query: Query<(Entity, &mut ManeuverNode)>,
+) {
+    let body = match focused_query.get_single() {
+        Ok(body) => body,
+        Err(_) => return,
+    };
+
+    egui::Window::new("Maneuvers").show(egui_context.ctx_mut(), |ui| {
+        egui::Grid::new("maneuver_nodes")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("T+ s");
+                ui.label("prograde");
+                ui.label("normal");
+                ui.label("radial");
+                ui.label("m/s");
+                ui.end_row();
+
+                for (entity, mut node) in node_query.iter_mut() {
+                    if node.body != body {
+                        continue;
+                    }
+
+                    // edit a copy, so untouched nodes don't count as changed every frame
+                    let mut edited = node.clone();
+                    let mut countdown = edited.time - clock.elapsed;
+                    if ui
+                        .add(egui::DragValue::new(&mut countdown).speed(10.))
+                        .changed()
+                    {
+                        edited.time = clock.elapsed + countdown;
+                    }
+                    for component in [&mut edited.prograde, &mut edited.normal, &mut edited.radial]
+                    {
+                        ui.add(egui::DragValue::new(component).speed(0.1));
+                    }
+                    ui.label(format!("{:.1}", edited.delta_v()));
+                    if ui.button("remove").clicked() {
+                        commands.entity(entity).despawn();
+                    }
+                    ui.end_row();
+
+                    if edited != *node {
+                        *node = edited;
+                    }
+                }
+            });
+
+        if ui.button("add node").clicked() {
+            commands.spawn().insert(ManeuverNode {
+                body,
+                time: clock.elapsed + 600.,
+                prograde: 0.,
+                normal: 0.,
+                radial: 0.,
+            });
+        }
+    });
+}
+
+/// Throttle and attitude controls for the focused body's engine.
+fn engine_panel(
+    mut egui_context: ResMut<EguiContext>,
+    conics: Res<Conics>,
+    mut focused_query: Query<(Entity, &mut Engine, &PhysicalProperties), With<Focused>>,
+    primary_query: Query<&PhysicalProperties>,
+) {
+    let (body, mut engine, properties) = match focused_query.get_single_mut() {
+        Ok(focused) => focused,
+        Err(_) => return,
+    };
+    let mass = properties.mass.to_f64();
+
+    // inertial attitude is held along wherever prograde points when it's picked
+    let prograde = conics
+        .0
+        .iter()
+        .find(|conic| conic.body == body)
+        .and_then(|conic| primary_query.get(conic.primary).ok())
+        .map(|primary| {
+            let position = HPVec3::sub(&properties.translation, &primary.translation);
+            let velocity = HPVec3::sub(&properties.acceleration, &primary.acceleration);
+            frame(position.to_dvec3(), velocity.to_dvec3()).0
+        });
+
+    egui::Window::new("Engine").show(egui_context.ctx_mut(), |ui| {
+        let mut edited = engine.clone();
+        ui.add(egui::Slider::new(&mut edited.throttle, 0.0..=1.0).text("throttle"));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut edited.attitude, Attitude::Prograde, "prograde");
+            ui.radio_value(&mut edited.attitude, Attitude::Retrograde, "retrograde");
+            ui.radio_value(&mut edited.attitude, Attitude::Normal, "normal");
+            let inertial = matches!(edited.attitude, Attitude::InertialFixed(_));
+            if ui.radio(inertial, "inertial").clicked() && !inertial {
+                if let Some(prograde) = prograde {
+                    edited.attitude = Attitude::InertialFixed(prograde);
+                }
+            }
+        });
+        ui.separator();
+        ui.label(format!(
+            "propellant {:.1} kg, {:.3} kg/s\ndelta-v left {:.1} m/s",
+            edited.propellant(mass),
+            edited.mass_flow(),
+            edited.delta_v(mass)
+        ));
+
+        if edited.throttle != engine.throttle || edited.attitude != engine.attitude {
+            *engine = edited;
+        }
+    });
+}
+
+/// Hohmann and bi-elliptic transfers from the focused body's orbit to a circular orbit or another
+/// body around the same primary, treating both orbits as circular at their osculating semi-major
+/// axes.
+fn transfer_panel(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut planner: ResMut<TransferPlanner>,
+    clock: Res<SimulationClock>,
+    conics: Res<Conics>,
+    focused_query: Query<Entity, With<Focused>>,
+    name_query: Query<&RenderInUI>,
+) {
+    let conic = match focused_query
+        .get_single()
+        .ok()
+        .and_then(|focused| conics.0.iter().find(|conic| conic.body == focused))
+    {
+        Some(conic) => conic,
+        None => return,
+    };
+    let name = |entity: Entity| match name_query.get(entity) {
+        Ok(name) => name.0.clone(),
+        Err(_) => format!("{:?}", entity),
+    };
+    let elements = &conic.elements;
+    let mu = elements.mu;
+
+    let targets: Vec<_> = conics
+        .0
+        .iter()
+        .filter(|other| other.primary == conic.primary && other.body != conic.body)
+        .collect();
+    let target = planner
+        .target
+        .and_then(|target| targets.iter().find(|other| other.body == target));
+    let radius = match target {
+        Some(target) => target.elements.semi_major_axis,
+        None => planner.radius * 1000.,
+    };
+
+    // the bi-elliptic transfer has to swing out past both orbits
+    let min_intermediate = elements.semi_major_axis.max(radius) / 1000.;
+    planner.intermediate_radius = planner.intermediate_radius.max(min_intermediate);
+
+    let hohmann = hohmann(mu, elements.semi_major_axis, radius);
+    let bi_elliptic = bi_elliptic(
+        mu,
+        elements.semi_major_axis,
+        radius,
+        planner.intermediate_radius * 1000.,
+    );
+    // meeting a body means waiting for it to line up
+    let wait = |transfer: &Transfer| match target {
+        Some(target) => phasing_wait(
+            transfer,
+            elements.position_at(elements.true_anomaly),
+            elements.velocity_at(elements.true_anomaly),
+            target.elements.position_at(target.elements.true_anomaly),
+            target.elements.velocity_at(target.elements.true_anomaly),
+        ),
+        None => Some(0.),
+    };
+
+    egui::Window::new("Transfer").show(egui_context.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("target")
+            .selected_text(match target {
+                Some(target) => name(target.body),
+                None => "circular orbit".to_string(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut planner.target, None, "circular orbit");
+                for other in targets.iter() {
+                    ui.selectable_value(&mut planner.target, Some(other.body), name(other.body));
+                }
+            });
+        if target.is_none() {
+            ui.add(
+                egui::DragValue::new(&mut planner.radius)
+                    .speed(100.)
+                    .suffix(" km"),
+            );
+        }
+        ui.add(
+            egui::DragValue::new(&mut planner.intermediate_radius)
+                .speed(1000.)
+                .clamp_range(min_intermediate..=f64::MAX)
+                .prefix("bi-elliptic out to ")
+                .suffix(" km"),
+        );
+        ui.separator();
+
+        let transfers = [
+            ("Hohmann", Some(&hohmann)),
+            ("bi-elliptic", bi_elliptic.as_ref()),
+        ];
+        for (label, transfer) in transfers {
+            let transfer = match transfer {
+                Some(transfer) => transfer,
+                None => {
+                    ui.label(format!(
+                        "{} has to swing out past {:.0} km",
+                        label, min_intermediate
+                    ));
+                    continue;
+                }
+            };
+            let wait = wait(transfer);
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} {:.1} m/s, {:.2} h{}",
+                    label,
+                    transfer.delta_v(),
+                    transfer.time_of_flight / 3600.,
+                    match (target, wait) {
+                        (Some(_), Some(wait)) => format!(" after {:.2} h", wait / 3600.),
+                        _ => String::new(),
+                    }
+                ));
+                if let Some(wait) = wait {
+                    if ui.button("create nodes").clicked() {
+                        for node in transfer.nodes(conic.body, clock.elapsed + wait) {
+                            commands.spawn().insert(node);
+                        }
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Scans launch windows between two bodies around the same primary on the async compute pool,
+/// exporting the C3 and arrival v∞ grid as CSV and a heatmap as PNG.
+fn porkchop_panel(
+    mut egui_context: ResMut<EguiContext>,
+    mut planner: ResMut<PorkchopPlanner>,
+    task_pool: Res<AsyncComputeTaskPool>,
+    conics: Res<Conics>,
+    name_query: Query<&RenderInUI>,
+) {
+    let finished = planner
+        .scan
+        .as_ref()
+        .and_then(|scan| scan.lock().ok()?.take());
+    if let Some(status) = finished {
+        planner.status = status;
+        planner.scan = None;
+    }
+
+    let name = |entity: Entity| match name_query.get(entity) {
+        Ok(name) => name.0.clone(),
+        Err(_) => format!("{:?}", entity),
+    };
+    let conic = |entity: Option<Entity>| {
+        entity.and_then(|entity| conics.0.iter().find(|conic| conic.body == entity))
+    };
+    let named = |entity: Option<Entity>| entity.map(name).unwrap_or_default();
+    let from = conic(planner.from);
+    let to = conic(planner.to).filter(|to| Some(to.primary) == from.map(|from| from.primary));
+
+    egui::Window::new("Porkchop").show(egui_context.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("from")
+            .selected_text(named(from.map(|from| from.body)))
+            .show_ui(ui, |ui| {
+                for conic in conics.0.iter() {
+                    ui.selectable_value(&mut planner.from, Some(conic.body), name(conic.body));
+                }
+            });
+        egui::ComboBox::from_label("to")
+            .selected_text(named(to.map(|to| to.body)))
+            .show_ui(ui, |ui| {
+                for conic in conics.0.iter().filter(|conic| {
+                    Some(conic.primary) == from.map(|from| from.primary)
+                        && Some(conic.body) != from.map(|from| from.body)
+                }) {
+                    ui.selectable_value(&mut planner.to, Some(conic.body), name(conic.body));
+                }
+            });
+        let grid = &mut planner.grid;
+        ui.add(
+            egui::DragValue::new(&mut grid.departure_span)
+                .prefix("departures over ")
+                .suffix(" d"),
+        );
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut grid.min_time_of_flight)
+                    .prefix("flights of ")
+                    .suffix(" d"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut grid.max_time_of_flight)
+                    .prefix("to ")
+                    .suffix(" d"),
+            );
+        });
+        ui.add(egui::Slider::new(&mut grid.resolution, 10..=400).text("cells"));
+        ui.add(egui::Slider::new(&mut grid.max_revolutions, 0..=3).text("revolutions"));
+        ui.horizontal(|ui| {
+            ui.label("export to");
+            ui.text_edit_singleline(&mut planner.output);
+        });
+
+        if planner.scan.is_some() {
+            ui.label("scanning...");
+        } else if let (Some(from), Some(to)) = (from, to) {
+            if ui.button("export").clicked() {
+                let state = |conic: &crate::conic::Conic| {
+                    let elements = &conic.elements;
+                    (
+                        elements.position_at(elements.true_anomaly),
+                        elements.velocity_at(elements.true_anomaly),
+                    )
+                };
+                let (mu, from, to) = (from.elements.mu, state(from), state(to));
+                let (grid, output) = (planner.grid.clone(), PathBuf::from(&planner.output));
+                let scan = Arc::new(Mutex::new(None));
+                planner.scan = Some(scan.clone());
+
+                task_pool
+                    .spawn(async move {
+                        let plot = grid.scan(mu, from, to);
+                        let status = match plot.write(&output) {
+                            Ok(_) => plot.report(),
+                            Err(error) => {
+                                error!("{}", error);
+                                error.to_string()
+                            }
+                        };
+                        if let Ok(mut scan) = scan.lock() {
+                            *scan = Some(status);
+                        }
+                    })
+                    .detach();
+            }
+        }
+        ui.label(&planner.status);
+    });
+}
+
+/// Relative motion of the focused body around the target in the target's RIC frame, and the two
+/// Clohessy–Wiltshire burns that bring it to rest on the target.
+fn rendezvous_panel(
+    mut commands: Commands,
+    mut egui_context: ResMut<EguiContext>,
+    mut planner: ResMut<RendezvousPlanner>,
+    clock: Res<SimulationClock>,
+    conics: Res<Conics>,
+    focused_query: Query<Entity, With<Focused>>,
+    target_query: Query<Entity, (With<Targeted>, Without<Focused>)>,
+    properties_query: Query<&PhysicalProperties>,
+    name_query: Query<&RenderInUI>,
+) {
+    let (chaser, target) = match (focused_query.get_single(), target_query.get_single()) {
+        (Ok(chaser), Ok(target)) => (chaser, target),
+        _ => return,
+    };
+    let (position, velocity, mean_motion) =
+        match relative_to_target(&conics, &properties_query, target, chaser) {
+            Some(relative) => relative,
+            None => return,
+        };
+    let transfer_time = planner.transfer_time * 60.;
+    let burns = cw_rendezvous(mean_motion, position, velocity, transfer_time);
+
+    // in-track across, radial up, in km
+    let path = |velocity: DVec3, duration: f64| {
+        egui::plot::Values::from_values(
+            (0..=RELATIVE_PATH_POINTS)
+                .map(|point| {
+                    let time = duration * point as f64 / RELATIVE_PATH_POINTS as f64;
+                    let (position, _) = cw_propagate(mean_motion, position, velocity, time);
+                    egui::plot::Value::new(position.y / 1000., position.x / 1000.)
+                })
+                .collect(),
+        )
+    };
+    let period = 2. * std::f64::consts::PI / mean_motion;
+
+    egui::Window::new("Rendezvous").show(egui_context.ctx_mut(), |ui| {
+        ui.label(format!(
+            "target {}",
+            name_query
+                .get(target)
+                .map(|name| name.0.clone())
+                .unwrap_or_default()
+        ));
+        egui::Grid::new("relative_state")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("");
+                ui.label("radial");
+                ui.label("in-track");
+                ui.label("cross-track");
+                ui.end_row();
+                for (label, vector) in [("m", position), ("m/s", velocity)] {
+                    ui.label(label);
+                    for component in vector.to_array() {
+                        ui.label(format!("{:.2}", component));
+                    }
+                    ui.end_row();
+                }
+            });
+        ui.label(format!(
+            "range {:.3} km, rate {:.2} m/s",
+            position.length() / 1000.,
+            range_rate(position, velocity)
+        ));
+        ui.separator();
+
+        egui::plot::Plot::new("relative_motion")
+            .data_aspect(1.)
+            .view_aspect(2.)
+            .legend(egui::plot::Legend::default())
+            .show(ui, |plot_ui| {
+                plot_ui.line(egui::plot::Line::new(path(velocity, period)).name("coasting"));
+                if let Some((departure, _)) = burns {
+                    plot_ui.line(
+                        egui::plot::Line::new(path(velocity + departure, transfer_time))
+                            .name("rendezvous"),
+                    );
+                }
+                plot_ui.points(
+                    egui::plot::Points::new(egui::plot::Values::from_values(vec![
+                        egui::plot::Value::new(0., 0.),
+                    ]))
+                    .radius(4.)
+                    .name("target"),
+                );
+            });
+
+        ui.add(
+            egui::DragValue::new(&mut planner.transfer_time)
+                .speed(1.)
+                .prefix("arrive after ")
+                .suffix(" min"),
+        );
+        match burns {
+            Some((departure, arrival)) => {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{:.2} + {:.2} m/s",
+                        departure.length(),
+                        arrival.length()
+                    ));
+                    if ui.button("create nodes").clicked() {
+                        for (time, delta_v) in [
+                            (clock.elapsed, departure),
+                            (clock.elapsed + transfer_time, arrival),
+                        ] {
+                            let (prograde, normal, radial) = node_components(delta_v);
+                            commands.spawn().insert(ManeuverNode {
+                                body: chaser,
+                                time,
+                                prograde,
+                                normal,
+                                radial,
+                            });
+                        }
+                    }
+                });
+            }
+            None => {
+                ui.label("can't steer to the target in that time");
+            }
+        }
+    });
+}
+
+/// Turning angle, periapsis altitude, B-plane point and velocity change of a flyby.
+fn flyby_summary(flyby: &Flyby, planet_radius: f64) -> String {
+    format!(
+        "v∞ {:.3} km/s, turned {:.2}°\nperiapsis altitude {:.0} km\nB·T {:.0} km, B·R {:.0} km\ndelta-v {:.1} m/s",
+        flyby.v_infinity_in.length() / 1000.,
+        flyby.turning_angle.to_degrees(),
+        (flyby.periapsis - planet_radius) / 1000.,
+        flyby.b_t / 1000.,
+        flyby.b_r / 1000.,
+        flyby.delta_v().length()
+    )
+}
+
+/// Designs a gravity assist for the focused body. Searches for its next encounter with a planet
+/// after its next maneuver node, and retargets that node to pass a chosen B-plane point.
+fn flyby_panel(
+    mut egui_context: ResMut<EguiContext>,
+    mut planner: ResMut<FlybyPlanner>,
+    task_pool: Res<AsyncComputeTaskPool>,
+    clock: Res<SimulationClock>,
+    conics: Res<Conics>,
+    focused_query: Query<Entity, With<Focused>>,
+    mut node_query: Query<(Entity, &mut ManeuverNode)>,
+    sim_query: Query<(Entity, &PhysicalProperties), With<Simulated>>,
+    ref_query: Query<Entity, With<ReferenceFrame>>,
+    properties_query: Query<&PhysicalProperties>,
+    name_query: Query<&RenderInUI>,
+) {
+    let finished = planner
+        .running
+        .as_ref()
+        .and_then(|running| running.lock().ok()?.take());
+    if let Some(result) = finished {
+        planner.encounter = result.encounter;
+        planner.status = result.status;
+        planner.running = None;
+        if let Some((node_entity, corrected)) = result.corrected {
+            if let Ok((_, mut node)) = node_query.get_mut(node_entity) {
+                *node = corrected;
+            }
+        }
+    }
+
+    let craft = match focused_query.get_single() {
+        Ok(craft) => craft,
+        Err(_) => return,
+    };
+    let name = |entity: Entity| match name_query.get(entity) {
+        Ok(name) => name.0.clone(),
+        Err(_) => format!("{:?}", entity),
+    };
+
+    // already on the way past something
+    let current = conics
+        .0
+        .iter()
+        .find(|conic| conic.body == craft)
+        .and_then(|conic| {
+            let elements = &conic.elements;
+            let flyby = Flyby::from_state(
+                elements.position_at(elements.true_anomaly),
+                elements.velocity_at(elements.true_anomaly),
+                elements.mu,
+            )?;
+            let radius = properties_query
+                .get(conic.primary)
+                .map(|primary| primary.estimated_radius.to_f64())
+                .unwrap_or_default();
+            Some((conic.primary, flyby, radius))
+        });
+
+    let next_node = node_query
+        .iter()
+        .filter(|(_, node)| node.body == craft && node.time >= clock.elapsed)
+        .min_by(|(_, a), (_, b)| a.time.total_cmp(&b.time))
+        .map(|(entity, node)| (entity, node.clone()));
+
+    let mut search = false;
+    let mut target = false;
+    egui::Window::new("Flyby").show(egui_context.ctx_mut(), |ui| {
+        if let Some((primary, flyby, radius)) = current {
+            ui.label(format!("passing {}", name(primary)));
+            ui.label(flyby_summary(&flyby, radius));
+            ui.separator();
+        }
+
+        egui::ComboBox::from_label("planet")
+            .selected_text(planner.planet.map(name).unwrap_or_default())
+            .show_ui(ui, |ui| {
+                for conic in conics.0.iter().filter(|conic| conic.body != craft) {
+                    ui.selectable_value(&mut planner.planet, Some(conic.body), name(conic.body));
+                }
+            });
+        ui.add(
+            egui::DragValue::new(&mut planner.horizon)
+                .speed(1.)
+                .prefix("search ")
+                .suffix(" d"),
+        );
+        ui.label(match &next_node {
+            Some((_, node)) => format!(
+                "after the burn at T+{:.0} s, {:.1} m/s",
+                node.time - clock.elapsed,
+                node.delta_v()
+            ),
+            None => "no burn planned, coasting".to_string(),
+        });
+        let idle = planner.running.is_none();
+        if idle && planner.planet.is_some() && ui.button("find encounter").clicked() {
+            search = true;
+        }
+        if let Some(encounter) = &planner.encounter {
+            ui.label(format!(
+                "in {:.2} d\n{}\nspeed change {:.1} m/s",
+                encounter.time / 86_400.,
+                flyby_summary(&encounter.flyby, encounter.planet_radius),
+                encounter.flyby.speed_change(encounter.planet_velocity)
+            ));
+        }
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut planner.b_t)
+                    .prefix("B·T ")
+                    .suffix(" km"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut planner.b_r)
+                    .prefix("B·R ")
+                    .suffix(" km"),
+            );
+        });
+        if idle && next_node.is_some() && planner.planet.is_some() && ui.button("target").clicked()
+        {
+            target = true;
+        }
+        if idle {
+            ui.label(&planner.status);
+        } else {
+            ui.label("searching...");
+        }
+    });
+    if !search && !target {
+        return;
+    }
+
+    let bodies = body_states(sim_query.iter(), ref_query.get_single().ok());
+    let index = |entity: Entity| bodies.iter().position(|body| body.entity == entity);
+    let (craft_index, planet_index) = match (index(craft), planner.planet.and_then(index)) {
+        (Some(craft), Some(planet)) => (craft, planet),
+        _ => return,
+    };
+    // without a node, coast from now
+    let (node_entity, node) = match next_node {
+        Some((entity, node)) => (Some(entity), node),
+        None => (
+            None,
+            ManeuverNode {
+                body: craft,
+                time: clock.elapsed,
+                prograde: 0.,
+                normal: 0.,
+                radial: 0.,
+            },
+        ),
+    };
+    let nodes: Vec<ManeuverNode> = node_query.iter().map(|(_, node)| node.clone()).collect();
+    let (start, horizon) = (clock.elapsed, planner.horizon * 86_400.);
+    let (b_t, b_r) = (planner.b_t * 1000., planner.b_r * 1000.);
+    let previous = planner.encounter;
+    let running = Arc::new(Mutex::new(None));
+    planner.running = Some(running.clone());
+
+    task_pool
+        .spawn(async move {
+            let flyby_search = FlybySearch::new(
+                bodies,
+                craft_index,
+                planet_index,
+                &node,
+                &nodes,
+                start,
+                horizon,
+            );
+            let result = match node_entity.filter(|_| target) {
+                None => {
+                    let encounter = flyby_search.encounter(&node);
+                    let status = match encounter {
+                        Some(_) => String::new(),
+                        None => "no flyby in that time".to_string(),
+                    };
+                    FlybyResult {
+                        encounter,
+                        status,
+                        corrected: None,
+                    }
+                }
+                Some(node_entity) => match flyby_search.target(&node, b_t, b_r) {
+                    Ok((corrected, encounter, iterations)) => FlybyResult {
+                        encounter: Some(encounter),
+                        status: format!(
+                            "on target after {} iterations, burn now {:.1} m/s",
+                            iterations,
+                            corrected.delta_v()
+                        ),
+                        corrected: Some((node_entity, corrected)),
+                    },
+                    Err(error) => FlybyResult {
+                        encounter: previous,
+                        status: error.to_string(),
+                        corrected: None,
+                    },
+                },
+            };
+            if let Ok(mut running) = running.lock() {
+                *running = Some(result);
+            }
+        })
+        .detach();
+}
+
+/// Checkbox switching an optional goal on and off, with a value to drag when it's on.
+fn optional_goal(
+    ui: &mut egui::Ui,
+    label: &str,
+    goal: &mut Option<f64>,
+    default: f64,
+    suffix: &str,
+) {
+    ui.horizontal(|ui| {
+        let mut enabled = goal.is_some();
+        if ui.checkbox(&mut enabled, label).changed() {
+            *goal = if enabled { Some(default) } else { None };
+        }
+        if let Some(value) = goal {
+            ui.add(egui::DragValue::new(value).suffix(suffix));
+        }
+    });
+}
+
+/// Differential corrector for the focused body's next maneuver node. Changes the chosen parts
+/// of the burn until the orbit after it has the periapsis and inclination asked for, or the
+/// craft arrives on the target at the time asked for, and reports how it got there.
+fn targeting_panel(
+    mut egui_context: ResMut<EguiContext>,
+    mut planner: ResMut<TargetingPlanner>,
+    clock: Res<SimulationClock>,
+    focused_query: Query<Entity, With<Focused>>,
+    target_query: Query<Entity, (With<Targeted>, Without<Focused>)>,
+    mut node_query: Query<(Entity, &mut ManeuverNode)>,
+    sim_query: Query<(Entity, &PhysicalProperties), With<Simulated>>,
+    ref_query: Query<Entity, With<ReferenceFrame>>,
+    name_query: Query<&RenderInUI>,
+) {
+    let craft = match focused_query.get_single() {
+        Ok(craft) => craft,
+        Err(_) => return,
+    };
+    let target = target_query.get_single().ok();
+    let next_node = node_query
+        .iter()
+        .filter(|(_, node)| node.body == craft && node.time >= clock.elapsed)
+        .min_by(|(_, a), (_, b)| a.time.total_cmp(&b.time))
+        .map(|(entity, node)| (entity, node.clone()));
+
+    let mut run = false;
+    egui::Window::new("Targeting").show(egui_context.ctx_mut(), |ui| {
+        optional_goal(
+            ui,
+            "periapsis",
+            &mut planner.periapsis_altitude,
+            400.,
+            " km",
+        );
+        optional_goal(ui, "inclination", &mut planner.inclination, 90., "°");
+        match target.and_then(|target| name_query.get(target).ok()) {
+            Some(name) => {
+                optional_goal(
+                    ui,
+                    &format!("arrive at {} after", name.0),
+                    &mut planner.arrival,
+                    1.,
+                    " h",
+                );
+            }
+            None => {
+                planner.arrival = None;
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("vary");
+            for (enabled, label) in planner
+                .controls
+                .iter_mut()
+                .zip(["prograde", "normal", "radial", "time"])
+            {
+                ui.checkbox(enabled, label);
+            }
+        });
+        match &next_node {
+            Some((_, node)) => {
+                ui.label(format!(
+                    "burn at T+{:.0} s, {:.1} m/s",
+                    node.time - clock.elapsed,
+                    node.delta_v()
+                ));
+                if ui.button("correct").clicked() {
+                    run = true;
+                }
+            }
+            None => {
+                ui.label("add a maneuver node to correct");
+            }
+        }
+        ui.separator();
+        for line in planner.report.iter() {
+            ui.label(line);
+        }
+    });
+
+    let (node_entity, node) = match (run, next_node) {
+        (true, Some(next_node)) => next_node,
+        _ => return,
+    };
+    let bodies = body_states(sim_query.iter(), ref_query.get_single().ok());
+    let craft_index = match bodies.iter().position(|body| body.entity == craft) {
+        Some(index) => index,
+        None => return,
+    };
+
+    let mut goals = Vec::new();
+    if let Some(altitude) = planner.periapsis_altitude {
+        goals.push(Goal::PeriapsisAltitude(altitude * 1000.));
+    }
+    if let Some(inclination) = planner.inclination {
+        goals.push(Goal::Inclination(inclination.to_radians()));
+    }
+    if let (Some(hours), Some(target)) = (planner.arrival, target) {
+        goals.push(Goal::Arrival {
+            body: target,
+            time: clock.elapsed + hours * 3600.,
+        });
+    }
+    let tolerances: Vec<f64> = goals.iter().flat_map(Goal::tolerances).collect();
+    let controls: Vec<Control> = Control::ALL
+        .into_iter()
+        .zip(planner.controls)
+        .filter(|(_, enabled)| *enabled)
+        .map(|(control, _)| control)
+        .collect();
+
+    let others = node_query
+        .iter()
+        .filter(|(entity, _)| *entity != node_entity)
+        .map(|(_, node)| node.clone())
+        .collect();
+    let shooter = Shooter::new(bodies, craft_index, clock.elapsed, others);
+    let correction = correct(&node, &controls, &tolerances, |node| {
+        shooter.residuals(node, &goals)
+    });
+
+    let report = correction.report(&goals, clock.elapsed);
+    for line in report.iter() {
+        info!("targeting: {}", line);
+    }
+    planner.report = report;
+
+    if correction.outcome == Outcome::Converged {
+        if let Ok((_, mut node)) = node_query.get_mut(node_entity) {
+            *node = correction.node;
+        }
+    }
+}
+
+/// The focused body's state transition matrix, how much where it is now depends on where it was
+/// when the matrix was last reset.
+fn sensitivity_panel(
+    mut egui_context: ResMut<EguiContext>,
+    clock: Res<SimulationClock>,
+    mut planner: ResMut<UncertaintyPlanner>,
+    mut focused_query: Query<(&mut StateTransition, Option<&mut Covariance>), With<Focused>>,
+) {
+    let (mut transition, mut covariance) = match focused_query.get_single_mut() {
+        Ok(focused) => focused,
+        Err(_) => return,
+    };
+    let matrix = transition.matrix;
+    let (rr, rv, vr, vv) = sensitivity(&matrix);
+
+    egui::Window::new("Sensitivity").show(egui_context.ctx_mut(), |ui| {
+        ui.label(format!(
+            "since {:.2} h ago",
+            (clock.elapsed - transition.epoch) / 3600.
+        ));
+        egui::Grid::new("state_transition")
+            .striped(true)
+            .show(ui, |ui| {
+                for row in 0..6 {
+                    for column in 0..6 {
+                        ui.label(format!("{:+.3e}", matrix.get(row, column)));
+                    }
+                    ui.end_row();
+                }
+            });
+        ui.label(format!(
+            "up to {:.3} m per m and {:.1} m per m/s of starting error\nup to {:.3e} m/s per m and {:.3} m/s per m/s",
+            rr, rv, vr, vv
+        ));
+        if ui.button("reset").clicked() {
+            *transition = StateTransition {
+                matrix: StateMatrix::IDENTITY,
+                epoch: clock.elapsed,
+            };
+        }
+
+        let covariance = match &mut covariance {
+            Some(covariance) => covariance,
+            None => return,
+        };
+        ui.separator();
+        ui.heading("Uncertainty");
+        let mut axes = principal_axes(covariance.current.rr).map(|(variance, _)| variance.max(0.).sqrt());
+        axes.sort_by(|a, b| b.total_cmp(a));
+        ui.label(format!(
+            "1σ position axes {:.1} × {:.1} × {:.1} m",
+            axes[0], axes[1], axes[2]
+        ));
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut planner.position_sigma)
+                    .speed(1.)
+                    .clamp_range(0.0..=f64::MAX)
+                    .prefix("σ ")
+                    .suffix(" m"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut planner.velocity_sigma)
+                    .speed(0.01)
+                    .clamp_range(0.0..=f64::MAX)
+                    .prefix("σ ")
+                    .suffix(" m/s"),
+            );
+        });
+        if ui.button("restart").clicked() {
+            **covariance =
+                Covariance::diagonal(planner.position_sigma, planner.velocity_sigma, clock.elapsed);
+            *transition = StateTransition {
+                matrix: StateMatrix::IDENTITY,
+                epoch: clock.elapsed,
+            };
+        }
+    });
+}
@@ -0,0 +1,432 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{
+    headless::{Args, Snapshot},
+    lambert::lambert,
+    orbit::kepler_propagate,
+    simulation::{HPVec3, GRAVITATIONAL_CONSTANT},
+};
+
+/// Flag on the command line that scans a porkchop plot instead of opening a window.
+pub const FLAG: &str = "--porkchop";
+
+const USAGE: &str = "usage: orbital-simulations --porkchop [--from NAME] [--to NAME] \
+[--primary NAME] [--departures DAYS] [--min-flight DAYS] [--max-flight DAYS] [--resolution N] \
+[--revolutions N] [--output FILE]";
+
+/// C3 in km²/s² at and above which the heatmap saturates.
+const C3_CEILING: f64 = 100.;
+
+/// Pixels per grid cell side in the heatmap.
+const CELL_PIXELS: usize = 4;
+
+/// Heatmap colours from the cheapest C3 to the ceiling.
+const COLOR_STOPS: [[f64; 3]; 5] = [
+    [68., 1., 84.],
+    [59., 82., 139.],
+    [33., 145., 140.],
+    [94., 201., 98.],
+    [253., 231., 37.],
+];
+
+/// Cheapest transfer for one departure time and time of flight.
+#[derive(Clone, Copy, Debug)]
+pub struct PorkchopCell {
+    /// Characteristic energy at departure in m²/s², the square of the hyperbolic excess speed.
+    pub c3: f64,
+    /// Hyperbolic excess speed on arrival in m/s.
+    pub arrival_v_infinity: f64,
+    /// Full turns around the primary on the way.
+    pub revolutions: u32,
+}
+
+/// Grid of transfers between two bodies around the same primary, over departure times and times
+/// of flight.
+pub struct Porkchop {
+    /// Seconds from now.
+    pub departures: Vec<f64>,
+    /// Seconds from departure.
+    pub times_of_flight: Vec<f64>,
+    /// One row per time of flight, one column per departure. Empty where no transfer was found.
+    pub cells: Vec<Option<PorkchopCell>>,
+}
+
+/// Departure times and times of flight a porkchop plot is scanned over.
+#[derive(Clone, Debug)]
+pub struct PorkchopGrid {
+    /// Days from now over which departures are scanned.
+    pub departure_span: f64,
+    /// Shortest and longest times of flight in days.
+    pub min_time_of_flight: f64,
+    pub max_time_of_flight: f64,
+    /// Grid cells along each axis.
+    pub resolution: usize,
+    pub max_revolutions: u32,
+}
+
+impl Default for PorkchopGrid {
+    fn default() -> Self {
+        PorkchopGrid {
+            // a little over the earth to mars synodic period
+            departure_span: 800.,
+            min_time_of_flight: 100.,
+            max_time_of_flight: 400.,
+            resolution: 100,
+            max_revolutions: 0,
+        }
+    }
+}
+
+impl PorkchopGrid {
+    /// Scans the grid for transfers between bodies at `from` and `to`, as in `porkchop`.
+    pub fn scan(&self, mu: f64, from: (DVec3, DVec3), to: (DVec3, DVec3)) -> Porkchop {
+        let cells = self.resolution.max(2);
+        let range = |start: f64, end: f64| {
+            (0..cells)
+                .map(|cell| (start + (end - start) * cell as f64 / (cells - 1) as f64) * 86_400.)
+                .collect::<Vec<f64>>()
+        };
+        porkchop(
+            mu,
+            from,
+            to,
+            range(0., self.departure_span),
+            range(self.min_time_of_flight, self.max_time_of_flight),
+            self.max_revolutions,
+        )
+    }
+}
+
+/// Which bodies to scan transfers between and where to write the plot, read off the command
+/// line.
+#[derive(Clone, Debug)]
+pub struct PorkchopSettings {
+    pub from: String,
+    pub to: String,
+    /// Name of the body both orbit.
+    pub primary: String,
+    pub grid: PorkchopGrid,
+    /// Where the grid goes as CSV. The heatmap goes next to it as PNG.
+    pub output: PathBuf,
+}
+
+impl Default for PorkchopSettings {
+    fn default() -> Self {
+        PorkchopSettings {
+            from: "Earth".to_string(),
+            to: "Mars".to_string(),
+            primary: "Sun".to_string(),
+            grid: PorkchopGrid::default(),
+            output: PathBuf::from("porkchop.csv"),
+        }
+    }
+}
+
+impl PorkchopSettings {
+    /// Settings from the command line arguments after the program name, or `None` without
+    /// `FLAG`.
+    pub fn from_args(args: &[String]) -> Result<Option<PorkchopSettings>, PorkchopError> {
+        let mut args = match Args::find(args, FLAG) {
+            Some(args) => args,
+            None => return Ok(None),
+        };
+
+        let mut settings = PorkchopSettings::default();
+        while let Some(arg) = args.next() {
+            let grid = &mut settings.grid;
+            let value = match arg.as_str() {
+                "--from" => args.value(&arg).map(|name| settings.from = name),
+                "--to" => args.value(&arg).map(|name| settings.to = name),
+                "--primary" => args.value(&arg).map(|name| settings.primary = name),
+                "--departures" => args.value(&arg).map(|days| grid.departure_span = days),
+                "--min-flight" => args.value(&arg).map(|days| grid.min_time_of_flight = days),
+                "--max-flight" => args.value(&arg).map(|days| grid.max_time_of_flight = days),
+                "--resolution" => args.value(&arg).map(|cells| grid.resolution = cells),
+                "--revolutions" => args.value(&arg).map(|turns| grid.max_revolutions = turns),
+                "--output" => args.value(&arg).map(|path| settings.output = path),
+                _ => Err(format!("unknown argument {}", arg)),
+            };
+            value.map_err(PorkchopError::Usage)?;
+        }
+        let grid = &settings.grid;
+        if grid.min_time_of_flight <= 0. || grid.max_time_of_flight < grid.min_time_of_flight {
+            return Err(PorkchopError::Usage(
+                "times of flight have to be positive, --max-flight at least --min-flight"
+                    .to_string(),
+            ));
+        }
+        Ok(Some(settings))
+    }
+}
+
+#[derive(Debug)]
+pub enum PorkchopError {
+    Usage(String),
+    MissingBody(String),
+    Io(io::Error),
+    Png(png::EncodingError),
+}
+
+impl fmt::Display for PorkchopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PorkchopError::Usage(problem) => write!(f, "{}\n{}", problem, USAGE),
+            PorkchopError::MissingBody(name) => write!(f, "there's no body called {}", name),
+            PorkchopError::Io(error) => write!(f, "couldn't write the porkchop plot: {}", error),
+            PorkchopError::Png(error) => write!(f, "couldn't encode the porkchop plot: {}", error),
+        }
+    }
+}
+
+impl From<io::Error> for PorkchopError {
+    fn from(error: io::Error) -> Self {
+        PorkchopError::Io(error)
+    }
+}
+
+impl From<png::EncodingError> for PorkchopError {
+    fn from(error: png::EncodingError) -> Self {
+        PorkchopError::Png(error)
+    }
+}
+
+/// Scans departures and times of flight for transfers from a body at `from` to one at `to`,
+/// each a position and velocity relative to a primary with gravitational parameter `mu`. Both
+/// bodies are moved along two-body orbits, and every cell keeps the lowest C3 over up to
+/// `max_revolutions` full turns.
+pub fn porkchop(
+    mu: f64,
+    from: (DVec3, DVec3),
+    to: (DVec3, DVec3),
+    departures: Vec<f64>,
+    times_of_flight: Vec<f64>,
+    max_revolutions: u32,
+) -> Porkchop {
+    let mut cells = Vec::with_capacity(departures.len() * times_of_flight.len());
+    for time_of_flight in times_of_flight.iter() {
+        for departure in departures.iter() {
+            let (start, start_velocity) = kepler_propagate(from.0, from.1, mu, *departure);
+            let (end, end_velocity) = kepler_propagate(to.0, to.1, mu, departure + time_of_flight);
+
+            let cell = lambert(mu, start, end, *time_of_flight, max_revolutions)
+                .into_iter()
+                .map(|solution| PorkchopCell {
+                    c3: (solution.departure_velocity - start_velocity).length_squared(),
+                    arrival_v_infinity: (solution.arrival_velocity - end_velocity).length(),
+                    revolutions: solution.revolutions,
+                })
+                .min_by(|a, b| a.c3.total_cmp(&b.c3));
+            cells.push(cell);
+        }
+    }
+
+    Porkchop {
+        departures,
+        times_of_flight,
+        cells,
+    }
+}
+
+impl Porkchop {
+    fn cell(&self, departure: usize, time_of_flight: usize) -> Option<PorkchopCell> {
+        self.cells[time_of_flight * self.departures.len() + departure]
+    }
+
+    /// The cheapest cell's departure, time of flight and transfer.
+    pub fn best(&self) -> Option<(f64, f64, PorkchopCell)> {
+        self.times_of_flight
+            .iter()
+            .enumerate()
+            .flat_map(|(row, time_of_flight)| {
+                self.departures
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(column, departure)| {
+                        Some((*departure, *time_of_flight, self.cell(column, row)?))
+                    })
+            })
+            .min_by(|a, b| a.2.c3.total_cmp(&b.2.c3))
+    }
+
+    /// Writes the grid to `path` as CSV and the heatmap next to it as PNG.
+    pub fn write(&self, path: &Path) -> Result<(), PorkchopError> {
+        self.write_csv(path)?;
+        self.write_png(&path.with_extension("png"))
+    }
+
+    /// The cheapest transfer found, in words.
+    pub fn report(&self) -> String {
+        match self.best() {
+            Some((departure, time_of_flight, cell)) => format!(
+                "best C3 {:.2} km²/s², v∞ {:.2} km/s\nleaving in {:.0} d, {:.0} d flight",
+                cell.c3 / 1e6,
+                cell.arrival_v_infinity / 1e3,
+                departure / 86_400.,
+                time_of_flight / 86_400.
+            ),
+            None => "no transfers found".to_string(),
+        }
+    }
+
+    /// Writes every cell as a CSV row, in days and km/s.
+    fn write_csv(&self, path: &Path) -> Result<(), PorkchopError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "departure_days,time_of_flight_days,c3_km2_s2,arrival_v_infinity_km_s,revolutions"
+        )?;
+        for (row, time_of_flight) in self.times_of_flight.iter().enumerate() {
+            for (column, departure) in self.departures.iter().enumerate() {
+                let transfer = match self.cell(column, row) {
+                    Some(cell) => format!(
+                        "{:.4},{:.4},{}",
+                        cell.c3 / 1e6,
+                        cell.arrival_v_infinity / 1e3,
+                        cell.revolutions
+                    ),
+                    None => ",,".to_string(),
+                };
+                writeln!(
+                    file,
+                    "{:.3},{:.3},{}",
+                    departure / 86_400.,
+                    time_of_flight / 86_400.,
+                    transfer
+                )?;
+            }
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Renders C3 as a heatmap with departures along the x axis and times of flight up the y
+    /// axis. Cells without a transfer are black.
+    fn write_png(&self, path: &Path) -> Result<(), PorkchopError> {
+        let width = self.departures.len() * CELL_PIXELS;
+        let height = self.times_of_flight.len() * CELL_PIXELS;
+        let mut data = vec![0_u8; width * height * 3];
+
+        for y in 0..height {
+            // longest flights at the top
+            let row = self.times_of_flight.len() - 1 - y / CELL_PIXELS;
+            for x in 0..width {
+                let color = match self.cell(x / CELL_PIXELS, row) {
+                    Some(cell) => heat_color(cell.c3 / 1e6 / C3_CEILING),
+                    None => [0; 3],
+                };
+                let pixel = (y * width + x) * 3;
+                data[pixel..pixel + 3].copy_from_slice(&color);
+            }
+        }
+
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            width as u32,
+            height as u32,
+        );
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&data)?;
+        Ok(())
+    }
+}
+
+/// Scans the porkchop plot in `settings` between bodies spawned into `world`, from where they
+/// are now, and writes it out.
+pub fn run_porkchop(world: &mut World, settings: &PorkchopSettings) -> Result<(), PorkchopError> {
+    let snapshot = Snapshot::from_world(world);
+    let find = |name: &String| {
+        snapshot
+            .find(name)
+            .map(|index| &snapshot.bodies[index])
+            .ok_or_else(|| PorkchopError::MissingBody(name.clone()))
+    };
+    let primary = find(&settings.primary)?;
+    let state = |name: &String| {
+        find(name).map(|body| {
+            (
+                HPVec3::sub(&body.translation, &primary.translation).to_dvec3(),
+                HPVec3::sub(&body.velocity, &primary.velocity).to_dvec3(),
+            )
+        })
+    };
+    let mu = (GRAVITATIONAL_CONSTANT * primary.mass.clone()).to_f64();
+    info!(
+        "scanning transfers from {} to {} around {}",
+        settings.from, settings.to, settings.primary
+    );
+
+    let plot = settings
+        .grid
+        .scan(mu, state(&settings.from)?, state(&settings.to)?);
+    plot.write(&settings.output)?;
+    println!("{}", plot.report());
+    info!(
+        "wrote {} and {}",
+        settings.output.display(),
+        settings.output.with_extension("png").display()
+    );
+    Ok(())
+}
+
+/// Colour for a fraction of the C3 ceiling, spread on a square root so the cheap end that matters
+/// gets most of the range.
+fn heat_color(fraction: f64) -> [u8; 3] {
+    let position = fraction.clamp(0., 1.).sqrt() * (COLOR_STOPS.len() - 1) as f64;
+    let index = (position.floor() as usize).min(COLOR_STOPS.len() - 2);
+    let blend = position - index as f64;
+    let (low, high) = (COLOR_STOPS[index], COLOR_STOPS[index + 1]);
+    [0, 1, 2].map(|channel| (low[channel] + (high[channel] - low[channel]) * blend) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::orbit::REFERENCE_POLE;
+
+    const SUN_MU: f64 = 1.327_124_4e20;
+    const ASTRONOMICAL_UNIT: f64 = 1.495_978_707e11;
+
+    /// Circular orbit of `radius` at `angle` around the pole the bodies orbit.
+    fn circular(radius: f64, angle: f64) -> (DVec3, DVec3) {
+        let position = DVec3::new(angle.cos(), 0., angle.sin()) * radius;
+        let speed = (SUN_MU / radius).sqrt();
+        (position, REFERENCE_POLE.cross(position).normalize() * speed)
+    }
+
+    #[test]
+    fn earth_to_mars_is_close_to_hohmann() {
+        let (earth, mars) = (ASTRONOMICAL_UNIT, 1.523_7 * ASTRONOMICAL_UNIT);
+        let transfer = (earth + mars) / 2.;
+        let hohmann_time = PI * (transfer.powi(3) / SUN_MU).sqrt();
+        let departure_speed = (SUN_MU * (2. / earth - 1. / transfer)).sqrt();
+        let hohmann_c3 = (departure_speed - (SUN_MU / earth).sqrt()).powi(2);
+
+        // mars placed so it is opposite the earth a Hohmann transfer time later
+        let mars_angle = PI - hohmann_time * (SUN_MU / mars.powi(3)).sqrt();
+        let days = hohmann_time / 86_400.;
+        let grid = PorkchopGrid {
+            departure_span: 0.,
+            min_time_of_flight: days - 30.,
+            max_time_of_flight: days + 30.,
+            resolution: 61,
+            max_revolutions: 0,
+        };
+        let plot = grid.scan(SUN_MU, circular(earth, 0.), circular(mars, mars_angle));
+
+        let (_, time_of_flight, cell) = plot.best().unwrap();
+        // about 8.7 km²/s², only matched off the degenerate half turn itself
+        assert!((hohmann_c3 / 1e6 - 8.7).abs() < 0.1);
+        assert!((cell.c3 - hohmann_c3).abs() < 0.05 * hohmann_c3);
+        assert!((time_of_flight - hohmann_time).abs() < 5. * 86_400.);
+    }
+}
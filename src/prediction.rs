@@ -0,0 +1,544 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::{math::DVec3, prelude::*, render::mesh::PrimitiveTopology, tasks::AsyncComputeTaskPool};
+use rug::Float;
+
+use crate::{
+    atmosphere::REENTRY_ALTITUDE,
+    camera::{self, Focused, PanOrbitCamera},
+    lines::{set_lines, spawn_lines, LineMaterialHandle},
+    maneuver::{advance_through, ManeuverNode},
+    orbit::{kepler_propagate, primaries, spheres_of_influence, OrbitalElements},
+    simulation::{
+        body_states, step, BodyState, ForceModels, HPVec3, PhysicalProperties, ReferenceFrame,
+        Simulated, SimulationClock, DEFAULT_PRECISION, GRAVITATIONAL_CONSTANT,
+    },
+    ui::RenderInUI,
+    view::{self, RenderOrigin, RenderScale},
+};
+
+/// How the look-ahead moves bodies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropagationMode {
+    /// Integrates every force between every body, like the live simulation.
+    NBody,
+    /// Two-body orbits around each body's current primary, switching primary at sphere of
+    /// influence boundaries. Much cheaper, and what mission design starts from.
+    PatchedConic,
+}
+
+/// Settings for the look-ahead propagation of the focused body.
+pub struct PredictionSettings {
+    pub mode: PropagationMode,
+    /// Number of steps integrated ahead of the live simulation.
+    pub steps: usize,
+    /// Simulated seconds per look-ahead step, shortened for bodies on tight orbits.
+    pub timestep: f64,
+    /// Fewest look-ahead steps spent on one orbit around the primary.
+    pub steps_per_orbit: f64,
+    /// How often, in real time, the prediction is recomputed.
+    pub refresh: Timer,
+}
+
+impl Default for PredictionSettings {
+    fn default() -> Self {
+        PredictionSettings {
+            mode: PropagationMode::NBody,
+            steps: 2000,
+            timestep: 600.,
+            steps_per_orbit: 200.,
+            refresh: Timer::from_seconds(1., true),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkerKind {
+    Periapsis,
+    Apoapsis,
+    /// Closest approach to another simulated body.
+    ClosestApproach(Entity),
+    /// Falling into another body's atmosphere, where the path ends.
+    Reentry(Entity),
+    /// Crossing into the sphere of influence of a new primary.
+    SoiTransition(Entity),
+    /// A planned burn.
+    Maneuver,
+}
+
+/// Notable point along a predicted path.
+#[derive(Clone, Debug)]
+pub struct PredictionMarker {
+    pub kind: MarkerKind,
+    /// Simulated seconds from now.
+    pub time: f64,
+    /// Position relative to the primary.
+    pub offset: DVec3,
+    /// Distance to the primary, or to the approached body for closest approaches.
+    pub distance: f64,
+}
+
+/// Predicted future path of the focused body, relative to the body it orbits.
+#[derive(Default)]
+pub struct Prediction {
+    pub body: Option<Entity>,
+    pub primary: Option<Entity>,
+    pub path: Vec<DVec3>,
+    pub markers: Vec<PredictionMarker>,
+}
+
+/// Look-ahead running on the async compute pool, so long n-body predictions don't stall frames.
+#[derive(Default)]
+struct PredictionTask {
+    running: Option<Arc<Mutex<Option<Prediction>>>>,
+    /// Whether a refresh or a node edit came in since the running look-ahead started.
+    stale: bool,
+}
+
+/// Line mesh drawing the predicted path.
+#[derive(Component)]
+struct PredictionLine;
+
+/// Screen space label for a prediction marker.
+#[derive(Component)]
+struct MarkerLabel(usize);
+
+/// Plugin used to preview the future path of the focused body.
+pub struct PredictionPlugin;
+
+impl Plugin for PredictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PredictionSettings>();
+        app.init_resource::<Prediction>();
+        app.init_resource::<PredictionTask>();
+        app.add_startup_system(spawn_prediction_line);
+        app.add_system(toggle_propagation_mode);
+        app.add_system(update_prediction);
+        app.add_system(draw_prediction.after(view::LABEL));
+        app.add_system(spawn_marker_labels.after(update_prediction));
+        app.add_system(update_marker_labels.after(camera::LABEL));
+    }
+}
+
+/// Switch between n-body and patched-conic look-ahead with K.
+fn toggle_propagation_mode(
+    input_keyboard: Res<Input<KeyCode>>,
+    mut settings: ResMut<PredictionSettings>,
+) {
+    if input_keyboard.just_pressed(KeyCode::K) {
+        settings.mode = match settings.mode {
+            PropagationMode::NBody => PropagationMode::PatchedConic,
+            PropagationMode::PatchedConic => PropagationMode::NBody,
+        };
+        info!("prediction now {:?}", settings.mode);
+    }
+}
+
+/// Moves every body along the two-body orbit around its current primary, the patched-conic
+/// approximation. Bodies without a primary coast in a straight line.
+pub fn patched_conic_step(bodies: &mut [BodyState], timestep: f64) {
+    let influence = spheres_of_influence(bodies);
+    // states relative to the primaries, taken before anything moves
+    let relative: Vec<Option<(DVec3, DVec3, f64)>> = bodies
+        .iter()
+        .zip(influence.iter())
+        .map(|(body, (primary, _))| {
+            let primary = &bodies[(*primary)?];
+            let position = HPVec3::sub(&body.translation, &primary.translation).to_dvec3();
+            let velocity = HPVec3::sub(&body.velocity, &primary.velocity).to_dvec3();
+            let mu = (GRAVITATIONAL_CONSTANT * (body.mass.clone() + &primary.mass)).to_f64();
+            Some((position, velocity, mu))
+        })
+        .collect();
+
+    // heaviest first, so every primary has moved before the bodies placed around it
+    let mut order: Vec<usize> = (0..bodies.len()).collect();
+    order.sort_by(|a, b| bodies[*b].mass.total_cmp(&bodies[*a].mass));
+
+    for index in order {
+        if bodies[index].fixed {
+            continue;
+        }
+        match (influence[index].0, relative[index]) {
+            (Some(primary), Some((position, velocity, mu))) => {
+                let (position, velocity) = kepler_propagate(position, velocity, mu, timestep);
+                bodies[index].translation =
+                    HPVec3::add(&bodies[primary].translation, &HPVec3::from_dvec3(position));
+                bodies[index].velocity =
+                    HPVec3::add(&bodies[primary].velocity, &HPVec3::from_dvec3(velocity));
+            }
+            _ => {
+                let drift = HPVec3::scalar_mul(
+                    &bodies[index].velocity,
+                    &Float::with_val(DEFAULT_PRECISION, timestep),
+                );
+                bodies[index].translation = HPVec3::add(&bodies[index].translation, &drift);
+            }
+        }
+    }
+}
+
+/// Moves a copy of `bodies` forward from `start`, burning `nodes` on the way, and records the
+/// path of `bodies[body]` relative to `bodies[primary]` with the notable points along it. The
+/// live simulation is not touched.
+pub fn propagate(
+    mut bodies: Vec<BodyState>,
+    body: usize,
+    primary: usize,
+    steps: usize,
+    timestep: f64,
+    mode: PropagationMode,
+    nodes: &[ManeuverNode],
+    start: f64,
+) -> (Vec<DVec3>, Vec<PredictionMarker>) {
+    let mut nodes = nodes.to_vec();
+    let mut path = Vec::with_capacity(steps + 1);
+    let mut markers = Vec::new();
+
+    // the last two distances to every body, to spot local extremes one step late
+    let mut history: Vec<[f64; 2]> = vec![[f64::NAN; 2]; bodies.len()];
+    let mut previous_offset = DVec3::ZERO;
+    let mut current_primary = spheres_of_influence(&bodies)[body].0;
+
+    'steps: for index in 0..=steps {
+        let mut burned = Vec::new();
+        if index > 0 {
+            burned = advance_through(
+                &mut bodies,
+                &nodes,
+                start + (index - 1) as f64 * timestep,
+                timestep,
+                |bodies, timestep| match mode {
+                    PropagationMode::NBody => {
+                        step(bodies, &Float::with_val(DEFAULT_PRECISION, timestep));
+                    }
+                    PropagationMode::PatchedConic => patched_conic_step(bodies, timestep),
+                },
+            );
+        }
+
+        let offset =
+            HPVec3::sub(&bodies[body].translation, &bodies[primary].translation).to_dvec3();
+        path.push(offset);
+
+        // marked where the step that burned ends, close enough at look-ahead resolution
+        burned.sort_unstable();
+        for node in burned.into_iter().rev() {
+            let node = nodes.remove(node);
+            if node.body == bodies[body].entity {
+                markers.push(PredictionMarker {
+                    kind: MarkerKind::Maneuver,
+                    time: node.time - start,
+                    offset,
+                    distance: offset.length(),
+                });
+            }
+        }
+
+        let new_primary = spheres_of_influence(&bodies)[body].0;
+        if new_primary != current_primary {
+            if let Some(new_primary) = new_primary {
+                markers.push(PredictionMarker {
+                    kind: MarkerKind::SoiTransition(bodies[new_primary].entity),
+                    time: index as f64 * timestep,
+                    offset,
+                    distance: bodies[body]
+                        .translation
+                        .distance(&bodies[new_primary].translation)
+                        .to_f64(),
+                });
+            }
+            current_primary = new_primary;
+        }
+
+        for other in 0..bodies.len() {
+            if other == body {
+                continue;
+            }
+
+            let distance = bodies[body]
+                .translation
+                .distance(&bodies[other].translation)
+                .to_f64();
+            let [before, previous] = history[other];
+            let time = index.saturating_sub(1) as f64 * timestep;
+
+            if bodies[body].drag.is_some()
+                && bodies[other].atmosphere.is_some()
+                && distance - bodies[other].radius < REENTRY_ALTITUDE
+            {
+                markers.push(PredictionMarker {
+                    kind: MarkerKind::Reentry(bodies[other].entity),
+                    time: index as f64 * timestep,
+                    offset,
+                    distance,
+                });
+                break 'steps;
+            }
+
+            if previous < before && previous <= distance {
+                let kind = if other == primary {
+                    MarkerKind::Periapsis
+                } else {
+                    MarkerKind::ClosestApproach(bodies[other].entity)
+                };
+                markers.push(PredictionMarker {
+                    kind,
+                    time,
+                    offset: previous_offset,
+                    distance: previous,
+                });
+            } else if other == primary && previous > before && previous >= distance {
+                markers.push(PredictionMarker {
+                    kind: MarkerKind::Apoapsis,
+                    time,
+                    offset: previous_offset,
+                    distance: previous,
+                });
+            }
+
+            history[other] = [previous, distance];
+        }
+
+        previous_offset = offset;
+    }
+
+    (path, markers)
+}
+
+fn update_prediction(
+    time: Res<Time>,
+    clock: Res<SimulationClock>,
+    task_pool: Res<AsyncComputeTaskPool>,
+    mut settings: ResMut<PredictionSettings>,
+    mut prediction: ResMut<Prediction>,
+    mut task: ResMut<PredictionTask>,
+    sim_query: Query<(Entity, &PhysicalProperties), With<Simulated>>,
+    ref_query: Query<Entity, With<ReferenceFrame>>,
+    focused_query: Query<Entity, With<Focused>>,
+    node_query: Query<&ManeuverNode>,
+    changed_node_query: Query<(), Changed<ManeuverNode>>,
+    removed_nodes: RemovedComponents<ManeuverNode>,
+    force_models: ForceModels,
+) {
+    let finished = task
+        .running
+        .as_ref()
+        .and_then(|running| running.lock().ok()?.take());
+    if let Some(finished) = finished {
+        *prediction = finished;
+        task.running = None;
+    }
+
+    let focused = focused_query.get_single().ok();
+    let refresh = settings.refresh.tick(time.delta()).just_finished();
+    // nodes being edited update the path as soon as the running look-ahead is done
+    let nodes_changed = !changed_node_query.is_empty() || removed_nodes.iter().next().is_some();
+    task.stale |= refresh || nodes_changed;
+    if task.running.is_some() || (!task.stale && focused == prediction.body) {
+        return;
+    }
+    task.stale = false;
+
+    let mut bodies = body_states(sim_query.iter(), ref_query.get_single().ok());
+    force_models.attach(&mut bodies);
+    let body = focused.and_then(|focused| bodies.iter().position(|b| b.entity == focused));
+    let (body, primary) = match body.and_then(|body| Some((body, primaries(&bodies)[body]?))) {
+        Some(indices) => indices,
+        None => {
+            *prediction = Prediction {
+                body: focused,
+                ..default()
+            };
+            return;
+        }
+    };
+
+    let primary_entity = bodies[primary].entity;
+    let timestep = timestep(&settings, &bodies[body], &bodies[primary]);
+    let nodes: Vec<ManeuverNode> = node_query.iter().cloned().collect();
+    let (steps, mode, start) = (settings.steps, settings.mode, clock.elapsed);
+    let running = Arc::new(Mutex::new(None));
+    task.running = Some(running.clone());
+
+    task_pool
+        .spawn(async move {
+            let (path, markers) =
+                propagate(bodies, body, primary, steps, timestep, mode, &nodes, start);
+            if let Ok(mut running) = running.lock() {
+                *running = Some(Prediction {
+                    body: focused,
+                    primary: Some(primary_entity),
+                    path,
+                    markers,
+                });
+            }
+        })
+        .detach();
+}
+
+/// Look-ahead step for `body`, short enough to resolve its orbit around `primary`.
+fn timestep(settings: &PredictionSettings, body: &BodyState, primary: &BodyState) -> f64 {
+    let position = HPVec3::sub(&body.translation, &primary.translation).to_dvec3();
+    let velocity = HPVec3::sub(&body.velocity, &primary.velocity).to_dvec3();
+    let mu = (GRAVITATIONAL_CONSTANT * (body.mass.clone() + &primary.mass)).to_f64();
+
+    match OrbitalElements::from_state(position, velocity, mu).period() {
+        Some(period) => settings.timestep.min(period / settings.steps_per_orbit),
+        None => settings.timestep,
+    }
+}
+
+fn spawn_prediction_line(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterialHandle>,
+) {
+    let (line, _) = spawn_lines(
+        &mut commands,
+        &mut meshes,
+        &material,
+        PrimitiveTopology::LineStrip,
+    );
+    commands.entity(line).insert(PredictionLine);
+}
+
+/// Render space position of a point given relative to the prediction's primary.
+fn render_position(
+    origin: &RenderOrigin,
+    scale: &RenderScale,
+    primary: &PhysicalProperties,
+    offset: DVec3,
+) -> Vec3 {
+    scale.to_render(origin.relative(&primary.translation) + offset)
+}
+
+fn draw_prediction(
+    mut meshes: ResMut<Assets<Mesh>>,
+    origin: Res<RenderOrigin>,
+    scale: Res<RenderScale>,
+    prediction: Res<Prediction>,
+    line_query: Query<&Handle<Mesh>, With<PredictionLine>>,
+    primary_query: Query<&PhysicalProperties>,
+) {
+    let mesh = match line_query.get_single().ok().and_then(|m| meshes.get_mut(m)) {
+        Some(mesh) => mesh,
+        None => return,
+    };
+
+    let primary = match prediction.primary.and_then(|p| primary_query.get(p).ok()) {
+        Some(primary) => primary,
+        None => {
+            set_lines(mesh, Vec::new(), Vec::new());
+            return;
+        }
+    };
+
+    // the path is laid around where the primary is now, like the trails
+    let count = prediction.path.len().max(1) as f32;
+    let mut positions = Vec::with_capacity(prediction.path.len());
+    let mut colors = Vec::with_capacity(prediction.path.len());
+    for (index, offset) in prediction.path.iter().enumerate() {
+        positions.push(render_position(&origin, &scale, primary, *offset).to_array());
+        colors.push([1.0, 0.55, 0.1, 0.9 * (1. - index as f32 / count) + 0.1]);
+    }
+    set_lines(mesh, positions, colors);
+}
+
+fn spawn_marker_labels(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    prediction: Res<Prediction>,
+    label_query: Query<Entity, With<MarkerLabel>>,
+    name_query: Query<&RenderInUI>,
+) {
+    if !prediction.is_changed() {
+        return;
+    }
+
+    for label in label_query.iter() {
+        commands.entity(label).despawn_recursive();
+    }
+
+    for (index, marker) in prediction.markers.iter().enumerate() {
+        let name = match marker.kind {
+            MarkerKind::Periapsis => "Pe".to_string(),
+            MarkerKind::Apoapsis => "Ap".to_string(),
+            MarkerKind::ClosestApproach(entity) => match name_query.get(entity) {
+                Ok(name) => format!("CA {}", name.0),
+                Err(_) => "CA".to_string(),
+            },
+            MarkerKind::Reentry(_) => "Re-entry".to_string(),
+            MarkerKind::SoiTransition(entity) => match name_query.get(entity) {
+                Ok(name) => format!("SOI {}", name.0),
+                Err(_) => "SOI".to_string(),
+            },
+            MarkerKind::Maneuver => "Burn".to_string(),
+        };
+
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    display: Display::None,
+                    ..default()
+                },
+                text: Text::with_section(
+                    format!(
+                        "{} {:.0} km T+{:.1} h",
+                        name,
+                        marker.distance / 1000.,
+                        marker.time / 3600.
+                    ),
+                    TextStyle {
+                        font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
+                        font_size: 14.0,
+                        color: Color::ORANGE,
+                    },
+                    default(),
+                ),
+                ..default()
+            })
+            .insert(MarkerLabel(index));
+    }
+}
+
+fn update_marker_labels(
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    origin: Res<RenderOrigin>,
+    scale: Res<RenderScale>,
+    prediction: Res<Prediction>,
+    camera_query: Query<(&Camera, &Transform), With<PanOrbitCamera>>,
+    primary_query: Query<&PhysicalProperties>,
+    mut label_query: Query<(&MarkerLabel, &mut Style)>,
+) {
+    let (camera, camera_transform) = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let primary = prediction.primary.and_then(|p| primary_query.get(p).ok());
+    let camera_global = GlobalTransform::from(*camera_transform);
+
+    for (label, mut style) in label_query.iter_mut() {
+        let screen = match (primary, prediction.markers.get(label.0)) {
+            (Some(primary), Some(marker)) => {
+                let position = render_position(&origin, &scale, primary, marker.offset);
+                camera.world_to_screen(&windows, &images, &camera_global, position)
+            }
+            _ => None,
+        };
+
+        match screen {
+            Some(screen) => {
+                style.display = Display::Flex;
+                style.position = Rect {
+                    left: Val::Px(screen.x),
+                    bottom: Val::Px(screen.y),
+                    ..default()
+                };
+            }
+            None => style.display = Display::None,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::f64::consts::PI;
+
+use bevy::{math::DVec3, prelude::*};
+
+/// Solar radiation pressure on a perfectly absorbing surface one astronomical unit from the sun,
+/// in N/m².
+const SOLAR_PRESSURE: f64 = 4.56e-6;
+
+/// One astronomical unit in meters.
+const ASTRONOMICAL_UNIT: f64 = 1.495_978_707e11;
+
+/// How the shadows of occluding bodies are modelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadowModel {
+    /// Sunlight arrives in parallel, so shadows are cylinders with no penumbra.
+    Cylindrical,
+    /// The sun is a disc, giving a penumbra where it's partly covered.
+    Conical,
+}
+
+/// Sunlight pushing on a spacecraft, which is treated as a flat plate facing the sun.
+#[derive(Component, Clone, Debug)]
+pub struct RadiationPressure {
+    /// Area facing the sun, in m².
+    pub area: f64,
+    /// Between 1 for a perfect absorber and 2 for a perfect mirror.
+    pub reflectivity: f64,
+    pub shadow: ShadowModel,
+}
+
+impl RadiationPressure {
+    /// Acceleration away from the sun at `offset` from it, for a body of `mass` receiving
+    /// `illumination` of the full sunlight.
+    pub fn acceleration(&self, mass: f64, offset: DVec3, illumination: f64) -> DVec3 {
+        let distance = offset.length();
+        if distance <= 0. {
+            return DVec3::ZERO;
+        }
+        let pressure = SOLAR_PRESSURE * (ASTRONOMICAL_UNIT / distance).powi(2);
+        offset / distance * (pressure * self.reflectivity * self.area / mass * illumination)
+    }
+}
+
+/// Plugin used to switch between shadow models.
+pub struct RadiationPlugin;
+
+impl Plugin for RadiationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(toggle_shadow_model);
+    }
+}
+
+/// Switch every body between cylindrical and conical shadows with P.
+fn toggle_shadow_model(
+    input_keyboard: Res<Input<KeyCode>>,
+    mut radiation_query: Query<&mut RadiationPressure>,
+) {
+    if !input_keyboard.just_pressed(KeyCode::P) {
+        return;
+    }
+
+    for mut radiation in radiation_query.iter_mut() {
+        radiation.shadow = match radiation.shadow {
+            ShadowModel::Cylindrical => ShadowModel::Conical,
+            ShadowModel::Conical => ShadowModel::Cylindrical,
+        };
+        info!("using {:?} shadows", radiation.shadow);
+    }
+}
+
+/// Fraction of the sun's disc visible from `position` with a body of `occluder_radius` at
+/// `occluder`, from 0 in umbra to 1 in full sunlight.
+pub fn illumination(
+    model: ShadowModel,
+    position: DVec3,
+    sun: DVec3,
+    sun_radius: f64,
+    occluder: DVec3,
+    occluder_radius: f64,
+) -> f64 {
+    match model {
+        ShadowModel::Cylindrical => {
+            let offset = position - occluder;
+            let sun_direction = (sun - occluder).normalize_or_zero();
+            let along = offset.dot(sun_direction);
+            let across = (offset - sun_direction * along).length();
+            if along < 0. && across < occluder_radius {
+                0.
+            } else {
+                1.
+            }
+        }
+        ShadowModel::Conical => {
+            let to_sun = sun - position;
+            let to_occluder = occluder - position;
+            // the occluder has to sit between us and the sun to cast a shadow
+            if to_occluder.length() >= to_sun.length() || to_occluder.dot(to_sun) <= 0. {
+                return 1.;
+            }
+
+            // apparent radii of both discs and the angle between their centers
+            let sun_angle = (sun_radius / to_sun.length()).clamp(-1., 1.).asin();
+            let occluder_angle = (occluder_radius / to_occluder.length())
+                .clamp(-1., 1.)
+                .asin();
+            let separation = to_sun.angle_between(to_occluder);
+
+            if separation >= sun_angle + occluder_angle {
+                1.
+            } else if separation <= occluder_angle - sun_angle {
+                0.
+            } else if separation <= sun_angle - occluder_angle {
+                // annular, the occluder sits inside the sun's disc
+                1. - (occluder_angle / sun_angle).powi(2)
+            } else {
+                // partial overlap of the two discs
+                let x = (separation.powi(2) + sun_angle.powi(2) - occluder_angle.powi(2))
+                    / (2. * separation);
+                let y = (sun_angle.powi(2) - x * x).max(0.).sqrt();
+                let overlap = sun_angle.powi(2) * (x / sun_angle).clamp(-1., 1.).acos()
+                    + occluder_angle.powi(2)
+                        * ((separation - x) / occluder_angle).clamp(-1., 1.).acos()
+                    - separation * y;
+                1. - overlap / (PI * sun_angle.powi(2))
+            }
+        }
+    }
+}
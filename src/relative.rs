@@ -0,0 +1,218 @@
+use bevy::{
+    math::{DMat3, DVec3},
+    prelude::*,
+};
+
+use crate::{
+    camera::{Focused, Targeted},
+    conic::Conics,
+    simulation::{self, HPVec3, PhysicalProperties},
+    ui::RenderInUI,
+};
+
+/// Non-dimensional determinant of the position-from-velocity block below which Clohessy–Wiltshire
+/// targeting gives up.
+const SINGULAR_TOLERANCE: f64 = 1e-6;
+
+/// Radial, in-track and cross-track unit vectors of a body at `position` moving at `velocity`,
+/// both relative to its primary, as the columns of a matrix.
+pub fn ric_frame(position: DVec3, velocity: DVec3) -> DMat3 {
+    let radial = position.normalize_or_zero();
+    let cross_track = position.cross(velocity).normalize_or_zero();
+    DMat3::from_cols(radial, cross_track.cross(radial), cross_track)
+}
+
+/// Position and velocity of a chaser relative to a target, in the target's rotating RIC frame.
+/// All four states are relative to the primary the target orbits.
+pub fn relative_motion(
+    target_position: DVec3,
+    target_velocity: DVec3,
+    chaser_position: DVec3,
+    chaser_velocity: DVec3,
+) -> (DVec3, DVec3) {
+    let frame = ric_frame(target_position, target_velocity).transpose();
+    // the frame turns with the target
+    let rotation = target_position.cross(target_velocity) / target_position.length_squared();
+    let position = chaser_position - target_position;
+    let velocity = chaser_velocity - target_velocity - rotation.cross(position);
+    (frame * position, frame * velocity)
+}
+
+/// Rate in m/s at which the range to the target changes, positive when opening.
+pub fn range_rate(position: DVec3, velocity: DVec3) -> f64 {
+    let range = position.length();
+    if range == 0. {
+        return 0.;
+    }
+    position.dot(velocity) / range
+}
+
+/// Blocks of the Clohessy–Wiltshire state transition matrix for `time` seconds around a
+/// circular orbit with mean motion `mean_motion`, mapping position and velocity onto position
+/// (the first two) and velocity (the last two).
+fn cw_transition(mean_motion: f64, time: f64) -> [DMat3; 4] {
+    let n = mean_motion;
+    let (s, c) = (n * time).sin_cos();
+    let nt = n * time;
+    [
+        DMat3::from_cols(
+            DVec3::new(4. - 3. * c, 6. * (s - nt), 0.),
+            DVec3::Y,
+            DVec3::new(0., 0., c),
+        ),
+        DMat3::from_cols(
+            DVec3::new(s / n, 2. * (c - 1.) / n, 0.),
+            DVec3::new(2. * (1. - c) / n, (4. * s - 3. * nt) / n, 0.),
+            DVec3::new(0., 0., s / n),
+        ),
+        DMat3::from_cols(
+            DVec3::new(3. * n * s, 6. * n * (c - 1.), 0.),
+            DVec3::ZERO,
+            DVec3::new(0., 0., -n * s),
+        ),
+        DMat3::from_cols(
+            DVec3::new(c, -2. * s, 0.),
+            DVec3::new(2. * s, 4. * c - 3., 0.),
+            DVec3::new(0., 0., c),
+        ),
+    ]
+}
+
+/// Moves a relative state in the RIC frame on by `time` seconds with the Clohessy–Wiltshire
+/// equations, linearised about a circular target orbit with mean motion `mean_motion`.
+pub fn cw_propagate(
+    mean_motion: f64,
+    position: DVec3,
+    velocity: DVec3,
+    time: f64,
+) -> (DVec3, DVec3) {
+    let [rr, rv, vr, vv] = cw_transition(mean_motion, time);
+    (rr * position + rv * velocity, vr * position + vv * velocity)
+}
+
+/// Two burns in the RIC frame that take a chaser from `position` and `velocity` to rest on the
+/// target in `time` seconds, one now and one on arrival. `None` at transfer times where the
+/// Clohessy–Wiltshire equations can't steer, whole orbits and, out of plane, half orbits.
+pub fn cw_rendezvous(
+    mean_motion: f64,
+    position: DVec3,
+    velocity: DVec3,
+    time: f64,
+) -> Option<(DVec3, DVec3)> {
+    let [rr, rv, vr, vv] = cw_transition(mean_motion, time);
+    // near those times the burns blow up and are all rounding error
+    if time <= 0. || (rv.determinant() * mean_motion.powi(3)).abs() < SINGULAR_TOLERANCE {
+        return None;
+    }
+    let departure = -(rv.inverse() * (rr * position));
+    let arrival = vr * position + vv * departure;
+    Some((departure - velocity, -arrival))
+}
+
+/// Splits a burn in the RIC frame into a maneuver node's prograde, normal and radial parts.
+/// Clohessy–Wiltshire already has the chaser close to a circular target, where those line up
+/// with in-track, cross-track and radial.
+pub fn node_components(delta_v: DVec3) -> (f64, f64, f64) {
+    (delta_v.y, delta_v.z, delta_v.x)
+}
+
+/// Relative position and velocity of `chaser` in the RIC frame of `target`, and the target's
+/// mean motion, around the primary the target's conic is drawn about.
+pub fn relative_to_target(
+    conics: &Conics,
+    properties_query: &Query<&PhysicalProperties>,
+    target: Entity,
+    chaser: Entity,
+) -> Option<(DVec3, DVec3, f64)> {
+    let conic = conics.0.iter().find(|conic| conic.body == target)?;
+    let primary = properties_query.get(conic.primary).ok()?;
+    let state = |body: Entity| {
+        let properties = properties_query.get(body).ok()?;
+        Some((
+            HPVec3::sub(&properties.translation, &primary.translation).to_dvec3(),
+            HPVec3::sub(&properties.acceleration, &primary.acceleration).to_dvec3(),
+        ))
+    };
+    let (target_position, target_velocity) = state(target)?;
+    let (chaser_position, chaser_velocity) = state(chaser)?;
+
+    let (position, velocity) = relative_motion(
+        target_position,
+        target_velocity,
+        chaser_position,
+        chaser_velocity,
+    );
+    let elements = &conic.elements;
+    let mean_motion = (elements.mu / elements.semi_major_axis.powi(3)).sqrt();
+    Some((position, velocity, mean_motion))
+}
+
+/// Readout of the range to the target.
+#[derive(Component)]
+struct RelativeText;
+
+/// Plugin used to show where the target is relative to the focused body.
+pub struct RelativePlugin;
+
+impl Plugin for RelativePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_relative_text);
+        app.add_system(update_relative_text.after(simulation::LABEL));
+    }
+}
+
+fn spawn_relative_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(20.),
+                    top: Val::Px(350.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
+                    font_size: 18.0,
+                    color: Color::rgb(1.0, 0.7, 0.4),
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(RelativeText);
+}
+
+fn update_relative_text(
+    conics: Res<Conics>,
+    focused_query: Query<Entity, With<Focused>>,
+    target_query: Query<(Entity, &RenderInUI), (With<Targeted>, Without<Focused>)>,
+    properties_query: Query<&PhysicalProperties>,
+    mut text_query: Query<&mut Text, With<RelativeText>>,
+) {
+    let mut text = match text_query.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    let relative = match (focused_query.get_single(), target_query.get_single()) {
+        (Ok(focused), Ok((target, name))) => {
+            relative_to_target(&conics, &properties_query, target, focused)
+                .map(|relative| (name, relative))
+        }
+        _ => None,
+    };
+    text.sections[0].value = match relative {
+        Some((name, (position, velocity, _))) => format!(
+            "target {}\nrange {:.3} km\nrate  {:.2} m/s",
+            name.0,
+            position.length() / 1000.,
+            range_rate(position, velocity)
+        ),
+        None => String::new(),
+    };
+}
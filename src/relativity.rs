@@ -0,0 +1,423 @@
+use std::{f64::consts::PI, fmt, thread};
+
+use bevy::{math::DVec3, prelude::*};
+use rug::Float;
+
+use crate::{
+    camera::Focused,
+    headless::{Args, Snapshot},
+    orbit::OrbitalElements,
+    simulation::{
+        self, step, BodyState, HPVec3, PhysicalProperties, SimulationClock, DEFAULT_PRECISION,
+        GRAVITATIONAL_CONSTANT,
+    },
+    sun::Sun,
+};
+
+/// Speed of light in m/s.
+pub const SPEED_OF_LIGHT: f64 = 299_792_458.;
+
+/// Julian century in seconds, the usual unit for perihelion precession.
+const CENTURY: f64 = 36_525. * 86_400.;
+
+/// Arcseconds in a radian.
+const ARCSECONDS: f64 = 180. / PI * 3_600.;
+
+/// Flag on the command line that measures perihelion precession instead of opening a window.
+pub const FLAG: &str = "--perihelion";
+
+const USAGE: &str = "usage: orbital-simulations --perihelion [--body NAME] [--primary NAME] \
+[--with NAME]... [--orbits N] [--timestep SECONDS] [--softening M]";
+
+/// Bodies orbiting an entity with this component get the first post-Newtonian correction of a
+/// Schwarzschild mass on top of its Newtonian pull.
+#[derive(Component)]
+pub struct PostNewtonian;
+
+/// First post-Newtonian acceleration of a test body at `position` with `velocity`, both relative
+/// to a mass with gravitational parameter `mu`.
+pub fn acceleration(mu: f64, position: DVec3, velocity: DVec3) -> DVec3 {
+    let radius = position.length();
+    if radius <= 0. {
+        return DVec3::ZERO;
+    }
+    let factor = mu / (SPEED_OF_LIGHT * SPEED_OF_LIGHT * radius.powi(3));
+    factor
+        * ((4. * mu / radius - velocity.length_squared()) * position
+            + 4. * position.dot(velocity) * velocity)
+}
+
+/// Perihelion precession predicted by general relativity for an orbit, in radians per orbit.
+pub fn precession_per_orbit(elements: &OrbitalElements) -> f64 {
+    6. * PI * elements.mu / (SPEED_OF_LIGHT * SPEED_OF_LIGHT * elements.semi_latus_rectum())
+}
+
+/// Records where a body passes periapsis around `primary`, to measure how the orbit turns.
+#[derive(Component)]
+pub struct PerihelionTracker {
+    pub primary: Entity,
+    first: Option<PerihelionPassage>,
+    latest: Option<PerihelionPassage>,
+    passages: usize,
+    /// Radial velocity and offset from the primary at the previous step.
+    previous: Option<(f64, DVec3)>,
+}
+
+struct PerihelionPassage {
+    time: f64,
+    direction: DVec3,
+}
+
+impl PerihelionTracker {
+    pub fn new(primary: Entity) -> Self {
+        PerihelionTracker {
+            primary,
+            first: None,
+            latest: None,
+            passages: 0,
+            previous: None,
+        }
+    }
+
+    /// Records a step of `timestep` seconds ending `time` seconds into the simulation, which
+    /// left the body at `offset` from the primary, moving at `velocity` relative to it.
+    fn observe(&mut self, time: f64, timestep: f64, offset: DVec3, velocity: DVec3) {
+        let radial_velocity = offset.dot(velocity);
+
+        // periapsis is where the radial velocity turns from falling to rising
+        if let Some((previous_radial, previous_offset)) = self.previous {
+            if previous_radial < 0. && radial_velocity >= 0. {
+                let fraction = previous_radial / (previous_radial - radial_velocity);
+                let passage = PerihelionPassage {
+                    time: time - timestep * (1. - fraction),
+                    direction: previous_offset.lerp(offset, fraction).normalize(),
+                };
+                if self.first.is_none() {
+                    self.first = Some(passage);
+                } else {
+                    self.latest = Some(passage);
+                }
+                self.passages += 1;
+            }
+        }
+        self.previous = Some((radial_velocity, offset));
+    }
+
+    /// Measured turn of periapsis in radians per second, once it's been passed twice.
+    fn precession_rate(&self, normal: DVec3) -> Option<f64> {
+        let (first, latest) = (self.first.as_ref()?, self.latest.as_ref()?);
+        if latest.time <= first.time {
+            return None;
+        }
+        let angle = first
+            .direction
+            .cross(latest.direction)
+            .dot(normal)
+            .atan2(first.direction.dot(latest.direction));
+        Some(angle / (latest.time - first.time))
+    }
+}
+
+/// Readout of the focused body's measured perihelion precession.
+#[derive(Component)]
+struct PrecessionText;
+
+/// Plugin used to switch the post-Newtonian correction and measure perihelion precession.
+pub struct RelativityPlugin;
+
+impl Plugin for RelativityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_precession_text);
+        app.add_system(toggle_post_newtonian);
+        app.add_system(track_perihelia.after(simulation::LABEL));
+        app.add_system(update_precession_text.after(track_perihelia));
+    }
+}
+
+/// Switch the post-Newtonian correction of every simulated body with G.
+fn toggle_post_newtonian(
+    mut commands: Commands,
+    input_keyboard: Res<Input<KeyCode>>,
+    pn_query: Query<Entity, With<PostNewtonian>>,
+    sun_query: Query<Entity, With<Sun>>,
+) {
+    if !input_keyboard.just_pressed(KeyCode::G) {
+        return;
+    }
+
+    if pn_query.is_empty() {
+        for sun in sun_query.iter() {
+            commands.entity(sun).insert(PostNewtonian);
+        }
+        info!("post-Newtonian correction on");
+    } else {
+        for entity in pn_query.iter() {
+            commands.entity(entity).remove::<PostNewtonian>();
+        }
+        info!("post-Newtonian correction off");
+    }
+}
+
+fn track_perihelia(
+    clock: Res<SimulationClock>,
+    mut tracker_query: Query<(&mut PerihelionTracker, &PhysicalProperties)>,
+    primary_query: Query<&PhysicalProperties>,
+) {
+    for (mut tracker, properties) in tracker_query.iter_mut() {
+        let primary = match primary_query.get(tracker.primary) {
+            Ok(primary) => primary,
+            Err(_) => continue,
+        };
+        let offset = HPVec3::sub(&properties.translation, &primary.translation).to_dvec3();
+        let velocity = HPVec3::sub(&properties.acceleration, &primary.acceleration).to_dvec3();
+        tracker.observe(clock.elapsed, clock.timestep, offset, velocity);
+    }
+}
+
+fn spawn_precession_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(20.),
+                    top: Val::Px(230.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
+                    font_size: 18.0,
+                    color: Color::rgb(0.8, 0.6, 1.0),
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(PrecessionText);
+}
+
+fn update_precession_text(
+    focused_query: Query<(&PerihelionTracker, &PhysicalProperties), With<Focused>>,
+    primary_query: Query<(&PhysicalProperties, Option<&PostNewtonian>)>,
+    mut text_query: Query<&mut Text, With<PrecessionText>>,
+) {
+    let mut text = match text_query.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    let readout = focused_query
+        .get_single()
+        .ok()
+        .and_then(|(tracker, properties)| {
+            let (primary, post_newtonian) = primary_query.get(tracker.primary).ok()?;
+            let offset = HPVec3::sub(&properties.translation, &primary.translation).to_dvec3();
+            let velocity = HPVec3::sub(&properties.acceleration, &primary.acceleration).to_dvec3();
+            let mu = (GRAVITATIONAL_CONSTANT * primary.mass.clone()).to_f64();
+            let elements = OrbitalElements::from_state(offset, velocity, mu);
+
+            let expected = elements
+                .period()
+                .map(|period| precession_per_orbit(&elements) / period * CENTURY * ARCSECONDS)
+                .unwrap_or_default();
+            let measured = match tracker.precession_rate(elements.normal) {
+                Some(rate) => format!(
+                    "{:.2}\"/cy over {} passes",
+                    rate * CENTURY * ARCSECONDS,
+                    tracker.passages
+                ),
+                None => format!("waiting, {} passes", tracker.passages),
+            };
+
+            Some(format!(
+                "perihelion {}\nGR expects {:.2}\"/cy, 1PN {}",
+                measured,
+                expected,
+                if post_newtonian.is_some() {
+                    "on"
+                } else {
+                    "off"
+                }
+            ))
+        });
+
+    text.sections[0].value = readout.unwrap_or_default();
+}
+
+/// Which orbit to measure the precession of and how finely, read off the command line.
+#[derive(Clone, Debug)]
+pub struct PerihelionSettings {
+    /// Name of the orbiting body.
+    pub body: String,
+    /// Name of the body it orbits, which gets the post-Newtonian correction.
+    pub primary: String,
+    /// Names of other bodies flown along, whose pull turns the orbit as well.
+    pub with: Vec<String>,
+    /// Orbits flown, there have to be at least two to see periapsis move.
+    pub orbits: usize,
+    pub timestep: f64,
+}
+
+impl Default for PerihelionSettings {
+    fn default() -> Self {
+        PerihelionSettings {
+            body: "Mercury".to_string(),
+            primary: "Sun".to_string(),
+            with: Vec::new(),
+            orbits: 10,
+            timestep: 600.,
+        }
+    }
+}
+
+impl PerihelionSettings {
+    /// Settings from the command line arguments after the program name, or `None` without
+    /// `FLAG`.
+    pub fn from_args(args: &[String]) -> Result<Option<PerihelionSettings>, PerihelionError> {
+        let mut args = match Args::find(args, FLAG) {
+            Some(args) => args,
+            None => return Ok(None),
+        };
+
+        let mut settings = PerihelionSettings::default();
+        while let Some(arg) = args.next() {
+            let value = match arg.as_str() {
+                "--body" => args.value(&arg).map(|name| settings.body = name),
+                "--primary" => args.value(&arg).map(|name| settings.primary = name),
+                "--with" => args.value(&arg).map(|name| settings.with.push(name)),
+                "--orbits" => args.value(&arg).map(|orbits| settings.orbits = orbits),
+                "--timestep" => args
+                    .value(&arg)
+                    .map(|timestep| settings.timestep = timestep),
+                _ => Err(format!("unknown argument {}", arg)),
+            };
+            value.map_err(PerihelionError::Usage)?;
+        }
+        if settings.orbits < 2 || settings.timestep <= 0. {
+            return Err(PerihelionError::Usage(
+                "--orbits has to be at least 2 and --timestep positive".to_string(),
+            ));
+        }
+        Ok(Some(settings))
+    }
+}
+
+#[derive(Debug)]
+pub enum PerihelionError {
+    Usage(String),
+    MissingBody(String),
+    /// The body isn't on a closed orbit around the primary.
+    Unbound(String),
+    /// Periapsis wasn't passed twice, the timestep is too coarse to see it.
+    TooFewPassages(usize),
+}
+
+impl fmt::Display for PerihelionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PerihelionError::Usage(problem) => write!(f, "{}\n{}", problem, USAGE),
+            PerihelionError::MissingBody(name) => write!(f, "there's no body called {}", name),
+            PerihelionError::Unbound(name) => write!(f, "{} isn't on a closed orbit", name),
+            PerihelionError::TooFewPassages(passages) => write!(
+                f,
+                "periapsis was only seen {} times, try a smaller --timestep",
+                passages
+            ),
+        }
+    }
+}
+
+/// Flies the body in `settings` around its primary twice, with and without the post-Newtonian
+/// correction, and prints how much faster periapsis turns with it next to what general
+/// relativity predicts.
+///
+/// Taking the run without the correction away leaves out the turn from other bodies and from
+/// the integrator itself, which at coarse timesteps is far bigger than the relativistic one.
+pub fn check_perihelion(
+    world: &mut World,
+    settings: &PerihelionSettings,
+) -> Result<(), PerihelionError> {
+    let snapshot = Snapshot::from_world(world);
+    let find = |name: &String| {
+        snapshot
+            .find(name)
+            .ok_or_else(|| PerihelionError::MissingBody(name.clone()))
+    };
+    let mut bodies = vec![
+        snapshot.bodies[find(&settings.primary)?].clone(),
+        snapshot.bodies[find(&settings.body)?].clone(),
+    ];
+    for name in settings.with.iter() {
+        bodies.push(snapshot.bodies[find(name)?].clone());
+    }
+
+    let offset = HPVec3::sub(&bodies[1].translation, &bodies[0].translation).to_dvec3();
+    let velocity = HPVec3::sub(&bodies[1].velocity, &bodies[0].velocity).to_dvec3();
+    let mu = (GRAVITATIONAL_CONSTANT * bodies[0].mass.clone()).to_f64();
+    let elements = OrbitalElements::from_state(offset, velocity, mu);
+    let period = elements
+        .period()
+        .ok_or_else(|| PerihelionError::Unbound(settings.body.clone()))?;
+    // half an orbit more, so the last periapsis isn't missed
+    let duration = (settings.orbits as f64 + 0.5) * period;
+    info!(
+        "flying {} around {} for {} orbits at {} s steps, with and without 1PN",
+        settings.body, settings.primary, settings.orbits, settings.timestep
+    );
+
+    let (with_correction, without_correction) = thread::scope(|scope| {
+        let with_correction =
+            scope.spawn(|| precession_rate(bodies.clone(), true, duration, settings.timestep));
+        let without_correction =
+            precession_rate(bodies.clone(), false, duration, settings.timestep);
+        let with_correction = with_correction
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        (with_correction, without_correction)
+    });
+    let arcseconds_per_century = |tracker: &PerihelionTracker| {
+        tracker
+            .precession_rate(elements.normal)
+            .map(|rate| rate * CENTURY * ARCSECONDS)
+            .ok_or(PerihelionError::TooFewPassages(tracker.passages))
+    };
+    let measured = arcseconds_per_century(&with_correction)?;
+    let baseline = arcseconds_per_century(&without_correction)?;
+    let expected = precession_per_orbit(&elements) / period * CENTURY * ARCSECONDS;
+
+    println!("with 1PN       {:.3}\"/cy", measured);
+    println!("without 1PN    {:.3}\"/cy", baseline);
+    println!("1PN difference {:.3}\"/cy", measured - baseline);
+    println!("GR expects     {:.3}\"/cy", expected);
+    Ok(())
+}
+
+/// Flies `bodies` for `duration` seconds, the first being the primary of the second, and tracks
+/// the second's periapsis passages.
+fn precession_rate(
+    mut bodies: Vec<BodyState>,
+    post_newtonian: bool,
+    duration: f64,
+    timestep: f64,
+) -> PerihelionTracker {
+    for body in bodies.iter_mut() {
+        body.post_newtonian = false;
+    }
+    bodies[0].post_newtonian = post_newtonian;
+
+    let mut tracker = PerihelionTracker::new(bodies[0].entity);
+    let step_size = Float::with_val(DEFAULT_PRECISION, timestep);
+    let mut now = 0.;
+    while now < duration {
+        step(&mut bodies, &step_size);
+        now += timestep;
+        let offset = HPVec3::sub(&bodies[1].translation, &bodies[0].translation).to_dvec3();
+        let velocity = HPVec3::sub(&bodies[1].velocity, &bodies[0].velocity).to_dvec3();
+        tracker.observe(now, timestep, offset, velocity);
+    }
+    tracker
+}
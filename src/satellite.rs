@@ -1 +1,194 @@
+use crate::atmosphere::DragProperties;
+use crate::camera::{Focusable, Targeted};
+use crate::covariance::Covariance;
+use crate::earth::Earth;
+use crate::engine::{Attitude, Engine};
+use crate::radiation::{RadiationPressure, ShadowModel};
+use crate::simulation::{HPVec3, PhysicalProperties, Simulated, GRAVITATIONAL_CONSTANT};
+use crate::trail::Trail;
+use crate::ui::RenderInUI;
+use crate::variational::StateTransition;
+use bevy::{math::DVec3, prelude::*};
+use rug::Float;
 
+/// Rough size of a small satellite in meters, it's drawn far larger than this anyway.
+const RADIUS: f32 = 2.;
+
+/// Approximate mass of a small satellite in kg.
+const MASS: f32 = 1_000.;
+
+/// Mass in kg with the propellant used up.
+const DRY_MASS: f64 = 700.;
+
+/// Thrust of a small bipropellant apogee engine in newtons.
+const THRUST: f64 = 400.;
+
+/// Specific impulse of the same engine in seconds.
+const SPECIFIC_IMPULSE: f64 = 320.;
+
+/// Cross sectional area of a small satellite in m².
+const AREA: f64 = 4.;
+
+/// Typical drag coefficient of a satellite in free molecular flow.
+const DRAG_COEFFICIENT: f64 = 2.2;
+
+/// Radiation pressure coefficient of a satellite that reflects some of the light hitting it.
+const REFLECTIVITY: f64 = 1.3;
+
+/// Starting position uncertainty of a spacecraft in meters along each axis, typical of a
+/// ground tracked orbit determination.
+pub const POSITION_SIGMA: f64 = 100.;
+
+/// Starting velocity uncertainty of a spacecraft in m/s along each axis.
+pub const VELOCITY_SIGMA: f64 = 0.1;
+
+/// Height above the earth's equator in meters.
+const ALTITUDE: f64 = 700_000.;
+
+/// Inclination in degrees from the earth's spin axis, chosen so J2 turns the orbit's node once a
+/// year in the same sense the sun moves around the earth, a sun-synchronous orbit at this
+/// altitude.
+///
+/// The earth here spins about +Y but orbits the sun about -Y, backwards compared to the real
+/// one, so the usual retrograde 98.2° would drift the node against the sun. Measured from this
+/// spin axis the orbit has to be prograde instead, at the mirror image of 98.2°.
+const INCLINATION: f64 = 81.84;
+
+/// Simulated seconds of the satellite's path kept in its trail, a few orbits.
+const TRAIL_LENGTH: f64 = 3. * 3_600.;
+
+/// How far the chaser starts behind the satellite along its orbit, in meters.
+const CHASER_LAG: f64 = 10_000.;
+
+/// How far the chaser starts below the satellite, in meters.
+const CHASER_DROP: f64 = 1_000.;
+
+#[derive(Component)]
+pub struct Satellite;
+
+/// Spawns a satellite on a circular low earth orbit, starting at its ascending node over the
+/// earth's equator, and a chaser a little below and behind it to rendezvous with it. Has to run
+/// after the earth exists.
+pub fn setup_satellite(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    earth_query: Query<(Entity, &PhysicalProperties, &Transform), With<Earth>>,
+) {
+    let (earth, earth_properties, earth_transform) = match earth_query.get_single() {
+        Ok(earth) => earth,
+        Err(_) => return,
+    };
+
+    let earth_rotation = earth_transform.rotation.as_f64();
+    let pole = earth_rotation * DVec3::Y;
+    let node = earth_rotation * DVec3::X;
+
+    let radius = earth_properties.estimated_radius.to_f64() + ALTITUDE;
+    let mu = (GRAVITATIONAL_CONSTANT * earth_properties.mass.clone()).to_f64();
+
+    let inclination = INCLINATION.to_radians();
+    let direction = pole.cross(node) * inclination.cos() + pole * inclination.sin();
+
+    // circular orbit at `distance`, `angle` radians on from the ascending node
+    let state = |distance: f64, angle: f64| {
+        let (sin, cos) = angle.sin_cos();
+        let orbital_velocity = (mu / distance).sqrt();
+        (
+            HPVec3::add(
+                &earth_properties.translation,
+                &HPVec3::from_dvec3((node * cos + direction * sin) * distance),
+            ),
+            HPVec3::add(
+                &earth_properties.acceleration,
+                &HPVec3::from_dvec3((direction * cos - node * sin) * orbital_velocity),
+            ),
+        )
+    };
+
+    let (translation, velocity) = state(radius, 0.);
+    let satellite = spawn_spacecraft(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        earth,
+        "Satellite",
+        Color::rgb(0.9, 0.8, 0.3),
+        translation,
+        velocity,
+    );
+    commands
+        .entity(satellite)
+        .insert(Satellite)
+        .insert(Targeted);
+
+    let (translation, velocity) = state(radius - CHASER_DROP, -CHASER_LAG / radius);
+    spawn_spacecraft(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        earth,
+        "Chaser",
+        Color::rgb(0.4, 0.8, 0.9),
+        translation,
+        velocity,
+    );
+}
+
+fn spawn_spacecraft(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    earth: Entity,
+    name: &str,
+    color: Color,
+    translation: HPVec3,
+    velocity: HPVec3,
+) -> Entity {
+    let mut trail_color = color;
+    trail_color.set_a(0.8);
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Icosphere {
+                radius: 0.5,
+                subdivisions: 1,
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: color,
+                unlit: true,
+                ..default()
+            }),
+            transform: Transform::from_scale(Vec3::splat(RADIUS * 2.)),
+            ..default()
+        })
+        .insert(RenderInUI(name.to_string()))
+        .insert(Simulated)
+        .insert(PhysicalProperties {
+            mass: Float::with_val(128, MASS),
+            estimated_radius: Float::with_val(128, RADIUS),
+            acceleration: velocity,
+            translation,
+        })
+        .insert(DragProperties {
+            area: AREA,
+            drag_coefficient: DRAG_COEFFICIENT,
+        })
+        .insert(RadiationPressure {
+            area: AREA,
+            reflectivity: REFLECTIVITY,
+            shadow: ShadowModel::Conical,
+        })
+        .insert(Engine {
+            thrust: THRUST,
+            specific_impulse: SPECIFIC_IMPULSE,
+            throttle: 0.,
+            attitude: Attitude::Prograde,
+            dry_mass: DRY_MASS,
+        })
+        .insert(Trail::new(Some(earth), TRAIL_LENGTH, trail_color))
+        .insert(StateTransition::default())
+        .insert(Covariance::diagonal(POSITION_SIGMA, VELOCITY_SIGMA, 0.))
+        .insert(Focusable)
+        .id()
+}
@@ -1,24 +1,45 @@
-use bevy::{core::FixedTimestep, prelude::*};
+use bevy::{
+    ecs::system::SystemParam,
+    math::{DQuat, DVec3},
+    prelude::*,
+};
 use rug::Float;
 
+use crate::{
+    atmosphere::{Atmosphere, DragProperties},
+    engine::Engine,
+    gravity::GravityField,
+    headless,
+    maneuver::{advance_through, ManeuverNode},
+    orbit::primaries,
+    radiation::{illumination, RadiationPressure},
+    relativity::{self, PostNewtonian},
+    sun::Sun,
+    ui::RenderInUI,
+    variational::{drift_transitions, kick_transitions, StateMatrix, StateTransition},
+};
+
 pub const LABEL: &str = "SIMULATION_TIMESTEP";
 
-const GRAVITATIONAL_CONSTANT: f32 = 6.674e-10_f32;
+pub const GRAVITATIONAL_CONSTANT: f32 = 6.674e-11_f32;
 
-const DEFAULT_PRECISION: u32 = 128;
+pub const DEFAULT_PRECISION: u32 = 128;
 
 #[derive(Component)]
 pub struct Simulated;
 
-/// Entities with this component are targeted by the gravity caluclation system.
-#[derive(Component)]
-pub struct Gravity;
-
+/// Spins an entity around its local Y axis.
 #[derive(Component)]
 pub struct Rotating {
     pub degrees_per_second: Float,
 }
 
+impl Rotating {
+    pub fn radians_per_second(&self) -> f64 {
+        self.degrees_per_second.to_f64().to_radians()
+    }
+}
+
 /// Entities with this component have mass, which is required for gravity calculations.
 #[derive(Component)]
 pub struct PhysicalProperties {
@@ -67,24 +88,21 @@ impl HPVec3 {
         )
     }
 
-    pub fn distance(&mut self, b: &HPVec3) -> Float {
+    pub fn distance(&self, b: &HPVec3) -> Float {
         (Float::with_val(DEFAULT_PRECISION, &self.x - &b.x).square()
             + Float::with_val(DEFAULT_PRECISION, &self.y - &b.y).square()
             + Float::with_val(DEFAULT_PRECISION, &self.z - &b.z).square())
         .sqrt()
     }
 
-    pub fn normalize(&self) -> HPVec3 {
-        let magnitude = (Float::with_val(DEFAULT_PRECISION, self.x.square_ref())
+    pub fn length_squared(&self) -> Float {
+        Float::with_val(DEFAULT_PRECISION, self.x.square_ref())
             + Float::with_val(DEFAULT_PRECISION, self.y.square_ref())
-            + Float::with_val(DEFAULT_PRECISION, self.z.square_ref()))
-        .sqrt();
+            + Float::with_val(DEFAULT_PRECISION, self.z.square_ref())
+    }
 
-        HPVec3 {
-            x: Float::with_val(DEFAULT_PRECISION, &self.x / &magnitude),
-            y: Float::with_val(DEFAULT_PRECISION, &self.y / &magnitude),
-            z: Float::with_val(DEFAULT_PRECISION, &self.z / &magnitude),
-        }
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
     }
 
     #[inline(always)]
@@ -100,8 +118,16 @@ impl HPVec3 {
         }
     }
 
-    pub fn to_vec3(&self) -> Vec3 {
-        Vec3::new(self.x.to_f32(), self.y.to_f32(), self.z.to_f32())
+    pub fn to_dvec3(&self) -> DVec3 {
+        DVec3::new(self.x.to_f64(), self.y.to_f64(), self.z.to_f64())
+    }
+
+    pub fn from_dvec3(vec: DVec3) -> HPVec3 {
+        HPVec3 {
+            x: Float::with_val(DEFAULT_PRECISION, vec.x),
+            y: Float::with_val(DEFAULT_PRECISION, vec.y),
+            z: Float::with_val(DEFAULT_PRECISION, vec.z),
+        }
     }
 
     pub fn from_vec3(vec: &Vec3) -> HPVec3 {
@@ -117,88 +143,665 @@ impl HPVec3 {
 #[derive(Component)]
 pub struct ReferenceFrame;
 
-/// ECS Stage that represents when the simulation step is calculated.
-#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
-struct SimulationUpdateStage;
+/// Simulated time, in seconds since the start of the simulation.
+pub struct SimulationClock {
+    pub elapsed: f64,
+    /// Simulated seconds advanced by every simulation step.
+    pub timestep: f64,
+    /// Nothing is stepped while paused.
+    pub paused: bool,
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        SimulationClock {
+            elapsed: 0.,
+            timestep: 1.,
+            paused: false,
+        }
+    }
+}
+
+/// Settings for how gravity between point masses is worked out.
+#[derive(Clone, Default)]
+pub struct GravitySettings {
+    /// Plummer softening length in meters, keeping the pull finite as two bodies meet.
+    pub softening: f64,
+}
+
+impl GravitySettings {
+    /// Takes `--softening METERS` out of the command line, returning the settings and the
+    /// arguments left for the command.
+    pub fn from_args(args: &[String]) -> Result<(GravitySettings, Vec<String>), String> {
+        let mut settings = GravitySettings::default();
+        let mut rest = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg != "--softening" {
+                rest.push(arg.clone());
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", arg))?;
+            settings.softening = headless::parse(arg, value)?;
+            if settings.softening < 0. || !settings.softening.is_finite() {
+                return Err(format!("{} has to be a length of at least 0", arg));
+            }
+        }
+        Ok((settings, rest))
+    }
+}
+
+/// Sent when the simulation can't go on. The clock is paused and the last good state is kept.
+pub struct SimulationFault {
+    /// Bodies whose position or velocity diverged, or the two bodies that met.
+    pub entities: Vec<Entity>,
+    /// Simulated seconds since the start of the simulation, before the failed step.
+    pub time: f64,
+    pub cause: FaultCause,
+}
+
+/// Why the simulation stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultCause {
+    /// Positions or velocities stopped being finite.
+    Diverged,
+    /// Two bodies sat exactly on top of each other without softening, so the pull between them
+    /// is undefined.
+    Coincident,
+}
 
 /// ECS Plugin used to encapsulate the simulation update at a fixed timestep.
 pub struct SimulationPlugin;
 
 impl Plugin for SimulationPlugin {
     fn build(&self, app: &mut App) {
-        /*         app.add_stage_after(
-            CoreStage::Update,
-            SimulationUpdateStage,
-            SystemStage::single_threaded()
-                .with_run_criteria(FixedTimestep::step(1. / 10000.).with_label(LABEL))
-                .with_system(simulation_step.exclusive_system()),
-        ); */
-        app.add_system(simulation_step);
+        app.init_resource::<SimulationClock>();
+        app.init_resource::<GravitySettings>();
+        app.add_event::<SimulationFault>();
+        app.add_system(toggle_pause);
+        app.add_system(log_simulation_faults.after(LABEL));
+        app.add_system(simulation_step.label(LABEL));
         app.add_system(rotation_step);
+        app.add_system(insert_acceleration_breakdowns);
+    }
+}
+
+/// Snapshot of a simulated body, detached from the ECS so it can be integrated on its own.
+#[derive(Clone)]
+pub struct BodyState {
+    pub entity: Entity,
+    pub mass: Float,
+    pub translation: HPVec3,
+    /// Per second velocity, stored as `PhysicalProperties::acceleration` on the entity.
+    pub velocity: HPVec3,
+    /// Reference frames are not moved by the integrator.
+    pub fixed: bool,
+    pub radius: f64,
+    /// Plummer softening length in meters.
+    pub softening: f64,
+    pub orientation: DQuat,
+    /// Angular velocity in radians per second, turning the orientation as the body is integrated.
+    pub spin: DVec3,
+    // force models beyond the point mass, attached by `ForceModels`
+    pub gravity_field: Option<GravityField>,
+    pub atmosphere: Option<Atmosphere>,
+    pub drag: Option<DragProperties>,
+    /// Whether the body shines, pushing on bodies with radiation pressure.
+    pub light_source: bool,
+    pub radiation: Option<RadiationPressure>,
+    /// Whether bodies around this one get the post-Newtonian correction.
+    pub post_newtonian: bool,
+    pub engine: Option<Engine>,
+    /// State transition matrix integrated along with the body, if it's tracked.
+    pub transition: Option<StateMatrix>,
+}
+
+impl BodyState {
+    pub fn new(entity: Entity, properties: &PhysicalProperties, fixed: bool) -> BodyState {
+        BodyState {
+            entity,
+            mass: properties.mass.clone(),
+            translation: properties.translation.clone(),
+            velocity: properties.acceleration.clone(),
+            fixed,
+            radius: properties.estimated_radius.to_f64(),
+            softening: 0.,
+            orientation: DQuat::IDENTITY,
+            spin: DVec3::ZERO,
+            gravity_field: None,
+            atmosphere: None,
+            drag: None,
+            light_source: false,
+            radiation: None,
+            post_newtonian: false,
+            engine: None,
+            transition: None,
+        }
+    }
+
+    /// Writes the integrated state back onto the entity's physical properties.
+    pub fn apply(&self, properties: &mut PhysicalProperties) {
+        properties.translation = self.translation.clone();
+        properties.acceleration = self.velocity.clone();
+        // engines burn mass off as they thrust
+        properties.mass = self.mass.clone();
+    }
+}
+
+/// Where a contribution to a body's acceleration came from, along with the other body involved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForceSource {
+    /// Point mass gravity.
+    Gravity(Entity),
+    /// Gravity field of a non-spherical body, beyond its point mass.
+    GravityField(Entity),
+    Drag(Entity),
+    RadiationPressure(Entity),
+    /// Post-Newtonian correction to point mass gravity.
+    PostNewtonian(Entity),
+    /// The body's own engine.
+    Thrust(Entity),
+}
+
+/// Acceleration of a body, summed in high precision and split up by source.
+#[derive(Clone)]
+pub struct BodyAcceleration {
+    pub total: HPVec3,
+    pub sources: Vec<(ForceSource, DVec3)>,
+    /// Later bodies this one sat exactly on top of, with no softening to keep their pull finite.
+    /// Their pull on each other is left out.
+    pub coincident: Vec<Entity>,
+}
+
+impl BodyAcceleration {
+    fn add(&mut self, source: ForceSource, acceleration: HPVec3) {
+        self.sources.push((source, acceleration.to_dvec3()));
+        self.total.add_self(&acceleration);
+    }
+}
+
+/// Per source contributions to a body's acceleration over the last simulation step.
+#[derive(Component, Default)]
+pub struct AccelerationBreakdown(pub Vec<(ForceSource, DVec3)>);
+
+/// Acceleration of every body from the gravity of every other body, and from any force models
+/// attached to the states.
+pub fn accelerations(bodies: &[BodyState]) -> Vec<BodyAcceleration> {
+    let mut accelerations = vec![
+        BodyAcceleration {
+            total: HPVec3::zero(),
+            sources: Vec::new(),
+            coincident: Vec::new(),
+        };
+        bodies.len()
+    ];
+
+    for a in 0..bodies.len() {
+        for b in (a + 1)..bodies.len() {
+            let (a_state, b_state) = (&bodies[a], &bodies[b]);
+
+            // grab the offset between the physical objects, softened so close passes stay finite
+            let offset = HPVec3::sub(&b_state.translation, &a_state.translation);
+            let softening = (a_state.softening.powi(2) + b_state.softening.powi(2)) / 2.;
+            let distance_squared = offset.length_squared() + softening;
+            if distance_squared.is_zero() {
+                accelerations[a].coincident.push(b_state.entity);
+                continue;
+            }
+
+            // G / d³, which scales the offset into an acceleration once multiplied by a mass
+            let scale = GRAVITATIONAL_CONSTANT
+                / Float::with_val(
+                    DEFAULT_PRECISION,
+                    distance_squared.clone().sqrt() * &distance_squared,
+                );
+            let a_acceleration = Float::with_val(DEFAULT_PRECISION, &scale * &b_state.mass);
+            let b_acceleration = Float::with_val(DEFAULT_PRECISION, -(scale * &a_state.mass));
+
+            accelerations[a].add(
+                ForceSource::Gravity(b_state.entity),
+                HPVec3::scalar_mul(&offset, &a_acceleration),
+            );
+            accelerations[b].add(
+                ForceSource::Gravity(a_state.entity),
+                HPVec3::scalar_mul(&offset, &b_acceleration),
+            );
+        }
+    }
+
+    for (a, a_state) in bodies.iter().enumerate() {
+        let field = match &a_state.gravity_field {
+            Some(field) => field,
+            None => continue,
+        };
+        let mu = (GRAVITATIONAL_CONSTANT * a_state.mass.clone()).to_f64();
+
+        for (b, b_state) in bodies.iter().enumerate() {
+            if a == b {
+                continue;
+            }
+
+            let offset = HPVec3::sub(&b_state.translation, &a_state.translation).to_dvec3();
+            // coincident bodies are already reported by the point mass loop
+            if offset == DVec3::ZERO {
+                continue;
+            }
+            let b_acceleration = field.acceleration(mu, offset, a_state.orientation);
+
+            // the body with the field is pulled back just as hard
+            let mass_ratio = (b_state.mass.clone() / &a_state.mass).to_f64();
+            accelerations[b].add(
+                ForceSource::GravityField(a_state.entity),
+                HPVec3::from_dvec3(b_acceleration),
+            );
+            accelerations[a].add(
+                ForceSource::GravityField(b_state.entity),
+                HPVec3::from_dvec3(-b_acceleration * mass_ratio),
+            );
+        }
+    }
+
+    for (a, a_state) in bodies.iter().enumerate() {
+        if !a_state.post_newtonian {
+            continue;
+        }
+        let mu = (GRAVITATIONAL_CONSTANT * a_state.mass.clone()).to_f64();
+
+        for (b, b_state) in bodies.iter().enumerate() {
+            if a == b {
+                continue;
+            }
+
+            let offset = HPVec3::sub(&b_state.translation, &a_state.translation).to_dvec3();
+            let velocity = HPVec3::sub(&b_state.velocity, &a_state.velocity).to_dvec3();
+            let b_acceleration = relativity::acceleration(mu, offset, velocity);
+
+            let mass_ratio = (b_state.mass.clone() / &a_state.mass).to_f64();
+            accelerations[b].add(
+                ForceSource::PostNewtonian(a_state.entity),
+                HPVec3::from_dvec3(b_acceleration),
+            );
+            accelerations[a].add(
+                ForceSource::PostNewtonian(b_state.entity),
+                HPVec3::from_dvec3(-b_acceleration * mass_ratio),
+            );
+        }
+    }
+
+    for (a, a_state) in bodies.iter().enumerate() {
+        let atmosphere = match &a_state.atmosphere {
+            Some(atmosphere) => atmosphere,
+            None => continue,
+        };
+
+        for (b, b_state) in bodies.iter().enumerate() {
+            let drag = match &b_state.drag {
+                Some(drag) if a != b => drag,
+                _ => continue,
+            };
+
+            let offset = HPVec3::sub(&b_state.translation, &a_state.translation).to_dvec3();
+            let density = atmosphere.density(offset.length() - a_state.radius);
+            if density <= 0. {
+                continue;
+            }
+
+            // the atmosphere turns with the body it belongs to
+            let relative_velocity = HPVec3::sub(&b_state.velocity, &a_state.velocity).to_dvec3()
+                - a_state.spin.cross(offset);
+            let mass = b_state.mass.to_f64();
+            let b_acceleration = drag.acceleration(mass, density, relative_velocity);
+
+            let mass_ratio = mass / a_state.mass.to_f64();
+            accelerations[b].add(
+                ForceSource::Drag(a_state.entity),
+                HPVec3::from_dvec3(b_acceleration),
+            );
+            accelerations[a].add(
+                ForceSource::Drag(b_state.entity),
+                HPVec3::from_dvec3(-b_acceleration * mass_ratio),
+            );
+        }
+    }
+
+    for (a, a_state) in bodies.iter().enumerate() {
+        if !a_state.light_source {
+            continue;
+        }
+
+        for (b, b_state) in bodies.iter().enumerate() {
+            let radiation = match &b_state.radiation {
+                Some(radiation) if a != b => radiation,
+                _ => continue,
+            };
+
+            // shadows are worked out around the lit body to keep the offsets small
+            let sun = HPVec3::sub(&a_state.translation, &b_state.translation).to_dvec3();
+            let lit = bodies
+                .iter()
+                .enumerate()
+                .filter(|(o, o_state)| *o != a && *o != b && o_state.radius > 0.)
+                .map(|(_, o_state)| {
+                    let occluder =
+                        HPVec3::sub(&o_state.translation, &b_state.translation).to_dvec3();
+                    illumination(
+                        radiation.shadow,
+                        DVec3::ZERO,
+                        sun,
+                        a_state.radius,
+                        occluder,
+                        o_state.radius,
+                    )
+                })
+                .fold(1., f64::min);
+            if lit <= 0. {
+                continue;
+            }
+
+            let b_acceleration = radiation.acceleration(b_state.mass.to_f64(), -sun, lit);
+            accelerations[b].add(
+                ForceSource::RadiationPressure(a_state.entity),
+                HPVec3::from_dvec3(b_acceleration),
+            );
+        }
+    }
+
+    // thrust is pointed relative to whatever the craft orbits, worked out only if anything fires
+    let mut body_primaries = None;
+    for (b, b_state) in bodies.iter().enumerate() {
+        let mass = b_state.mass.to_f64();
+        let engine = match &b_state.engine {
+            Some(engine) if engine.firing(mass) => engine,
+            _ => continue,
+        };
+
+        let body_primaries = body_primaries.get_or_insert_with(|| primaries(bodies));
+        let (position, velocity) = match body_primaries[b] {
+            Some(primary) => (
+                HPVec3::sub(&b_state.translation, &bodies[primary].translation).to_dvec3(),
+                HPVec3::sub(&b_state.velocity, &bodies[primary].velocity).to_dvec3(),
+            ),
+            None => (b_state.translation.to_dvec3(), b_state.velocity.to_dvec3()),
+        };
+        let b_acceleration = engine.acceleration(mass, engine.direction(position, velocity));
+        accelerations[b].add(
+            ForceSource::Thrust(b_state.entity),
+            HPVec3::from_dvec3(b_acceleration),
+        );
+    }
+
+    accelerations
+}
+
+/// Advances every body by one step of `timestep` seconds, returning the accelerations applied.
+///
+/// Uses a drift-kick-drift leapfrog, which keeps orbits from spiralling outwards the way a plain
+/// Euler step does. Close satellites would otherwise drift faster than any perturbation we model.
+pub fn step(bodies: &mut [BodyState], timestep: &Float) -> Vec<BodyAcceleration> {
+    let half_step = Float::with_val(DEFAULT_PRECISION, timestep / 2);
+    drift(bodies, &half_step);
+    drift_transitions(bodies, half_step.to_f64());
+    // thrust is worked out with the mass halfway through the step
+    burn_propellant(bodies, half_step.to_f64());
+
+    let accelerations = accelerations(bodies);
+    for (body, acceleration) in bodies.iter_mut().zip(accelerations.iter()) {
+        body.velocity
+            .add_self(&HPVec3::scalar_mul(&acceleration.total, timestep));
+    }
+    // the transition matrices follow the same map with the gradient of point mass gravity and
+    // gravity fields, leaving out drag, radiation pressure, the post-Newtonian correction and
+    // thrust, which are small or short lived next to them
+    kick_transitions(bodies, timestep.to_f64());
+
+    drift(bodies, &half_step);
+    drift_transitions(bodies, half_step.to_f64());
+    burn_propellant(bodies, half_step.to_f64());
+
+    for body in bodies.iter_mut() {
+        body.orientation =
+            DQuat::from_scaled_axis(body.spin * timestep.to_f64()) * body.orientation;
+    }
+
+    accelerations
+}
+
+/// Takes the propellant every firing engine uses over `seconds` off its body's mass.
+fn burn_propellant(bodies: &mut [BodyState], seconds: f64) {
+    for body in bodies.iter_mut() {
+        let engine = match &body.engine {
+            Some(engine) => engine,
+            None => continue,
+        };
+        let used = (engine.mass_flow() * seconds).min(engine.propellant(body.mass.to_f64()));
+        if used > 0. {
+            body.mass -= used;
+        }
+    }
+}
+
+/// Moves every body that isn't fixed along its velocity.
+fn drift(bodies: &mut [BodyState], timestep: &Float) {
+    for body in bodies.iter_mut().filter(|body| !body.fixed) {
+        let displacement = HPVec3::scalar_mul(&body.velocity, timestep);
+        body.translation.add_self(&displacement);
+    }
+}
+
+/// Snapshots the simulated bodies. Nothing moves unless a reference frame is present.
+pub fn body_states<'a>(
+    bodies: impl Iterator<Item = (Entity, &'a PhysicalProperties)>,
+    reference: Option<Entity>,
+) -> Vec<BodyState> {
+    bodies
+        .map(|(entity, properties)| {
+            let fixed = reference.is_none_or(|reference| reference == entity);
+            BodyState::new(entity, properties, fixed)
+        })
+        .collect()
+}
+
+/// Optional force model components, copied onto body states on top of their point masses.
+#[derive(SystemParam)]
+pub struct ForceModels<'w, 's> {
+    gravity_settings: Res<'w, GravitySettings>,
+    transform_query: Query<'w, 's, (&'static Transform, Option<&'static Rotating>)>,
+    field_query: Query<'w, 's, &'static GravityField>,
+    atmosphere_query: Query<'w, 's, &'static Atmosphere>,
+    drag_query: Query<'w, 's, &'static DragProperties>,
+    sun_query: Query<'w, 's, (), With<Sun>>,
+    radiation_query: Query<'w, 's, &'static RadiationPressure>,
+    pn_query: Query<'w, 's, (), With<PostNewtonian>>,
+    engine_query: Query<'w, 's, &'static Engine>,
+}
+
+impl<'w, 's> ForceModels<'w, 's> {
+    pub fn attach(&self, bodies: &mut [BodyState]) {
+        for body in bodies.iter_mut() {
+            body.softening = self.gravity_settings.softening;
+            if let Ok((transform, rotating)) = self.transform_query.get(body.entity) {
+                body.orientation = transform.rotation.as_f64();
+                if let Some(rotating) = rotating {
+                    body.spin = body.orientation * DVec3::Y * rotating.radians_per_second();
+                }
+            }
+            body.gravity_field = self.field_query.get(body.entity).ok().cloned();
+            body.atmosphere = self.atmosphere_query.get(body.entity).ok().cloned();
+            body.drag = self.drag_query.get(body.entity).ok().cloned();
+            body.light_source = self.sun_query.get(body.entity).is_ok();
+            body.radiation = self.radiation_query.get(body.entity).ok().cloned();
+            body.post_newtonian = self.pn_query.get(body.entity).is_ok();
+            body.engine = self.engine_query.get(body.entity).ok().cloned();
+        }
     }
 }
 
 pub fn simulation_step(
-    mut sim_query: Query<(&mut Transform, &mut PhysicalProperties, Entity), With<Simulated>>,
+    mut commands: Commands,
+    mut clock: ResMut<SimulationClock>,
+    mut sim_query: Query<
+        (
+            &mut PhysicalProperties,
+            Entity,
+            Option<&mut AccelerationBreakdown>,
+            Option<&mut StateTransition>,
+        ),
+        With<Simulated>,
+    >,
     ref_query: Query<Entity, With<ReferenceFrame>>,
+    node_query: Query<(Entity, &ManeuverNode)>,
+    force_models: ForceModels,
+    mut faults: EventWriter<SimulationFault>,
 ) {
-    let mut combinations = sim_query.iter_combinations_mut();
-
-    let reference = ref_query.get_single();
-
-    while let Some(
-        [(mut a_transform, mut a_properties, a_entity), (mut b_transform, mut b_properties, b_entity)],
-    ) = combinations.fetch_next()
-    {
-        // grab the distance between the physical objects
-        let distance = a_properties.translation.distance(&b_properties.translation);
-
-        // get the normalized direction vectors
-        let ab_direction_vec =
-            HPVec3::sub(&b_properties.translation, &a_properties.translation).normalize();
-        let ba_direction_vec =
-            HPVec3::sub(&a_properties.translation, &b_properties.translation).normalize();
-
-        // get the force between the two simulated entities.
-        let force =
-            (GRAVITATIONAL_CONSTANT * a_properties.mass.clone() * b_properties.mass.clone())
-                / distance.square();
-
-        // find the acceleration
-        let a_acceleration = force.clone() / a_properties.mass.clone();
-        let b_acceleration = force.clone() / b_properties.mass.clone();
-
-        let a_acceleration_vec = HPVec3::scalar_mul(&ab_direction_vec, &a_acceleration);
-        let b_acceleration_vec = HPVec3::scalar_mul(&ba_direction_vec, &b_acceleration);
-
-        // apply previous acceleration
-        if let Ok(ref_entity) = reference {
-            if ref_entity != a_entity {
-                a_properties.translation =
-                    HPVec3::add(&a_properties.translation, &a_properties.acceleration);
-            }
+    if clock.paused {
+        return;
+    }
 
-            if ref_entity != b_entity {
-                b_properties.translation =
-                    HPVec3::add(&b_properties.translation, &b_properties.acceleration);
+    let mut bodies = body_states(
+        sim_query
+            .iter()
+            .map(|(properties, entity, _, _)| (entity, properties)),
+        ref_query.get_single().ok(),
+    );
+    force_models.attach(&mut bodies);
+    for body in bodies.iter_mut() {
+        if let Ok((_, _, _, Some(transition))) = sim_query.get(body.entity) {
+            body.transition = Some(transition.matrix);
+        }
+    }
+
+    let (node_entities, nodes): (Vec<Entity>, Vec<ManeuverNode>) = node_query
+        .iter()
+        .map(|(entity, node)| (entity, node.clone()))
+        .unzip();
+    let mut accelerations = Vec::new();
+    let mut coincident = Vec::new();
+    let burned = advance_through(
+        &mut bodies,
+        &nodes,
+        clock.elapsed,
+        clock.timestep,
+        |bodies, timestep| {
+            accelerations = step(bodies, &Float::with_val(DEFAULT_PRECISION, timestep));
+            for (body, acceleration) in bodies.iter().zip(accelerations.iter()) {
+                coincident.extend(
+                    acceleration
+                        .coincident
+                        .iter()
+                        .map(|other| (body.entity, *other)),
+                );
             }
+        },
+    );
+
+    if !coincident.is_empty() {
+        for (a, b) in coincident {
+            faults.send(SimulationFault {
+                entities: vec![a, b],
+                time: clock.elapsed,
+                cause: FaultCause::Coincident,
+            });
         }
+        clock.paused = true;
+        return;
+    }
 
-        // set new acceleration
-        a_properties.acceleration.add_self(&a_acceleration_vec);
-        b_properties.acceleration.add_self(&b_acceleration_vec);
+    let diverged: Vec<Entity> = bodies
+        .iter()
+        .filter(|body| !body.translation.is_finite() || !body.velocity.is_finite())
+        .map(|body| body.entity)
+        .collect();
+    if !diverged.is_empty() {
+        faults.send(SimulationFault {
+            entities: diverged,
+            time: clock.elapsed,
+            cause: FaultCause::Diverged,
+        });
+        clock.paused = true;
+        return;
+    }
 
-        // engine floats are not precise enough for the calculations
-        // but precise enough to render visuals :D
-        a_transform.translation = a_properties.translation.to_vec3();
-        b_transform.translation = b_properties.translation.to_vec3();
+    for index in burned {
+        info!(
+            "burned {:.1} m/s at {:.2} days",
+            nodes[index].delta_v(),
+            nodes[index].time / 86_400.
+        );
+        commands.entity(node_entities[index]).despawn();
     }
+
+    for (body, acceleration) in bodies.iter().zip(accelerations) {
+        if let Ok((mut properties, _, breakdown, transition)) = sim_query.get_mut(body.entity) {
+            body.apply(&mut properties);
+            if let Some(mut breakdown) = breakdown {
+                breakdown.0 = acceleration.sources;
+            }
+            if let (Some(mut transition), Some(matrix)) = (transition, body.transition) {
+                transition.matrix = matrix;
+            }
+        }
+    }
+
+    clock.elapsed += clock.timestep;
 }
 
-fn rotation_step(mut rot_query: Query<(&Rotating, &mut Transform), With<Rotating>>) {
+fn rotation_step(
+    clock: Res<SimulationClock>,
+    mut rot_query: Query<(&Rotating, &mut Transform), With<Rotating>>,
+) {
+    if clock.paused {
+        return;
+    }
+
     for (rot, mut transform) in rot_query.iter_mut() {
         let mut euler_rot = transform.rotation.to_euler(EulerRot::ZXY);
-        euler_rot.2 += rot.degrees_per_second.to_f32();
+        euler_rot.2 += (rot.radians_per_second() * clock.timestep) as f32;
         transform.rotation = Quat::from_euler(EulerRot::ZXY, euler_rot.0, euler_rot.1, euler_rot.2)
     }
 }
+
+/// Gives every simulated body a breakdown of its accelerations.
+fn insert_acceleration_breakdowns(
+    mut commands: Commands,
+    sim_query: Query<Entity, (With<Simulated>, Without<AccelerationBreakdown>)>,
+) {
+    for entity in sim_query.iter() {
+        commands
+            .entity(entity)
+            .insert(AccelerationBreakdown::default());
+    }
+}
+
+/// Pause or resume the simulation with Enter.
+fn toggle_pause(input_keyboard: Res<Input<KeyCode>>, mut clock: ResMut<SimulationClock>) {
+    if input_keyboard.just_pressed(KeyCode::Return) {
+        clock.paused = !clock.paused;
+    }
+}
+
+fn log_simulation_faults(mut faults: EventReader<SimulationFault>, name_query: Query<&RenderInUI>) {
+    for fault in faults.iter() {
+        let names: Vec<String> = fault
+            .entities
+            .iter()
+            .map(|entity| match name_query.get(*entity) {
+                Ok(name) => name.0.clone(),
+                Err(_) => format!("{:?}", entity),
+            })
+            .collect();
+        match fault.cause {
+            FaultCause::Diverged => error!(
+                "simulation diverged after {:.2} days in {}, paused",
+                fault.time / 86_400.,
+                names.join(", ")
+            ),
+            FaultCause::Coincident => error!(
+                "{} met at the same point after {:.2} days with no softening, paused",
+                names.join(" and "),
+                fault.time / 86_400.
+            ),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera::Focused,
+    orbit::{hill_radius, spheres_of_influence},
+    simulation::{
+        self, body_states, PhysicalProperties, ReferenceFrame, Simulated, SimulationClock,
+    },
+    ui::RenderInUI,
+};
+
+/// Sphere of influence of a body, and the primary it's currently inside.
+#[derive(Component)]
+pub struct SphereOfInfluence {
+    pub primary: Option<Entity>,
+    /// Radius in meters inside which the body's pull dominates perturbations from its primary.
+    pub laplace_radius: f64,
+    /// Radius in meters inside which the body can hold on to satellites of its own.
+    pub hill_radius: f64,
+}
+
+/// Sent when a body leaves the sphere of influence of one primary for another's.
+pub struct SoiTransition {
+    pub body: Entity,
+    pub from: Option<Entity>,
+    pub to: Option<Entity>,
+    /// Simulated seconds since the start of the simulation.
+    pub time: f64,
+}
+
+/// Readout of the focused body's sphere of influence.
+#[derive(Component)]
+struct SoiText;
+
+/// Plugin used to track which primary every body is inside the sphere of influence of.
+pub struct SoiPlugin;
+
+impl Plugin for SoiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SoiTransition>();
+        app.add_startup_system(spawn_soi_text);
+        app.add_system(update_spheres_of_influence.after(simulation::LABEL));
+        app.add_system(log_soi_transitions.after(update_spheres_of_influence));
+        app.add_system(update_soi_text.after(update_spheres_of_influence));
+    }
+}
+
+fn update_spheres_of_influence(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    mut transitions: EventWriter<SoiTransition>,
+    sim_query: Query<(Entity, &PhysicalProperties), With<Simulated>>,
+    ref_query: Query<Entity, With<ReferenceFrame>>,
+    mut soi_query: Query<&mut SphereOfInfluence>,
+) {
+    let bodies = body_states(sim_query.iter(), ref_query.get_single().ok());
+    let influence = spheres_of_influence(&bodies);
+
+    for (body, (primary, laplace_radius)) in bodies.iter().zip(influence) {
+        let primary_entity = primary.map(|primary| bodies[primary].entity);
+        let hill_radius = match primary {
+            Some(primary) => hill_radius(
+                body.translation
+                    .distance(&bodies[primary].translation)
+                    .to_f64(),
+                body.mass.to_f64(),
+                bodies[primary].mass.to_f64(),
+            ),
+            None => f64::INFINITY,
+        };
+
+        match soi_query.get_mut(body.entity) {
+            Ok(mut soi) => {
+                if soi.primary != primary_entity {
+                    transitions.send(SoiTransition {
+                        body: body.entity,
+                        from: soi.primary,
+                        to: primary_entity,
+                        time: clock.elapsed,
+                    });
+                }
+                *soi = SphereOfInfluence {
+                    primary: primary_entity,
+                    laplace_radius,
+                    hill_radius,
+                };
+            }
+            Err(_) => {
+                commands.entity(body.entity).insert(SphereOfInfluence {
+                    primary: primary_entity,
+                    laplace_radius,
+                    hill_radius,
+                });
+            }
+        }
+    }
+}
+
+fn log_soi_transitions(
+    mut transitions: EventReader<SoiTransition>,
+    name_query: Query<&RenderInUI>,
+) {
+    let name = |entity: Option<Entity>| match entity {
+        Some(entity) => match name_query.get(entity) {
+            Ok(name) => name.0.clone(),
+            Err(_) => format!("{:?}", entity),
+        },
+        None => "nothing".to_string(),
+    };
+
+    for transition in transitions.iter() {
+        info!(
+            "{} left the sphere of influence of {} for {} after {:.2} days",
+            name(Some(transition.body)),
+            name(transition.from),
+            name(transition.to),
+            transition.time / 86_400.
+        );
+    }
+}
+
+fn spawn_soi_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(20.),
+                    top: Val::Px(280.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
+                    font_size: 18.0,
+                    color: Color::rgb(0.6, 1.0, 0.6),
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(SoiText);
+}
+
+fn update_soi_text(
+    focused_query: Query<&SphereOfInfluence, With<Focused>>,
+    name_query: Query<&RenderInUI>,
+    mut text_query: Query<&mut Text, With<SoiText>>,
+) {
+    let mut text = match text_query.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    text.sections[0].value = match focused_query.get_single() {
+        Ok(soi) if soi.primary.is_some() => {
+            let primary = soi
+                .primary
+                .and_then(|primary| name_query.get(primary).ok())
+                .map(|name| name.0.clone())
+                .unwrap_or_default();
+            format!(
+                "inside {}\nSOI   {:.0} km\nHill  {:.0} km",
+                primary,
+                soi.laplace_radius / 1000.,
+                soi.hill_radius / 1000.
+            )
+        }
+        _ => String::new(),
+    };
+}
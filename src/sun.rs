@@ -1,6 +1,8 @@
 use crate::{
     camera::Focusable,
+    relativity::PostNewtonian,
     simulation::{HPVec3, PhysicalProperties, ReferenceFrame, Simulated},
+    ui::RenderInUI,
 };
 use bevy::prelude::*;
 use rug::Float;
@@ -26,8 +28,11 @@ pub fn setup_sun(mut commands: Commands, asset_server: Res<AssetServer>) {
             acceleration: HPVec3::zero(),
             translation: HPVec3::zero(),
         })
+        .insert(Sun)
+        .insert(RenderInUI("Sun".to_string()))
         .insert(Simulated)
         .insert(ReferenceFrame)
+        .insert(PostNewtonian)
         .insert(Focusable);
 
     const HALF_SIZE: f32 = 10.0;
@@ -0,0 +1,691 @@
+use std::fmt;
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{
+    headless::{self, Args, Snapshot},
+    maneuver::{advance_through, ManeuverNode},
+    orbit::{primaries, OrbitalElements},
+    prediction::patched_conic_step,
+    simulation::{BodyState, HPVec3, GRAVITATIONAL_CONSTANT},
+};
+
+/// Newton iterations allowed before the corrector gives up.
+const MAX_ITERATIONS: usize = 20;
+
+/// Halvings of a Newton step tried when the full one makes the residuals worse.
+const MAX_BACKTRACKS: usize = 6;
+
+/// Longest patched-conic step, short enough to notice sphere of influence crossings.
+const MAX_STEP: f64 = 3_600.;
+
+/// Periapsis altitude miss in meters that counts as on target.
+const ALTITUDE_TOLERANCE: f64 = 100.;
+
+/// Inclination miss in radians that counts as on target.
+const INCLINATION_TOLERANCE: f64 = 1e-5;
+
+/// Miss in meters along each axis that counts as arriving.
+const ARRIVAL_TOLERANCE: f64 = 1_000.;
+
+/// Flag on the command line that corrects a burn instead of opening a window.
+pub const FLAG: &str = "--targeting";
+
+const USAGE: &str = "usage: orbital-simulations --targeting [--body NAME] \
+[--burn TIME,PROGRADE,NORMAL,RADIAL] [--vary CONTROL,...] [--target-periapsis KM] \
+[--target-inclination DEGREES] [--target-arrival NAME,HOURS]
+controls are prograde, normal, radial and time, and at least one target is needed";
+
+/// Part of a maneuver node the corrector may change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Prograde,
+    Normal,
+    Radial,
+    Time,
+}
+
+impl Control {
+    pub const ALL: [Control; 4] = [
+        Control::Prograde,
+        Control::Normal,
+        Control::Radial,
+        Control::Time,
+    ];
+
+    fn value(self, node: &mut ManeuverNode) -> &mut f64 {
+        match self {
+            Control::Prograde => &mut node.prograde,
+            Control::Normal => &mut node.normal,
+            Control::Radial => &mut node.radial,
+            Control::Time => &mut node.time,
+        }
+    }
+
+    /// Nudge used to measure how the goals respond, in m/s or seconds.
+    fn perturbation(self) -> f64 {
+        match self {
+            Control::Time => 1.,
+            _ => 1e-3,
+        }
+    }
+
+    /// Size of a change worth the same as 1 m/s of delta-v, so the smallest step in delta-v and
+    /// timing together isn't all timing.
+    fn scale(self) -> f64 {
+        match self {
+            Control::Time => 60.,
+            _ => 1.,
+        }
+    }
+}
+
+/// Where the corrector is aiming.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Goal {
+    /// Periapsis altitude in meters above the primary's surface, right after the burn.
+    PeriapsisAltitude(f64),
+    /// Inclination in radians to the reference plane, right after the burn.
+    Inclination(f64),
+    /// Being where `body` is at `time`, in simulated seconds since the start of the simulation.
+    Arrival { body: Entity, time: f64 },
+}
+
+impl Goal {
+    /// Misses that count as on target, one per residual.
+    pub fn tolerances(&self) -> Vec<f64> {
+        match self {
+            Goal::PeriapsisAltitude(_) => vec![ALTITUDE_TOLERANCE],
+            Goal::Inclination(_) => vec![INCLINATION_TOLERANCE],
+            Goal::Arrival { .. } => vec![ARRIVAL_TOLERANCE; 3],
+        }
+    }
+
+    /// Name, unit size and unit shown for each residual.
+    pub fn labels(&self) -> Vec<(String, f64, &'static str)> {
+        match self {
+            Goal::PeriapsisAltitude(_) => vec![("periapsis".to_string(), 1000., "km")],
+            Goal::Inclination(_) => vec![("inclination".to_string(), 1_f64.to_radians(), "°")],
+            Goal::Arrival { .. } => ["x", "y", "z"]
+                .map(|axis| (format!("arrival {}", axis), 1000., "km"))
+                .to_vec(),
+        }
+    }
+}
+
+/// How a correction ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Converged,
+    /// The goals couldn't be measured from the starting burn.
+    Unreachable,
+    /// The controls don't move the goals.
+    Singular,
+    /// No step made the residuals smaller.
+    Stalled,
+    /// Ran out of iterations.
+    MaxIterations,
+}
+
+/// Result of running the corrector, with the residuals it saw on the way.
+#[derive(Clone, Debug)]
+pub struct Correction {
+    pub node: ManeuverNode,
+    pub outcome: Outcome,
+    /// Residuals of every iteration, the last being those of `node`.
+    pub history: Vec<Vec<f64>>,
+}
+
+impl Correction {
+    /// How the correction went for `goals`, line by line: the outcome, the miss at every
+    /// iteration and what is left of each residual. `now` is when burn times are counted from.
+    pub fn report(&self, goals: &[Goal], now: f64) -> Vec<String> {
+        let tolerances: Vec<f64> = goals.iter().flat_map(Goal::tolerances).collect();
+        let mut report = vec![format!(
+            "{:?} after {} iterations, burn {:.2} m/s at T+{:.0} s",
+            self.outcome,
+            self.history.len().saturating_sub(1),
+            self.node.delta_v(),
+            self.node.time - now
+        )];
+        for (iteration, residuals) in self.history.iter().enumerate() {
+            report.push(format!(
+                "{:>3}  miss {:.3e} × tolerance",
+                iteration,
+                scaled_miss(residuals, &tolerances)
+            ));
+        }
+        if let Some(residuals) = self.history.last() {
+            let labels = goals.iter().flat_map(Goal::labels);
+            for ((label, unit, suffix), residual) in labels.zip(residuals) {
+                report.push(format!(
+                    "{} off by {:+.4} {}",
+                    label,
+                    residual / unit,
+                    suffix
+                ));
+            }
+        }
+        report
+    }
+}
+
+/// Largest of `residuals` as a multiple of its tolerance, under 1 when on target.
+pub fn scaled_miss(residuals: &[f64], tolerances: &[f64]) -> f64 {
+    residuals
+        .iter()
+        .zip(tolerances)
+        .map(|(residual, tolerance)| (residual / tolerance).abs())
+        .fold(0., f64::max)
+}
+
+fn scaled_norm(residuals: &[f64], tolerances: &[f64]) -> f64 {
+    residuals
+        .iter()
+        .zip(tolerances)
+        .map(|(residual, tolerance)| (residual / tolerance).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Shooting method differential corrector. Changes `controls` of `node` until every residual
+/// from `residuals` is inside its tolerance, with Newton steps on finite difference
+/// sensitivities. With more controls than residuals it takes the smallest step that works,
+/// with fewer it takes the least squares one.
+pub fn correct(
+    node: &ManeuverNode,
+    controls: &[Control],
+    tolerances: &[f64],
+    mut residuals: impl FnMut(&ManeuverNode) -> Option<Vec<f64>>,
+) -> Correction {
+    let mut current = node.clone();
+    let mut history = Vec::new();
+    let finish = |node: ManeuverNode, outcome, history| Correction {
+        node,
+        outcome,
+        history,
+    };
+
+    let mut errors = match residuals(&current) {
+        Some(errors) => errors,
+        None => return finish(current, Outcome::Unreachable, history),
+    };
+    for _ in 0..MAX_ITERATIONS {
+        history.push(errors.clone());
+        if scaled_miss(&errors, tolerances) < 1. {
+            return finish(current, Outcome::Converged, history);
+        }
+        if controls.is_empty() {
+            return finish(current, Outcome::Singular, history);
+        }
+
+        // sensitivities of the scaled residuals to the scaled controls
+        let mut jacobian = vec![vec![0.; controls.len()]; errors.len()];
+        for (column, control) in controls.iter().enumerate() {
+            let mut nudged = current.clone();
+            *control.value(&mut nudged) += control.perturbation();
+            let nudged_errors = match residuals(&nudged) {
+                Some(nudged_errors) => nudged_errors,
+                None => return finish(current, Outcome::Unreachable, history),
+            };
+            for (row, tolerance) in tolerances.iter().enumerate() {
+                jacobian[row][column] = (nudged_errors[row] - errors[row])
+                    / tolerance
+                    / (control.perturbation() / control.scale());
+            }
+        }
+        let scaled: Vec<f64> = errors
+            .iter()
+            .zip(tolerances)
+            .map(|(error, tolerance)| -error / tolerance)
+            .collect();
+        let mut step = match newton_step(&jacobian, &scaled) {
+            Some(step) => step,
+            None => return finish(current, Outcome::Singular, history),
+        };
+
+        // back off while the step overshoots or loses the goals
+        let norm = scaled_norm(&errors, tolerances);
+        let mut accepted = None;
+        for _ in 0..=MAX_BACKTRACKS {
+            let mut candidate = current.clone();
+            for (control, change) in controls.iter().zip(step.iter()) {
+                *control.value(&mut candidate) += change * control.scale();
+            }
+            if let Some(candidate_errors) = residuals(&candidate) {
+                if scaled_norm(&candidate_errors, tolerances) < norm {
+                    accepted = Some((candidate, candidate_errors));
+                    break;
+                }
+            }
+            step.iter_mut().for_each(|change| *change /= 2.);
+        }
+        match accepted {
+            Some((candidate, candidate_errors)) => {
+                current = candidate;
+                errors = candidate_errors;
+            }
+            None => return finish(current, Outcome::Stalled, history),
+        }
+    }
+
+    history.push(errors.clone());
+    let outcome = if scaled_miss(&errors, tolerances) < 1. {
+        Outcome::Converged
+    } else {
+        Outcome::MaxIterations
+    };
+    finish(current, outcome, history)
+}
+
+/// Solves `jacobian` · step = `target`, the smallest step when under-determined and the least
+/// squares one when over-determined.
+fn newton_step(jacobian: &[Vec<f64>], target: &[f64]) -> Option<Vec<f64>> {
+    let rows = jacobian.len();
+    let columns = jacobian.first()?.len();
+    let entry = |row: usize, column: usize| jacobian[row][column];
+
+    if rows <= columns {
+        // Jᵀ (J Jᵀ)⁻¹ target
+        let square = (0..rows)
+            .map(|a| {
+                (0..rows)
+                    .map(|b| (0..columns).map(|k| entry(a, k) * entry(b, k)).sum())
+                    .collect()
+            })
+            .collect();
+        let weights = solve(square, target.to_vec())?;
+        Some(
+            (0..columns)
+                .map(|column| (0..rows).map(|row| entry(row, column) * weights[row]).sum())
+                .collect(),
+        )
+    } else {
+        // (Jᵀ J)⁻¹ Jᵀ target
+        let square = (0..columns)
+            .map(|a| {
+                (0..columns)
+                    .map(|b| (0..rows).map(|k| entry(k, a) * entry(k, b)).sum())
+                    .collect()
+            })
+            .collect();
+        let projected = (0..columns)
+            .map(|column| (0..rows).map(|row| entry(row, column) * target[row]).sum())
+            .collect();
+        solve(square, projected)
+    }
+}
+
+/// Gaussian elimination with partial pivoting. `None` if the matrix is singular.
+fn solve(mut matrix: Vec<Vec<f64>>, mut vector: Vec<f64>) -> Option<Vec<f64>> {
+    let size = vector.len();
+    let largest = matrix
+        .iter()
+        .flatten()
+        .fold(0., |largest: f64, entry| largest.max(entry.abs()));
+
+    for column in 0..size {
+        let pivot = (column..size).max_by(|a, b| {
+            matrix[*a][column]
+                .abs()
+                .total_cmp(&matrix[*b][column].abs())
+        })?;
+        if matrix[pivot][column].abs() <= largest * 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        vector.swap(column, pivot);
+
+        let pivot_row = matrix[column].clone();
+        for row in column + 1..size {
+            let factor = matrix[row][column] / pivot_row[column];
+            for (entry, pivot_entry) in matrix[row].iter_mut().zip(&pivot_row).skip(column) {
+                *entry -= factor * pivot_entry;
+            }
+            vector[row] -= factor * vector[column];
+        }
+    }
+
+    let mut solution = vec![0.; size];
+    for row in (0..size).rev() {
+        let known: f64 = (row + 1..size).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (vector[row] - known) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+/// Flies copies of the bodies forward with patched conics to see where a burn leaves a craft.
+pub struct Shooter {
+    bodies: Vec<BodyState>,
+    craft: usize,
+    /// Simulated seconds since the start of the simulation the bodies are at.
+    start: f64,
+    /// Other planned burns, flown along with the one being corrected.
+    nodes: Vec<ManeuverNode>,
+}
+
+impl Shooter {
+    pub fn new(
+        bodies: Vec<BodyState>,
+        craft: usize,
+        start: f64,
+        nodes: Vec<ManeuverNode>,
+    ) -> Shooter {
+        Shooter {
+            bodies,
+            craft,
+            start,
+            nodes,
+        }
+    }
+
+    /// Residuals of `goals` when flying `node`, in the order and units of the goals. `None` if
+    /// a goal can't be measured, when the craft has no primary or the arrival body is gone.
+    pub fn residuals(&self, node: &ManeuverNode, goals: &[Goal]) -> Option<Vec<f64>> {
+        let mut bodies = self.bodies.clone();
+        let mut nodes = self.nodes.clone();
+        nodes.push(node.clone());
+        let mut now = self.start;
+        let mut advance = |bodies: &mut [BodyState], to: f64| {
+            // burns due at or before now still happen, straight away
+            let to = to.max(now);
+            let mut burned = advance_through(bodies, &nodes, now, to - now, |bodies, duration| {
+                let steps = (duration / MAX_STEP).ceil().max(1.);
+                for _ in 0..steps as usize {
+                    patched_conic_step(bodies, duration / steps);
+                }
+            });
+            // each burn only once
+            burned.sort_unstable();
+            for index in burned.into_iter().rev() {
+                nodes.remove(index);
+            }
+            now = to;
+        };
+
+        // the orbit right after the burn
+        advance(&mut bodies, node.time);
+        let after_burn = || {
+            let primary = primaries(&bodies)[self.craft]?;
+            let position = HPVec3::sub(
+                &bodies[self.craft].translation,
+                &bodies[primary].translation,
+            );
+            let velocity = HPVec3::sub(&bodies[self.craft].velocity, &bodies[primary].velocity);
+            let mu = (GRAVITATIONAL_CONSTANT
+                * (bodies[self.craft].mass.clone() + &bodies[primary].mass))
+                .to_f64();
+            Some((
+                OrbitalElements::from_state(position.to_dvec3(), velocity.to_dvec3(), mu),
+                bodies[primary].radius,
+            ))
+        };
+        let (elements, radius) = after_burn()?;
+
+        let mut residuals = Vec::new();
+        for goal in goals {
+            match *goal {
+                Goal::PeriapsisAltitude(altitude) => {
+                    residuals.push(elements.periapsis_distance() - radius - altitude);
+                }
+                Goal::Inclination(inclination) => {
+                    residuals.push(elements.inclination - inclination);
+                }
+                Goal::Arrival { body, time } => {
+                    let other = bodies.iter().position(|other| other.entity == body)?;
+                    advance(&mut bodies, time);
+                    let miss: DVec3 =
+                        HPVec3::sub(&bodies[self.craft].translation, &bodies[other].translation)
+                            .to_dvec3();
+                    residuals.extend(miss.to_array());
+                }
+            }
+        }
+        Some(residuals)
+    }
+}
+
+/// Which burn to correct and what for, read off the command line.
+#[derive(Clone, Debug)]
+pub struct TargetingSettings {
+    /// Name of the craft flying the burn.
+    pub body: String,
+    /// Starting guess of the burn, as seconds from the start and prograde, normal and radial
+    /// delta-v in m/s.
+    pub burn: (f64, DVec3),
+    pub controls: Vec<Control>,
+    /// Periapsis altitude in km right after the burn.
+    pub periapsis_altitude: Option<f64>,
+    /// Inclination in degrees right after the burn.
+    pub inclination: Option<f64>,
+    /// Name of the body to meet, and hours from the start to meet it at.
+    pub arrival: Option<(String, f64)>,
+}
+
+impl Default for TargetingSettings {
+    fn default() -> Self {
+        TargetingSettings {
+            body: "Satellite".to_string(),
+            burn: (0., DVec3::ZERO),
+            controls: vec![Control::Prograde, Control::Normal, Control::Radial],
+            periapsis_altitude: None,
+            inclination: None,
+            arrival: None,
+        }
+    }
+}
+
+impl TargetingSettings {
+    /// Settings from the command line arguments after the program name, or `None` without
+    /// `FLAG`.
+    pub fn from_args(args: &[String]) -> Result<Option<TargetingSettings>, TargetingError> {
+        let mut args = match Args::find(args, FLAG) {
+            Some(args) => args,
+            None => return Ok(None),
+        };
+
+        let mut settings = TargetingSettings::default();
+        while let Some(arg) = args.next() {
+            let value =
+                match arg.as_str() {
+                    "--body" => args.value(&arg).map(|name| settings.body = name),
+                    "--burn" => args.value::<String>(&arg).and_then(|burn| {
+                        match list::<f64>(&arg, &burn)?[..] {
+                            [time, prograde, normal, radial] => {
+                                settings.burn = (time, DVec3::new(prograde, normal, radial));
+                                Ok(())
+                            }
+                            _ => Err(format!(
+                                "--burn takes time,prograde,normal,radial, not {}",
+                                burn
+                            )),
+                        }
+                    }),
+                    "--vary" => args.value::<String>(&arg).and_then(|controls| {
+                        settings.controls = list(&arg, &controls)?;
+                        Ok(())
+                    }),
+                    "--target-periapsis" => args
+                        .value(&arg)
+                        .map(|altitude| settings.periapsis_altitude = Some(altitude)),
+                    "--target-inclination" => args
+                        .value(&arg)
+                        .map(|inclination| settings.inclination = Some(inclination)),
+                    "--target-arrival" => args.value::<String>(&arg).and_then(|arrival| {
+                        let (name, hours) = arrival.rsplit_once(',').ok_or_else(|| {
+                            format!("--target-arrival takes NAME,HOURS, not {}", arrival)
+                        })?;
+                        settings.arrival = Some((name.to_string(), headless::parse(&arg, hours)?));
+                        Ok(())
+                    }),
+                    _ => Err(format!("unknown argument {}", arg)),
+                };
+            value.map_err(TargetingError::Usage)?;
+        }
+        if settings.periapsis_altitude.is_none()
+            && settings.inclination.is_none()
+            && settings.arrival.is_none()
+        {
+            return Err(TargetingError::Usage("nothing to target".to_string()));
+        }
+        Ok(Some(settings))
+    }
+}
+
+/// Reads a comma separated list given to `arg`.
+fn list<T: std::str::FromStr>(arg: &str, value: &str) -> Result<Vec<T>, String> {
+    value
+        .split(',')
+        .map(|part| headless::parse(arg, part.trim()))
+        .collect()
+}
+
+impl std::str::FromStr for Control {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Control, ()> {
+        match name {
+            "prograde" => Ok(Control::Prograde),
+            "normal" => Ok(Control::Normal),
+            "radial" => Ok(Control::Radial),
+            "time" => Ok(Control::Time),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TargetingError {
+    Usage(String),
+    MissingBody(String),
+    /// The corrector stopped without hitting the targets.
+    Failed(Outcome),
+}
+
+impl fmt::Display for TargetingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetingError::Usage(problem) => write!(f, "{}\n{}", problem, USAGE),
+            TargetingError::MissingBody(name) => write!(f, "there's no body called {}", name),
+            TargetingError::Failed(outcome) => write!(f, "targeting failed: {:?}", outcome),
+        }
+    }
+}
+
+/// Corrects the burn in `settings` for the bodies spawned into `world`, counting time from the
+/// start of the simulation, and prints how the correction went.
+pub fn run_targeting(
+    world: &mut World,
+    settings: &TargetingSettings,
+) -> Result<(), TargetingError> {
+    let snapshot = Snapshot::from_world(world);
+    let find = |name: &String| {
+        snapshot
+            .find(name)
+            .ok_or_else(|| TargetingError::MissingBody(name.clone()))
+    };
+    let craft = find(&settings.body)?;
+
+    let mut goals = Vec::new();
+    if let Some(altitude) = settings.periapsis_altitude {
+        goals.push(Goal::PeriapsisAltitude(altitude * 1000.));
+    }
+    if let Some(inclination) = settings.inclination {
+        goals.push(Goal::Inclination(inclination.to_radians()));
+    }
+    if let Some((name, hours)) = &settings.arrival {
+        goals.push(Goal::Arrival {
+            body: snapshot.bodies[find(name)?].entity,
+            time: hours * 3600.,
+        });
+    }
+    let tolerances: Vec<f64> = goals.iter().flat_map(Goal::tolerances).collect();
+
+    let (time, delta_v) = settings.burn;
+    let node = ManeuverNode {
+        body: snapshot.bodies[craft].entity,
+        time,
+        prograde: delta_v.x,
+        normal: delta_v.y,
+        radial: delta_v.z,
+    };
+    let shooter = Shooter::new(snapshot.bodies, craft, 0., Vec::new());
+    let correction = correct(&node, &settings.controls, &tolerances, |node| {
+        shooter.residuals(node, &goals)
+    });
+
+    for line in correction.report(&goals, 0.) {
+        println!("{}", line);
+    }
+    let node = &correction.node;
+    println!(
+        "--burn {:.3},{:.6},{:.6},{:.6}",
+        node.time, node.prograde, node.normal, node.radial
+    );
+    match correction.outcome {
+        Outcome::Converged => Ok(()),
+        outcome => Err(TargetingError::Failed(outcome)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::Float;
+
+    use super::*;
+    use crate::simulation::{PhysicalProperties, DEFAULT_PRECISION};
+
+    const EARTH_RADIUS: f64 = 6_371e3;
+
+    fn body(index: u32, mass: f64, radius: f64, position: DVec3, velocity: DVec3) -> BodyState {
+        let properties = PhysicalProperties {
+            mass: Float::with_val(DEFAULT_PRECISION, mass),
+            estimated_radius: Float::with_val(DEFAULT_PRECISION, radius),
+            acceleration: HPVec3::from_dvec3(velocity),
+            translation: HPVec3::from_dvec3(position),
+        };
+        BodyState::new(Entity::from_raw(index), &properties, false)
+    }
+
+    #[test]
+    fn corrects_burn_at_start() {
+        let earth_mass = 5.972e24;
+        let radius = 7_000e3;
+        let speed = (GRAVITATIONAL_CONSTANT as f64 * earth_mass / radius).sqrt();
+        let bodies = vec![
+            body(0, earth_mass, EARTH_RADIUS, DVec3::ZERO, DVec3::ZERO),
+            body(
+                1,
+                1_000.,
+                1.,
+                DVec3::new(radius, 0., 0.),
+                DVec3::new(0., 0., speed),
+            ),
+        ];
+        let start = 1_000.;
+        let node = ManeuverNode {
+            body: bodies[1].entity,
+            time: start,
+            // a prograde nudge from a circular orbit wouldn't move the periapsis
+            prograde: -10.,
+            normal: 0.,
+            radial: 0.,
+        };
+        let goals = [Goal::PeriapsisAltitude(300e3)];
+        let tolerances: Vec<f64> = goals.iter().flat_map(Goal::tolerances).collect();
+        let shooter = Shooter::new(bodies, 1, start, Vec::new());
+
+        let correction = correct(&node, &[Control::Prograde], &tolerances, |node| {
+            shooter.residuals(node, &goals)
+        });
+
+        assert_eq!(correction.outcome, Outcome::Converged);
+        // about 91 m/s retrograde lowers the far side of a 629 km circular orbit to 300 km
+        assert!(
+            (-92.0..-90.).contains(&correction.node.prograde),
+            "{:?}",
+            correction.node
+        );
+        assert_eq!(correction.node.time, start);
+    }
+}
@@ -0,0 +1,229 @@
+use std::f64::consts::PI;
+
+use bevy::{math::DVec3, prelude::*};
+use rug::Float;
+
+use crate::{
+    camera::Focused,
+    simulation::{self, HPVec3, PhysicalProperties, Simulated, SimulationClock, DEFAULT_PRECISION},
+    ui::RenderInUI,
+    view::BaseScale,
+};
+
+/// Held together by its own gravity, so it comes apart inside the Roche limit of a heavier body.
+#[derive(Component)]
+pub struct Disruptible {
+    /// How many fragments it breaks into.
+    pub fragments: usize,
+}
+
+/// Settings for tidal disruption.
+pub struct TidalSettings {
+    /// Whether bodies crossing a Roche limit break up, or only send the event.
+    pub break_up: bool,
+}
+
+impl Default for TidalSettings {
+    fn default() -> Self {
+        TidalSettings { break_up: true }
+    }
+}
+
+/// Sent when a disruptible body crosses the Roche limit of a heavier one.
+pub struct TidalDisruptionEvent {
+    pub body: Entity,
+    pub primary: Entity,
+    /// Simulated seconds since the start of the simulation.
+    pub time: f64,
+    /// Distance between the bodies' centers in meters.
+    pub distance: f64,
+    pub roche_limit: f64,
+    /// Fragments the body broke into, zero if it was left whole.
+    pub fragments: usize,
+}
+
+/// The primary whose Roche limit a body is already inside, so crossing is only reported once.
+#[derive(Component)]
+struct InsideRocheLimit(Entity);
+
+/// Plugin used to detect Roche limit crossings and break bodies up.
+pub struct TidalPlugin;
+
+impl Plugin for TidalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TidalSettings>();
+        app.add_event::<TidalDisruptionEvent>();
+        app.add_system(toggle_break_up);
+        app.add_system(detect_disruptions.after(simulation::LABEL));
+        app.add_system(log_disruptions.after(detect_disruptions));
+    }
+}
+
+/// Roche limit of a rigid spherical satellite, in meters from the primary's center.
+pub fn roche_limit(satellite_radius: f64, satellite_mass: f64, primary_mass: f64) -> f64 {
+    satellite_radius * (2. * primary_mass / satellite_mass).cbrt()
+}
+
+/// Switch whether bodies break up at the Roche limit with T.
+fn toggle_break_up(input_keyboard: Res<Input<KeyCode>>, mut settings: ResMut<TidalSettings>) {
+    if input_keyboard.just_pressed(KeyCode::T) {
+        settings.break_up = !settings.break_up;
+        info!(
+            "tidal break up {}",
+            if settings.break_up { "on" } else { "off" }
+        );
+    }
+}
+
+fn detect_disruptions(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    settings: Res<TidalSettings>,
+    mut disruptions: EventWriter<TidalDisruptionEvent>,
+    body_query: Query<(
+        Entity,
+        &Disruptible,
+        &PhysicalProperties,
+        Option<&InsideRocheLimit>,
+        Option<&BaseScale>,
+        Option<&Handle<Mesh>>,
+        Option<&Handle<StandardMaterial>>,
+    )>,
+    primary_query: Query<(Entity, &PhysicalProperties), With<Simulated>>,
+    focused_query: Query<(), With<Focused>>,
+) {
+    for (body, disruptible, properties, inside, base_scale, mesh, material) in body_query.iter() {
+        let mass = properties.mass.to_f64();
+        let radius = properties.estimated_radius.to_f64();
+
+        // the heavier body whose Roche limit it's deepest inside of
+        let crossing = primary_query
+            .iter()
+            .filter(|(primary, primary_properties)| {
+                *primary != body && primary_properties.mass > properties.mass
+            })
+            .filter_map(|(primary, primary_properties)| {
+                let distance = properties
+                    .translation
+                    .distance(&primary_properties.translation)
+                    .to_f64();
+                let limit = roche_limit(radius, mass, primary_properties.mass.to_f64());
+                (distance < limit).then_some((primary, primary_properties, distance, limit))
+            })
+            .min_by(|a, b| (a.2 / a.3).total_cmp(&(b.2 / b.3)));
+
+        let (primary, primary_properties, distance, limit) = match crossing {
+            Some(crossing) => crossing,
+            None => {
+                if inside.is_some() {
+                    commands.entity(body).remove::<InsideRocheLimit>();
+                }
+                continue;
+            }
+        };
+        if inside.is_some_and(|inside| inside.0 == primary) {
+            continue;
+        }
+
+        let fragments = if settings.break_up {
+            disruptible.fragments
+        } else {
+            0
+        };
+        if fragments > 0 {
+            let offset =
+                HPVec3::sub(&properties.translation, &primary_properties.translation).to_dvec3();
+            let velocity =
+                HPVec3::sub(&properties.acceleration, &primary_properties.acceleration).to_dvec3();
+            // the cloud keeps turning with the orbit, as a tidally locked body would
+            let angular_velocity = offset.cross(velocity) / offset.length_squared();
+            let fragment_radius = radius / (fragments as f64).cbrt();
+            let fragment_mass =
+                Float::with_val(DEFAULT_PRECISION, &properties.mass / fragments as u32);
+
+            for fragment_offset in fragment_offsets(fragments, 2. * radius) {
+                commands
+                    .spawn_bundle(PbrBundle {
+                        mesh: mesh.cloned().unwrap_or_default(),
+                        material: material.cloned().unwrap_or_default(),
+                        transform: Transform::from_scale(
+                            base_scale.map_or(Vec3::ONE, |scale| scale.0)
+                                * (fragment_radius / radius) as f32,
+                        ),
+                        ..default()
+                    })
+                    .insert(Simulated)
+                    .insert(PhysicalProperties {
+                        mass: fragment_mass.clone(),
+                        estimated_radius: Float::with_val(DEFAULT_PRECISION, fragment_radius),
+                        acceleration: HPVec3::add(
+                            &properties.acceleration,
+                            &HPVec3::from_dvec3(angular_velocity.cross(fragment_offset)),
+                        ),
+                        translation: HPVec3::add(
+                            &properties.translation,
+                            &HPVec3::from_dvec3(fragment_offset),
+                        ),
+                    });
+            }
+
+            commands.entity(body).despawn_recursive();
+            if focused_query.get(body).is_ok() {
+                commands.entity(primary).insert(Focused);
+            }
+        } else {
+            commands.entity(body).insert(InsideRocheLimit(primary));
+        }
+
+        disruptions.send(TidalDisruptionEvent {
+            body,
+            primary,
+            time: clock.elapsed,
+            distance,
+            roche_limit: limit,
+            fragments,
+        });
+    }
+}
+
+/// Evenly spread points on a sphere of `radius`, shifted so they average out at the center and
+/// the cloud keeps the body's center of mass and momentum.
+fn fragment_offsets(count: usize, radius: f64) -> Vec<DVec3> {
+    let golden_angle = PI * (3. - 5_f64.sqrt());
+    let mut offsets: Vec<DVec3> = (0..count)
+        .map(|i| {
+            let y = 1. - 2. * (i as f64 + 0.5) / count as f64;
+            let ring = (1. - y * y).sqrt();
+            let angle = golden_angle * i as f64;
+            DVec3::new(ring * angle.cos(), y, ring * angle.sin()) * radius
+        })
+        .collect();
+
+    let mean = offsets.iter().sum::<DVec3>() / count as f64;
+    for offset in offsets.iter_mut() {
+        *offset -= mean;
+    }
+    offsets
+}
+
+fn log_disruptions(
+    mut disruptions: EventReader<TidalDisruptionEvent>,
+    name_query: Query<&RenderInUI>,
+) {
+    let name = |entity: Entity| match name_query.get(entity) {
+        Ok(name) => name.0.clone(),
+        Err(_) => format!("{:?}", entity),
+    };
+
+    for disruption in disruptions.iter() {
+        info!(
+            "{} crossed the Roche limit of {} at {:.0} km ({:.0} km) after {:.2} days, {} fragments",
+            name(disruption.body),
+            name(disruption.primary),
+            disruption.distance / 1_000.,
+            disruption.roche_limit / 1_000.,
+            disruption.time / 86_400.,
+            disruption.fragments
+        );
+    }
+}
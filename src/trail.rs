@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+
+use bevy::{math::DVec3, prelude::*, render::mesh::PrimitiveTopology};
+
+use crate::{
+    lines::{set_lines, spawn_lines, LineMaterialHandle},
+    simulation::{self, HPVec3, PhysicalProperties, Simulated, SimulationClock},
+    view::{self, RenderOrigin, RenderScale},
+};
+
+/// Most points a trail keeps, however long it is.
+const MAX_TRAIL_POINTS: usize = 512;
+
+/// Records the recent path of a simulated body and draws it as a fading line.
+#[derive(Component)]
+pub struct Trail {
+    /// Body the path is recorded relative to, and drawn around. Absolute positions are recorded when `None`.
+    pub parent: Option<Entity>,
+    /// How far back the trail reaches, in simulated seconds.
+    pub length: f64,
+    pub color: Color,
+    points: VecDeque<TrailPoint>,
+}
+
+impl Trail {
+    pub fn new(parent: Option<Entity>, length: f64, color: Color) -> Self {
+        Trail {
+            parent,
+            length,
+            color,
+            points: VecDeque::new(),
+        }
+    }
+}
+
+/// Position of a body relative to its trail parent at a point in simulated time.
+struct TrailPoint {
+    time: f64,
+    offset: DVec3,
+}
+
+/// Line mesh drawing the trail of a body.
+#[derive(Component)]
+struct TrailLine(Entity);
+
+/// Plugin used to record and draw orbit trails.
+pub struct TrailPlugin;
+
+impl Plugin for TrailPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_trail_lines);
+        app.add_system(record_trails.after(simulation::LABEL));
+        app.add_system(draw_trails.after(view::LABEL));
+    }
+}
+
+fn spawn_trail_lines(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterialHandle>,
+    trail_query: Query<Entity, Added<Trail>>,
+) {
+    for entity in trail_query.iter() {
+        let (line, _) = spawn_lines(
+            &mut commands,
+            &mut meshes,
+            &material,
+            PrimitiveTopology::LineStrip,
+        );
+        commands.entity(line).insert(TrailLine(entity));
+    }
+}
+
+/// Offset of a body from its trail parent, or its absolute position without one.
+fn trail_offset(
+    trail: &Trail,
+    physical_properties: &PhysicalProperties,
+    parent_query: &Query<&PhysicalProperties, With<Simulated>>,
+) -> Option<DVec3> {
+    match trail.parent {
+        Some(parent) => parent_query.get(parent).ok().map(|parent_properties| {
+            HPVec3::sub(
+                &physical_properties.translation,
+                &parent_properties.translation,
+            )
+            .to_dvec3()
+        }),
+        None => Some(physical_properties.translation.to_dvec3()),
+    }
+}
+
+fn record_trails(
+    clock: Res<SimulationClock>,
+    mut trail_query: Query<(&mut Trail, &PhysicalProperties)>,
+    parent_query: Query<&PhysicalProperties, With<Simulated>>,
+) {
+    for (mut trail, physical_properties) in trail_query.iter_mut() {
+        let offset = match trail_offset(&trail, physical_properties, &parent_query) {
+            Some(offset) => offset,
+            None => continue,
+        };
+
+        // sample sparsely enough that the whole length fits in the point budget
+        let interval = trail.length / MAX_TRAIL_POINTS as f64;
+        let due = match trail.points.back() {
+            Some(last) => clock.elapsed - last.time >= interval,
+            None => true,
+        };
+        if due {
+            trail.points.push_back(TrailPoint {
+                time: clock.elapsed,
+                offset,
+            });
+        }
+
+        let oldest = clock.elapsed - trail.length;
+        while matches!(trail.points.front(), Some(point) if point.time < oldest) {
+            trail.points.pop_front();
+        }
+    }
+}
+
+fn draw_trails(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    clock: Res<SimulationClock>,
+    origin: Res<RenderOrigin>,
+    scale: Res<RenderScale>,
+    line_query: Query<(Entity, &TrailLine, &Handle<Mesh>)>,
+    trail_query: Query<(&Trail, &PhysicalProperties)>,
+    parent_query: Query<&PhysicalProperties, With<Simulated>>,
+) {
+    for (line_entity, line, mesh_handle) in line_query.iter() {
+        let (trail, physical_properties) = match trail_query.get(line.0) {
+            Ok(trail) => trail,
+            Err(_) => {
+                commands.entity(line_entity).despawn();
+                continue;
+            }
+        };
+
+        // the trail follows its parent, so the path is laid around where the parent is now
+        let base = match trail.parent {
+            Some(parent) => match parent_query.get(parent) {
+                Ok(parent_properties) => origin.relative(&parent_properties.translation),
+                Err(_) => continue,
+            },
+            None => origin.relative(&HPVec3::zero()),
+        };
+
+        let color = trail.color.as_linear_rgba_f32();
+        let mut positions = Vec::with_capacity(trail.points.len() + 1);
+        let mut colors = Vec::with_capacity(trail.points.len() + 1);
+        for point in trail.points.iter() {
+            let age = ((clock.elapsed - point.time) / trail.length).clamp(0., 1.) as f32;
+            positions.push(scale.to_render(base + point.offset).to_array());
+            colors.push([color[0], color[1], color[2], color[3] * (1. - age)]);
+        }
+
+        // close the gap between the last sample and the body itself
+        if let Some(offset) = trail_offset(trail, physical_properties, &parent_query) {
+            positions.push(scale.to_render(base + offset).to_array());
+            colors.push(color);
+        }
+
+        if let Some(mesh) = meshes.get_mut(mesh_handle) {
+            set_lines(mesh, positions, colors);
+        }
+    }
+}
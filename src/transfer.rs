@@ -0,0 +1,231 @@
+use std::f64::consts::PI;
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::maneuver::ManeuverNode;
+
+/// Burns taking a craft from one circular orbit to another around the same primary.
+#[derive(Clone, Debug)]
+pub struct Transfer {
+    /// Seconds from the first burn, and the prograde delta-v in m/s, negative to slow down.
+    pub burns: Vec<(f64, f64)>,
+    /// Seconds from the first burn to the last.
+    pub time_of_flight: f64,
+    /// Radians swept around the primary between the first and last burn.
+    pub sweep: f64,
+}
+
+impl Transfer {
+    /// Total delta-v in m/s.
+    pub fn delta_v(&self) -> f64 {
+        self.burns.iter().map(|(_, delta_v)| delta_v.abs()).sum()
+    }
+
+    /// Maneuver nodes flying the transfer for `body`, with the first burn at `start`.
+    pub fn nodes(&self, body: Entity, start: f64) -> Vec<ManeuverNode> {
+        self.burns
+            .iter()
+            .map(|(time, delta_v)| ManeuverNode {
+                body,
+                time: start + time,
+                prograde: *delta_v,
+                normal: 0.,
+                radial: 0.,
+            })
+            .collect()
+    }
+}
+
+/// Speed in m/s at `radius` on an orbit with semi-major axis `semi_major_axis`, from vis-viva.
+fn speed(mu: f64, radius: f64, semi_major_axis: f64) -> f64 {
+    (mu * (2. / radius - 1. / semi_major_axis)).sqrt()
+}
+
+/// Half an ellipse from `from` to `to`, in seconds.
+fn half_period(mu: f64, from: f64, to: f64) -> f64 {
+    PI * (((from + to) / 2.).powi(3) / mu).sqrt()
+}
+
+/// Two burn transfer between circular orbits of radius `from` and `to` along half an ellipse
+/// touching both.
+pub fn hohmann(mu: f64, from: f64, to: f64) -> Transfer {
+    let transfer = (from + to) / 2.;
+    let time_of_flight = half_period(mu, from, to);
+
+    Transfer {
+        burns: vec![
+            (0., speed(mu, from, transfer) - speed(mu, from, from)),
+            (time_of_flight, speed(mu, to, to) - speed(mu, to, transfer)),
+        ],
+        time_of_flight,
+        sweep: PI,
+    }
+}
+
+/// Three burn transfer between circular orbits of radius `from` and `to`, out to `intermediate`
+/// along one half ellipse and back down along another. Cheaper than Hohmann for large ratios.
+///
+/// `None` unless `intermediate` lies at or beyond both orbits, otherwise the burns at either end
+/// would fire at apoapsis of an ellipse they don't touch.
+pub fn bi_elliptic(mu: f64, from: f64, to: f64, intermediate: f64) -> Option<Transfer> {
+    if intermediate < from.max(to) {
+        return None;
+    }
+    let (first, second) = ((from + intermediate) / 2., (to + intermediate) / 2.);
+    let out = half_period(mu, from, intermediate);
+    let back = half_period(mu, intermediate, to);
+
+    Some(Transfer {
+        burns: vec![
+            (0., speed(mu, from, first) - speed(mu, from, from)),
+            (
+                out,
+                speed(mu, intermediate, second) - speed(mu, intermediate, first),
+            ),
+            (out + back, speed(mu, to, to) - speed(mu, to, second)),
+        ],
+        time_of_flight: out + back,
+        sweep: 2. * PI,
+    })
+}
+
+/// Seconds to coast on the current orbit before starting `transfer`, so a target body is there
+/// to meet the craft when it arrives. Positions and velocities are relative to the shared
+/// primary, and both orbits are taken as circular. `None` if the two never change phase.
+pub fn phasing_wait(
+    transfer: &Transfer,
+    position: DVec3,
+    velocity: DVec3,
+    target_position: DVec3,
+    target_velocity: DVec3,
+) -> Option<f64> {
+    let normal = position.cross(velocity).normalize_or_zero();
+    let angular_rate = |position: DVec3, velocity: DVec3| {
+        position.cross(velocity).dot(normal) / position.length_squared()
+    };
+
+    // angle the target leads by now, and the lead it needs at the first burn
+    let lead = position
+        .cross(target_position)
+        .dot(normal)
+        .atan2(position.dot(target_position));
+    let target_rate = angular_rate(target_position, target_velocity);
+    let needed = transfer.sweep - target_rate * transfer.time_of_flight;
+
+    let relative_rate = target_rate - angular_rate(position, velocity);
+    if relative_rate == 0. {
+        return None;
+    }
+    let wait = if relative_rate > 0. {
+        (needed - lead).rem_euclid(2. * PI) / relative_rate
+    } else {
+        (lead - needed).rem_euclid(2. * PI) / -relative_rate
+    };
+    Some(wait)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DQuat;
+
+    use super::*;
+
+    const EARTH_MU: f64 = 3.986004418e14;
+
+    /// Radii of Vallado's examples 6-1 and 6-2, from 191 km up to geostationary and the moon,
+    /// with 503 873 km of altitude for the bi-elliptic swing out.
+    const LOW: f64 = 6_569_481.11;
+    const GEOSTATIONARY: f64 = 42_159_485.57;
+    const LUNAR: f64 = 382_688_137.;
+    const SWING_OUT: f64 = 510_251_137.;
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn hohmann_matches_vallado() {
+        let transfer = hohmann(EARTH_MU, LOW, GEOSTATIONARY);
+
+        assert_near(transfer.burns[0].1, 2_457.04, 0.1);
+        assert_near(transfer.burns[1].1, 1_478.19, 0.1);
+        assert_near(transfer.delta_v(), 3_935.22, 0.1);
+        assert_near(transfer.time_of_flight / 3600., 5.2567, 1e-3);
+        assert_eq!(transfer.burns[1].0, transfer.time_of_flight);
+
+        // coming back down costs the same, in braking burns
+        let down = hohmann(EARTH_MU, GEOSTATIONARY, LOW);
+        assert_near(down.delta_v(), transfer.delta_v(), 1e-6);
+        assert!(down.burns.iter().all(|(_, delta_v)| *delta_v < 0.));
+    }
+
+    #[test]
+    fn bi_elliptic_matches_vallado() {
+        let transfer = bi_elliptic(EARTH_MU, LOW, LUNAR, SWING_OUT).unwrap();
+
+        assert_near(transfer.delta_v(), 3_904.06, 0.1);
+        assert_near(transfer.time_of_flight / 3600., 593.92, 0.01);
+        // the last burn brakes, dropping the far side from the swing out to the moon
+        assert!(transfer.burns[2].1 < 0.);
+        // beating the 3966 m/s but 119 h Hohmann transfer
+        let direct = hohmann(EARTH_MU, LOW, LUNAR);
+        assert_near(direct.delta_v(), 3_966.19, 0.1);
+        assert!(transfer.delta_v() < direct.delta_v());
+    }
+
+    #[test]
+    fn bi_elliptic_only_wins_past_a_ratio_of_11_94() {
+        // swinging out as far as makes no difference, the limit the crossover is found for
+        let cost = |ratio: f64| {
+            let to = LOW * ratio;
+            let bi_elliptic = bi_elliptic(EARTH_MU, LOW, to, LOW * 1e6).unwrap();
+            bi_elliptic.delta_v() - hohmann(EARTH_MU, LOW, to).delta_v()
+        };
+        assert!(cost(11.8) > 0.);
+        assert!(cost(12.1) < 0.);
+    }
+
+    #[test]
+    fn bi_elliptic_needs_to_swing_out_past_both_orbits() {
+        assert!(bi_elliptic(EARTH_MU, LOW, GEOSTATIONARY, GEOSTATIONARY * 0.9).is_none());
+        assert!(bi_elliptic(EARTH_MU, GEOSTATIONARY, LOW, GEOSTATIONARY * 0.9).is_none());
+        assert!(bi_elliptic(EARTH_MU, LOW, GEOSTATIONARY, LOW * 0.5).is_none());
+        assert!(bi_elliptic(EARTH_MU, LOW, GEOSTATIONARY, GEOSTATIONARY).is_some());
+    }
+
+    #[test]
+    fn waits_until_the_target_is_there_on_arrival() {
+        let circular = |radius: f64, angle: f64| {
+            let turn = DQuat::from_rotation_y(-angle);
+            let speed = (EARTH_MU / radius).sqrt();
+            (turn * DVec3::X * radius, turn * DVec3::Z * speed)
+        };
+        let transfer = hohmann(EARTH_MU, LOW, GEOSTATIONARY);
+        let rate = |radius: f64| (EARTH_MU / radius.powi(3)).sqrt();
+        let (position, velocity) = circular(LOW, 0.);
+
+        for lead in [0.3, 2., 4.] {
+            let (target_position, target_velocity) = circular(GEOSTATIONARY, lead);
+            let wait = phasing_wait(
+                &transfer,
+                position,
+                velocity,
+                target_position,
+                target_velocity,
+            )
+            .unwrap();
+
+            assert!(wait >= 0. && wait < 2. * PI / (rate(LOW) - rate(GEOSTATIONARY)));
+            // the craft arrives half a turn on from where it burned
+            let arrival = rate(LOW) * wait + PI;
+            let target = lead + rate(GEOSTATIONARY) * (wait + transfer.time_of_flight);
+            let miss = (target - arrival + PI).rem_euclid(2. * PI) - PI;
+            assert_near(miss, 0., 1e-9);
+        }
+    }
+}
@@ -28,7 +28,7 @@ pub struct UIPlugin;
 
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(FrameTimeDiagnosticsPlugin::default());
+        app.add_plugin(FrameTimeDiagnosticsPlugin);
         app.add_startup_system(setup_ui);
         app.add_system(update_fps);
         app.add_system(update_positions_of_simulated_components);
@@ -110,7 +110,7 @@ fn update_positions_of_simulated_components(
     let mut entities: HashMap<String, &PhysicalProperties> = HashMap::new();
 
     for (p_props, simulated) in gravity_query.iter() {
-        entities.insert(simulated.0.clone(), &p_props);
+        entities.insert(simulated.0.clone(), p_props);
     }
 
     for mut text in ptext_query.iter_mut() {
@@ -151,7 +151,7 @@ fn update_positions_of_simulated_components(
                         // Construct a `Vec` of `TextSection`s
                         sections: vec![
                             TextSection {
-                                value: format!("{}", simulated),
+                                value: simulated.to_string(),
                                 style: TextStyle {
                                     font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
                                     font_size: 24.0,
@@ -207,7 +207,7 @@ fn update_positions_of_simulated_components(
                         // Construct a `Vec` of `TextSection`s
                         sections: vec![
                             TextSection {
-                                value: format!("{}", simulated),
+                                value: simulated.to_string(),
                                 style: TextStyle {
                                     font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
                                     font_size: 24.0,
@@ -0,0 +1,166 @@
+use std::ops::Mul;
+
+use bevy::{
+    math::{DMat3, DVec3},
+    prelude::*,
+};
+
+use crate::simulation::{BodyState, GRAVITATIONAL_CONSTANT};
+
+/// 6×6 matrix over position and velocity, kept as four 3×3 blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StateMatrix {
+    /// Position rows, position columns.
+    pub rr: DMat3,
+    /// Position rows, velocity columns.
+    pub rv: DMat3,
+    /// Velocity rows, position columns.
+    pub vr: DMat3,
+    /// Velocity rows, velocity columns.
+    pub vv: DMat3,
+}
+
+impl StateMatrix {
+    pub const IDENTITY: StateMatrix = StateMatrix {
+        rr: DMat3::IDENTITY,
+        rv: DMat3::ZERO,
+        vr: DMat3::ZERO,
+        vv: DMat3::IDENTITY,
+    };
+
+    /// Entry at `row` and `column`, positions first.
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        let block = match (row < 3, column < 3) {
+            (true, true) => &self.rr,
+            (true, false) => &self.rv,
+            (false, true) => &self.vr,
+            (false, false) => &self.vv,
+        };
+        block.col(column % 3)[row % 3]
+    }
+
+    pub fn transpose(&self) -> StateMatrix {
+        StateMatrix {
+            rr: self.rr.transpose(),
+            rv: self.vr.transpose(),
+            vr: self.rv.transpose(),
+            vv: self.vv.transpose(),
+        }
+    }
+}
+
+impl Mul for StateMatrix {
+    type Output = StateMatrix;
+
+    fn mul(self, other: StateMatrix) -> StateMatrix {
+        StateMatrix {
+            rr: self.rr * other.rr + self.rv * other.vr,
+            rv: self.rr * other.rv + self.rv * other.vv,
+            vr: self.vr * other.rr + self.vv * other.vr,
+            vv: self.vr * other.rv + self.vv * other.vv,
+        }
+    }
+}
+
+/// State transition matrix of a body, how its position and velocity now move with changes to
+/// where it was at `epoch`. Integrated alongside the body by the simulation step.
+#[derive(Component, Clone, Debug)]
+pub struct StateTransition {
+    pub matrix: StateMatrix,
+    /// Simulated seconds since the start of the simulation the matrix starts from.
+    pub epoch: f64,
+}
+
+impl Default for StateTransition {
+    fn default() -> Self {
+        StateTransition {
+            matrix: StateMatrix::IDENTITY,
+            epoch: 0.,
+        }
+    }
+}
+
+/// How gravity on `bodies[index]` changes as it moves, ∂a/∂r, with everything else held where it
+/// is. Point masses are softened the same way the accelerations are, and the gravity fields of
+/// other bodies are included.
+pub fn gravity_gradient(bodies: &[BodyState], index: usize) -> DMat3 {
+    let body = &bodies[index];
+    let position = body.translation.to_dvec3();
+    let mut gradient = DMat3::ZERO;
+
+    for (other_index, other) in bodies.iter().enumerate() {
+        if other_index == index {
+            continue;
+        }
+        let offset = other.translation.to_dvec3() - position;
+        let softening = (body.softening.powi(2) + other.softening.powi(2)) / 2.;
+        let distance_squared = offset.length_squared() + softening;
+        if distance_squared == 0. {
+            continue;
+        }
+        let mu = (GRAVITATIONAL_CONSTANT * other.mass.clone()).to_f64();
+        let cubed = distance_squared * distance_squared.sqrt();
+        // 3 x xᵀ / d⁵ - I / d³
+        let outer = DMat3::from_cols(offset * offset.x, offset * offset.y, offset * offset.z);
+        gradient += (outer * (3. / distance_squared) - DMat3::IDENTITY) * (mu / cubed);
+
+        if let Some(field) = &other.gravity_field {
+            gradient += field.gradient(mu, -offset, other.orientation);
+        }
+    }
+    gradient
+}
+
+/// Carries the transition matrices of `bodies` through a drift of `timestep` seconds.
+pub fn drift_transitions(bodies: &mut [BodyState], timestep: f64) {
+    for body in bodies.iter_mut().filter(|body| !body.fixed) {
+        if let Some(matrix) = &mut body.transition {
+            matrix.rr += matrix.vr * timestep;
+            matrix.rv += matrix.vv * timestep;
+        }
+    }
+}
+
+/// Carries the transition matrices of `bodies` through a velocity kick of `timestep` seconds,
+/// at the positions the kick's accelerations were worked out at.
+pub fn kick_transitions(bodies: &mut [BodyState], timestep: f64) {
+    let gradients: Vec<Option<DMat3>> = (0..bodies.len())
+        .map(|index| {
+            let body = &bodies[index];
+            (body.transition.is_some() && !body.fixed)
+                .then(|| gravity_gradient(bodies, index) * timestep)
+        })
+        .collect();
+
+    for (body, gradient) in bodies.iter_mut().zip(gradients) {
+        if let (Some(matrix), Some(gradient)) = (&mut body.transition, gradient) {
+            matrix.vr += gradient * matrix.rr;
+            matrix.vv += gradient * matrix.rv;
+        }
+    }
+}
+
+/// How far each block can stretch a change in the starting state, as position per position,
+/// position per velocity, velocity per position and velocity per velocity.
+pub fn sensitivity(matrix: &StateMatrix) -> (f64, f64, f64, f64) {
+    (
+        spectral_norm(matrix.rr),
+        spectral_norm(matrix.rv),
+        spectral_norm(matrix.vr),
+        spectral_norm(matrix.vv),
+    )
+}
+
+/// Largest factor `matrix` stretches any vector by, from power iteration on MᵀM.
+fn spectral_norm(matrix: DMat3) -> f64 {
+    let square = matrix.transpose() * matrix;
+    let mut vector = DVec3::ONE.normalize();
+    for _ in 0..50 {
+        let next = (square * vector).normalize_or_zero();
+        if next == DVec3::ZERO {
+            return 0.;
+        }
+        vector = next;
+    }
+    (matrix * vector).length()
+}
@@ -0,0 +1,322 @@
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{
+    camera::{self, Focusable, Focused, PanOrbitCamera},
+    simulation::{self, HPVec3, PhysicalProperties, Simulated},
+    ui::RenderInUI,
+};
+
+pub const LABEL: &str = "RENDER_TRANSFORMS";
+
+const ORIGIN_LABEL: &str = "RENDER_ORIGIN";
+
+/// Bodies drawn larger than this many pixels don't need an icon to be found.
+const ICON_HIDE_PIXEL_RADIUS: f32 = 12.;
+
+/// Size of the clickable icon drawn over small bodies, in pixels.
+const ICON_SIZE: f32 = 8.;
+
+/// Point in simulation space that sits at the render origin.
+///
+/// Simulated positions are absolute and far outside what an `f32` can hold precisely, so
+/// render transforms are computed relative to this point (the focused body) in high precision
+/// before being handed to the engine.
+pub struct RenderOrigin {
+    pub translation: HPVec3,
+}
+
+impl Default for RenderOrigin {
+    fn default() -> Self {
+        RenderOrigin {
+            translation: HPVec3::zero(),
+        }
+    }
+}
+
+impl RenderOrigin {
+    /// Position of a simulation space point relative to the render origin, in meters.
+    pub fn relative(&self, translation: &HPVec3) -> DVec3 {
+        HPVec3::sub(translation, &self.translation).to_dvec3()
+    }
+}
+
+/// How distances from the render origin are mapped into render space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// One render unit per meter.
+    Linear,
+    /// Distances beyond `RenderScale::linear_distance` are compressed logarithmically, and
+    /// bodies shrink with them, so the whole system fits on screen at once.
+    Logarithmic,
+}
+
+/// Settings for mapping simulation space onto render space.
+pub struct RenderScale {
+    pub mode: ScaleMode,
+    /// Distance in meters up to which the logarithmic mode stays close to linear.
+    pub linear_distance: f64,
+    /// Smallest radius, in pixels, a simulated body is drawn with.
+    pub min_pixel_radius: f32,
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        RenderScale {
+            mode: ScaleMode::Linear,
+            linear_distance: 1.0e8,
+            min_pixel_radius: 2.,
+        }
+    }
+}
+
+impl RenderScale {
+    /// Maps a position relative to the render origin into render space.
+    pub fn to_render(&self, relative: DVec3) -> Vec3 {
+        match self.mode {
+            ScaleMode::Linear => relative.as_vec3(),
+            ScaleMode::Logarithmic => {
+                let distance = relative.length();
+                if distance == 0. {
+                    return Vec3::ZERO;
+                }
+                let compressed = self.linear_distance * (distance / self.linear_distance).ln_1p();
+                (relative * (compressed / distance)).as_vec3()
+            }
+        }
+    }
+
+    /// How much a body at `distance` meters from the render origin is shrunk by the mapping.
+    pub fn size_factor(&self, distance: f64) -> f64 {
+        match self.mode {
+            ScaleMode::Linear => 1.,
+            ScaleMode::Logarithmic => 1. / (1. + distance / self.linear_distance),
+        }
+    }
+}
+
+/// Scale of a simulated entity's transform before any render scaling is applied.
+#[derive(Component)]
+pub struct BaseScale(pub Vec3);
+
+/// Clickable screen space marker for a focusable body.
+#[derive(Component)]
+struct BodyIcon(Entity);
+
+/// ECS Plugin used to map the simulation state onto render transforms.
+pub struct ViewPlugin;
+
+impl Plugin for ViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderOrigin>();
+        app.init_resource::<RenderScale>();
+        app.add_system(
+            update_render_origin
+                .label(ORIGIN_LABEL)
+                .after(simulation::LABEL),
+        );
+        app.add_system(update_render_transforms.label(LABEL).after(ORIGIN_LABEL));
+        app.add_system(insert_base_scale);
+        app.add_system(toggle_scale_mode);
+        app.add_system(spawn_body_icons);
+        app.add_system(update_body_icons.after(camera::LABEL));
+        app.add_system(focus_clicked_icon);
+    }
+}
+
+/// Moves the render origin onto the focused body.
+fn update_render_origin(
+    mut origin: ResMut<RenderOrigin>,
+    focused_query: Query<&PhysicalProperties, With<Focused>>,
+) {
+    if let Ok(physical_properties) = focused_query.get_single() {
+        origin.translation = physical_properties.translation.clone();
+    }
+}
+
+/// Remembers the authored scale of simulated entities so render scaling can be reapplied each frame.
+fn insert_base_scale(
+    mut commands: Commands,
+    sim_query: Query<(Entity, &Transform), (With<Simulated>, Without<BaseScale>)>,
+) {
+    for (entity, transform) in sim_query.iter() {
+        commands.entity(entity).insert(BaseScale(transform.scale));
+    }
+}
+
+/// Writes the simulated positions, relative to the render origin, into the engine transforms and
+/// scales bodies so they never shrink below the minimum on-screen size.
+fn update_render_transforms(
+    windows: Res<Windows>,
+    origin: Res<RenderOrigin>,
+    scale: Res<RenderScale>,
+    camera_query: Query<(&Transform, &PerspectiveProjection), With<PanOrbitCamera>>,
+    mut sim_query: Query<
+        (&PhysicalProperties, &mut Transform, Option<&BaseScale>),
+        (With<Simulated>, Without<PanOrbitCamera>),
+    >,
+) {
+    let camera = camera_query.get_single().ok();
+    let window_height = windows.get_primary().map(|window| window.height());
+
+    for (physical_properties, mut transform, base_scale) in sim_query.iter_mut() {
+        // engine floats are not precise enough for the calculations
+        // but precise enough to render visuals once made relative to the focus :D
+        let relative = origin.relative(&physical_properties.translation);
+        transform.translation = scale.to_render(relative);
+
+        let base_scale = match base_scale {
+            Some(base_scale) => base_scale.0,
+            None => continue,
+        };
+
+        let size_factor = scale.size_factor(relative.length()) as f32;
+        let radius = physical_properties.estimated_radius.to_f32() * size_factor;
+        let mut boost = 1.;
+        if let (Some((camera_transform, projection)), Some(window_height)) = (camera, window_height)
+        {
+            let pixel_radius = pixel_radius(
+                radius,
+                camera_transform.translation.distance(transform.translation),
+                projection.fov,
+                window_height,
+            );
+            if pixel_radius > 0. && pixel_radius < scale.min_pixel_radius {
+                boost = scale.min_pixel_radius / pixel_radius;
+            }
+        }
+        transform.scale = base_scale * size_factor * boost;
+    }
+}
+
+/// Approximate on-screen radius in pixels of a sphere with `radius` at `distance` from the camera.
+fn pixel_radius(radius: f32, distance: f32, fov: f32, window_height: f32) -> f32 {
+    if distance <= radius {
+        return f32::MAX;
+    }
+    radius / (distance * (fov / 2.).tan()) * window_height / 2.
+}
+
+/// Switch between linear and logarithmic render scale with L.
+fn toggle_scale_mode(input_keyboard: Res<Input<KeyCode>>, mut scale: ResMut<RenderScale>) {
+    if input_keyboard.just_pressed(KeyCode::L) {
+        scale.mode = match scale.mode {
+            ScaleMode::Linear => ScaleMode::Logarithmic,
+            ScaleMode::Logarithmic => ScaleMode::Linear,
+        };
+    }
+}
+
+fn spawn_body_icons(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    focusable_query: Query<(Entity, Option<&RenderInUI>), Added<Focusable>>,
+) {
+    for (entity, render_in_ui) in focusable_query.iter() {
+        let name = render_in_ui.map(|r| r.0.clone()).unwrap_or_default();
+        commands
+            .spawn_bundle(ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Px(ICON_SIZE), Val::Px(ICON_SIZE)),
+                    display: Display::None,
+                    ..default()
+                },
+                color: Color::rgba(1., 1., 1., 0.8).into(),
+                ..default()
+            })
+            .insert(BodyIcon(entity))
+            .with_children(|icon| {
+                icon.spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(ICON_SIZE * 1.5),
+                            bottom: Val::Px(0.),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    text: Text::with_section(
+                        name,
+                        TextStyle {
+                            font: asset_server.load("fonts/UbuntuMono-Regular.ttf"),
+                            font_size: 16.0,
+                            color: Color::WHITE,
+                        },
+                        default(),
+                    ),
+                    ..default()
+                });
+            });
+    }
+}
+
+/// Keeps icons over their bodies, hiding them when the body is behind the camera or already large
+/// enough on screen to be found without one.
+fn update_body_icons(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    origin: Res<RenderOrigin>,
+    scale: Res<RenderScale>,
+    camera_query: Query<(&Camera, &Transform, &PerspectiveProjection), With<PanOrbitCamera>>,
+    body_query: Query<(&Transform, &PhysicalProperties), Without<PanOrbitCamera>>,
+    mut icon_query: Query<(Entity, &BodyIcon, &mut Style)>,
+) {
+    let (camera, camera_transform, projection) = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window_height = match windows.get_primary() {
+        Some(window) => window.height(),
+        None => return,
+    };
+    let camera_global = GlobalTransform::from(*camera_transform);
+
+    for (icon_entity, icon, mut style) in icon_query.iter_mut() {
+        let (transform, physical_properties) = match body_query.get(icon.0) {
+            Ok(body) => body,
+            Err(_) => {
+                commands.entity(icon_entity).despawn_recursive();
+                continue;
+            }
+        };
+
+        // bodies are scaled to at least the minimum size, so compare the unboosted radius
+        let relative = origin.relative(&physical_properties.translation);
+        let radius = physical_properties.estimated_radius.to_f32()
+            * scale.size_factor(relative.length()) as f32;
+        let distance = camera_transform.translation.distance(transform.translation);
+        let visible_radius = pixel_radius(radius, distance, projection.fov, window_height);
+
+        match camera.world_to_screen(&windows, &images, &camera_global, transform.translation) {
+            Some(screen) if visible_radius < ICON_HIDE_PIXEL_RADIUS => {
+                style.display = Display::Flex;
+                style.position = Rect {
+                    left: Val::Px(screen.x - ICON_SIZE / 2.),
+                    bottom: Val::Px(screen.y - ICON_SIZE / 2.),
+                    ..default()
+                };
+            }
+            _ => style.display = Display::None,
+        }
+    }
+}
+
+/// Focus the body whose icon was clicked.
+fn focus_clicked_icon(
+    mut commands: Commands,
+    icon_query: Query<(&Interaction, &BodyIcon), Changed<Interaction>>,
+    focusable_query: Query<Entity, With<Focusable>>,
+) {
+    for (interaction, icon) in icon_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        for focusable in focusable_query.iter() {
+            commands.entity(focusable).remove::<Focused>();
+        }
+        commands.entity(icon.0).insert(Focused);
+    }
+}